@@ -1,45 +1,39 @@
 use actix_web::web::{Data, Path};
 use actix_web::{web, App, HttpResponse, HttpServer};
-use poker_board::command::adapter::{CombinedEventStore, DefaultStore, NoRetry};
+use poker_board::command::adapter::jsonl::JsonlEventStore;
+use poker_board::command::adapter::{in_memory_snapshots, ArcMutexStore, SeededStore};
 use poker_board::command::event::{
-    BoardModifiedEvent, CombinedEvent, VoteTypeEvent, VoteValidation,
+    BoardModifiedEvent, CombinedEvent, ParticipantKind, VoteTypeEvent, VoteValidation,
 };
-use poker_board::command::BoardCommand;
+use poker_board::command::{self, BoardCommand};
+use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
-use std::sync::mpsc::Sender;
+use std::time::Duration;
+use util::composite::CompositeStore;
+use util::envelope::{Envelope, Metadata};
 use util::query::Query;
-use util::use_case::UseCase;
+use util::transaction::retry::{ExponentialBackoff, RetryOnConflict};
+use util::use_case::{CommandIdReused, UseCase};
 
-use crate::query_param::NameRequest;
 use poker_board::query;
-use util::store::LoadEntity;
-use websockets::sidecar::start_usecase_sidecar;
+use sqlite_store::Database;
+use util::store::{LoadEntity, SaveEntity};
+use websockets::presence::Presence;
+use websockets::sidecar::{start_usecase_sidecar, UseCaseSender};
 use websockets::store::StoreInterface;
-use websockets::websocket::UseCaseMessage;
+use websockets::websocket::JoinRequest;
 use websockets::{store, websocket};
 
-mod query_param {
-    use serde::Deserialize;
-
-    #[derive(Debug, Deserialize)]
-    pub struct NameRequest {
-        name: String,
-    }
-
-    impl ToString for NameRequest {
-        fn to_string(&self) -> String {
-            self.name.clone()
-        }
-    }
-}
+mod sse;
 
 async fn board_ws(
     r: actix_web::HttpRequest,
     stream: web::Payload,
     path: Path<String>,
     update_store: Data<StoreInterface>,
-    use_case_tx: Data<Sender<UseCaseMessage>>,
-    name: web::Query<NameRequest>,
+    use_case_tx: Data<UseCaseSender>,
+    presence: Data<Presence>,
+    join: web::Query<JoinRequest>,
 ) -> actix_web::Result<HttpResponse> {
     let board_id = path.into_inner();
     websocket::start(
@@ -48,7 +42,8 @@ async fn board_ws(
         board_id,
         update_store.into_inner(),
         use_case_tx.into_inner(),
-        name.to_string(),
+        presence.get_ref().clone(),
+        join.into_inner(),
     )
     .log()
 }
@@ -67,13 +62,60 @@ where
     }
 }
 
+const CORRELATION_ID_HEADER: &str = "X-Correlation-Id";
+const PARTICIPANT_TOKEN_HEADER: &str = "X-Participant-Token";
+
+#[derive(Deserialize)]
+struct CommandSubmission {
+    #[serde(default)]
+    command_id: Option<String>,
+    #[serde(flatten)]
+    command: BoardCommand,
+}
+
+/// The participant whose token the request carries, if any.
+fn request_participant(request: &actix_web::HttpRequest) -> Option<String> {
+    request
+        .headers()
+        .get(PARTICIPANT_TOKEN_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(websocket::participant_id)
+}
+
+fn correlation_id(request: &actix_web::HttpRequest) -> String {
+    request
+        .headers()
+        .get(CORRELATION_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// Attributes the command to the participant whose token the request carries, which also
+/// scopes the deduplication of its `command_id` to them.
+fn command_metadata(request: &actix_web::HttpRequest, command: &CommandSubmission) -> Metadata {
+    let correlation_id = correlation_id(request);
+    let command_id = command
+        .command_id
+        .clone()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let metadata = Metadata::new()
+        .with_command_id(command_id)
+        .with_correlation_id(correlation_id);
+    match request_participant(request) {
+        Some(participant_id) => metadata.with_actor_id(participant_id),
+        None => metadata,
+    }
+}
+
 #[actix_web::post("/board/{id}")]
 async fn modify_board(
+    request: actix_web::HttpRequest,
     data: Data<UseCase<CombinedEvent>>,
     body: String,
     path: Path<String>,
 ) -> HttpResponse {
-    let command = match serde_json::from_str::<BoardCommand>(&body) {
+    let submission = match serde_json::from_str::<CommandSubmission>(&body) {
         Ok(body) => body,
         Err(err) => {
             log::error!("Error parsing body: {}", err);
@@ -81,8 +123,18 @@ async fn modify_board(
         }
     };
 
+    // The body names the participant a command acts as, so it is only trusted when the
+    // request carries that participant's token.
+    if let Some(actor_id) = submission.command.actor_id() {
+        if request_participant(&request).as_deref() != Some(actor_id) {
+            log::warn!("Rejecting command for {} without their token", actor_id);
+            return HttpResponse::Forbidden().finish();
+        }
+    }
+
     let key = path.into_inner();
-    let response = data.execute(&key, &command).await;
+    let metadata = command_metadata(&request, &submission);
+    let response = data.execute(&key, &submission.command, &metadata).await;
     response
         .log()
         .map(|events| {
@@ -90,6 +142,63 @@ async fn modify_board(
                 .map(|body| HttpResponse::Ok().body(body))
                 .unwrap_or_else(|_| HttpResponse::InternalServerError().finish())
         })
+        .unwrap_or_else(|error| match error.downcast_ref::<CommandIdReused>() {
+            Some(_) => HttpResponse::Conflict().finish(),
+            None => HttpResponse::InternalServerError().finish(),
+        })
+}
+
+#[derive(Deserialize)]
+struct JoinSubmission {
+    name: String,
+    #[serde(default)]
+    kind: ParticipantKind,
+    #[serde(default)]
+    token: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Joined {
+    participant_id: String,
+    token: String,
+}
+
+/// Joins the board as a participant, or rejoins as the one a known token stands for, for
+/// clients without a websocket. The token that comes back authorizes their commands.
+#[actix_web::post("/board/{id}/join")]
+async fn join_board(
+    request: actix_web::HttpRequest,
+    data: Data<UseCase<CombinedEvent>>,
+    body: String,
+    path: Path<String>,
+) -> HttpResponse {
+    let join = match serde_json::from_str::<JoinSubmission>(&body) {
+        Ok(join) => join,
+        Err(err) => {
+            log::error!("Error parsing body: {}", err);
+            return HttpResponse::BadRequest().finish();
+        }
+    };
+
+    let key = path.into_inner();
+    let token = join
+        .token
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let participant_id = websocket::participant_id(&token);
+    let rejoin = command::rejoin_participant(join.name, participant_id.clone(), join.kind);
+    let metadata = Metadata::new()
+        .with_command_id(uuid::Uuid::new_v4().to_string())
+        .with_correlation_id(correlation_id(&request))
+        .with_actor_id(participant_id.clone());
+    let response = data.execute(&key, &rejoin, &metadata).await;
+    response
+        .log()
+        .map(|_| {
+            HttpResponse::Ok().json(Joined {
+                participant_id,
+                token,
+            })
+        })
         .unwrap_or_else(|_| HttpResponse::InternalServerError().finish())
 }
 
@@ -106,50 +215,146 @@ async fn get_board(query: Data<Query<BoardModifiedEvent>>, path: Path<String>) -
         .unwrap_or_else(|_| HttpResponse::NotFound().finish())
 }
 
+#[actix_web::get("/board/{id}/rounds")]
+async fn get_rounds(query: Data<Query<BoardModifiedEvent>>, path: Path<String>) -> HttpResponse {
+    let key = path.into_inner();
+    log::debug!("Getting rounds for board with key: {}", key);
+    let response = query
+        .query::<query::rounds::presentation::RoundHistoryPresentation>(&key)
+        .await;
+    response
+        .log()
+        .map(|rounds| HttpResponse::Ok().json(rounds))
+        .unwrap_or_else(|_| HttpResponse::NotFound().finish())
+}
+
 #[actix_web::get("/board/{id}/events")]
 async fn get_events(event_store: Data<StoreInterface>, path: Path<String>) -> HttpResponse {
     let key = path.into_inner();
     log::debug!("Getting board with key: {}", key);
-    let response = event_store.load(&key).await;
+    let response: Result<Option<Vec<Envelope<BoardModifiedEvent>>>, _> =
+        event_store.load(&key).await;
     response
         .log()
-        .map(|board| HttpResponse::Ok().json(board))
+        .map(|events| HttpResponse::Ok().json(events))
         .unwrap_or_else(|_| HttpResponse::NotFound().finish())
 }
 
+#[actix_web::get("/board/{id}/stream")]
+async fn stream_board(
+    request: actix_web::HttpRequest,
+    update_store: Data<StoreInterface>,
+    path: Path<String>,
+) -> HttpResponse {
+    let key = path.into_inner();
+    let since = request
+        .headers()
+        .get(sse::LAST_EVENT_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    log::debug!("Streaming board with key: {} since {}", key, since);
+    match sse::board_events(update_store.into_inner(), key, since).await {
+        Ok(events) => HttpResponse::Ok()
+            .content_type("text/event-stream")
+            .insert_header(("Cache-Control", "no-cache"))
+            .streaming(events),
+        Err(err) => {
+            log::error!("Error streaming board: {}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+fn combined_stores<V>(
+    store: StoreInterface,
+    vote_type_store: V,
+) -> (CompositeStore<CombinedEvent>, CompositeStore<CombinedEvent>)
+where
+    V: LoadEntity<Vec<VoteTypeEvent>, Key = String, Error = websockets::Error>
+        + SaveEntity<Vec<VoteTypeEvent>, Key = String, Error = websockets::Error>
+        + Clone
+        + 'static,
+{
+    let combined = || {
+        CompositeStore::new()
+            .with_stream(vote_type_store.clone(), vote_type_store.clone())
+            .with_stream(store.clone(), store.clone())
+    };
+    (combined(), combined())
+}
+
+const SNAPSHOT_INTERVAL: usize = 100;
+const DEDUPE_WINDOW: usize = 256;
+const DEDUPE_BOARDS: usize = 1024;
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     std::env::set_var("RUST_LOG", "debug");
     env_logger::init();
 
-    let vote_type_store = DefaultStore::<VoteTypeEvent>::new(vec![VoteTypeEvent::VoteTypeAdded {
-        vote_validation: VoteValidation::AnyNumber,
-        vote_type_id: "1".to_string(),
-    }]);
+    let seed = vec![
+        VoteTypeEvent::VoteTypeAdded {
+            vote_validation: VoteValidation::AnyNumber,
+            vote_type_id: "1".to_string(),
+        },
+        VoteTypeEvent::DefaultVoteTypeSet {
+            vote_type_id: "1".to_string(),
+        },
+    ];
 
-    let store = store::create_store();
-    let combined_write_store =
-        CombinedEventStore::new(store.clone(), vote_type_store.clone(), store.clone());
-    let combined_read_store =
-        CombinedEventStore::new(store.clone(), vote_type_store.clone(), store.clone());
+    let sqlite_path = std::env::var("SQLITE_PATH").ok();
+    let event_log_dir = std::env::var("EVENT_LOG_DIR").ok();
+    let (store, (combined_write_store, combined_read_store)) = match (sqlite_path, event_log_dir) {
+        (Some(path), _) => {
+            log::info!("Persisting events to {}", path);
+            let database = Database::open(&path).map_err(std::io::Error::other)?;
+            let store = store::create_persistent_store(database.store("board"));
+            let vote_type_store = SeededStore::new(seed, database.store("vote_types"));
+            (store.clone(), combined_stores(store, vote_type_store))
+        }
+        (None, Some(dir)) => {
+            log::info!("Persisting event logs to {}", dir);
+            let dir = std::path::Path::new(&dir);
+            let board_store = JsonlEventStore::<BoardModifiedEvent>::open(dir.join("board"))
+                .map_err(std::io::Error::other)?;
+            let vote_type_store = JsonlEventStore::<VoteTypeEvent>::open(dir.join("vote_types"))
+                .map_err(std::io::Error::other)?;
+            let store = store::create_persistent_store(board_store);
+            let vote_type_store = SeededStore::new(seed, vote_type_store);
+            (store.clone(), combined_stores(store, vote_type_store))
+        }
+        (None, None) => {
+            let store = store::create_store();
+            let vote_type_store = SeededStore::new(seed, ArcMutexStore::<VoteTypeEvent>::new());
+            (store.clone(), combined_stores(store, vote_type_store))
+        }
+    };
 
+    let snapshots = in_memory_snapshots(SNAPSHOT_INTERVAL);
     let transaction = util::transaction::Transaction::<Vec<CombinedEvent>>::new(
-        NoRetry::new(),
+        RetryOnConflict::new(ExponentialBackoff::new(
+            Duration::from_millis(10),
+            Duration::from_millis(200),
+            Duration::from_secs(2),
+        )),
         combined_write_store,
         combined_read_store,
-    );
+    )
+    .with_snapshots(snapshots.clone());
 
-    let use_case = UseCase::new(transaction);
-    let query = Query::<BoardModifiedEvent>::new(store.clone());
+    let use_case = UseCase::new(transaction).with_dedupe_window(DEDUPE_WINDOW, DEDUPE_BOARDS);
+    let query = Query::<BoardModifiedEvent>::new(store.clone()).with_snapshots(snapshots);
 
     let use_case_data = Data::new(use_case);
     let query_data = Data::new(query);
 
     let tx = start_usecase_sidecar(use_case_data.clone().into_inner());
+    let presence = Data::new(Presence::new());
 
     let host = std::env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
     let port = std::env::var("PORT")
-        .unwrap_or(String::default())
+        .unwrap_or_default()
         .parse::<u16>()
         .unwrap_or(8080);
 
@@ -161,10 +366,14 @@ async fn main() -> std::io::Result<()> {
             .app_data(Data::new(store.clone()))
             .app_data(query_data.clone())
             .app_data(Data::new(tx.clone()))
+            .app_data(presence.clone())
             .app_data(use_case_data.clone())
             .service(modify_board)
+            .service(join_board)
             .service(get_board)
+            .service(get_rounds)
             .service(get_events)
+            .service(stream_board)
     })
     .bind((host, port))?
     .run()
@@ -172,3 +381,135 @@ async fn main() -> std::io::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test;
+    use util::transaction::retry::{Instruction, RetryContext};
+    use util::transaction::Transaction;
+
+    async fn board_with(participants: &[&str]) -> UseCase<CombinedEvent> {
+        let vote_type_store = SeededStore::new(Vec::new(), ArcMutexStore::<VoteTypeEvent>::new());
+        let (write_store, read_store) = combined_stores(store::create_store(), vote_type_store);
+        let use_case = UseCase::new(Transaction::new(
+            |_context: &RetryContext| Instruction::Abort,
+            write_store,
+            read_store,
+        ))
+        .with_dedupe_window(DEDUPE_WINDOW, DEDUPE_BOARDS);
+        for token in participants {
+            let add = command::add_participant(
+                token.to_string(),
+                websocket::participant_id(token),
+                ParticipantKind::Voter,
+            );
+            use_case
+                .execute("board", &add, &Metadata::default())
+                .await
+                .unwrap();
+        }
+        use_case
+    }
+
+    #[actix_web::test]
+    async fn it_should_only_act_as_a_participant_for_a_request_with_their_token() {
+        let use_case = board_with(&["alice", "bob"]).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(use_case))
+                .service(modify_board),
+        )
+        .await;
+        let alice = websocket::participant_id("alice");
+        let removal = serde_json::json!({
+            "RemoveParticipant": { "participant_id": alice, "issued_by": alice }
+        });
+        let post = |token: Option<&str>| {
+            let request = test::TestRequest::post()
+                .uri("/board/board")
+                .set_payload(removal.to_string());
+            match token {
+                Some(token) => request.insert_header((PARTICIPANT_TOKEN_HEADER, token)),
+                None => request,
+            }
+            .to_request()
+        };
+
+        let response = test::call_service(&app, post(None)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = test::call_service(&app, post(Some("bob"))).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = test::call_service(&app, post(Some("alice"))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn it_should_scope_command_ids_to_the_participant_and_the_command() {
+        let use_case = board_with(&["alice", "bob"]).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(use_case))
+                .service(modify_board),
+        )
+        .await;
+        let rename = |token: &str, name: &str| {
+            let rename = serde_json::json!({
+                "command_id": "rename",
+                "RenameParticipant": {
+                    "participant_id": websocket::participant_id(token),
+                    "participant_name": name
+                }
+            });
+            test::TestRequest::post()
+                .uri("/board/board")
+                .insert_header((PARTICIPANT_TOKEN_HEADER, token))
+                .set_payload(rename.to_string())
+                .to_request()
+        };
+
+        let response = test::call_service(&app, rename("alice", "Alice")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = test::call_service(&app, rename("alice", "Alice")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = test::call_service(&app, rename("alice", "Mallory")).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = test::call_service(&app, rename("bob", "Bob")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = test::read_body(response).await;
+        assert!(String::from_utf8_lossy(&body).contains("Bob"));
+    }
+
+    #[actix_web::test]
+    async fn it_should_join_over_http_with_a_token_that_acts_as_the_facilitator() {
+        let use_case = board_with(&[]).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(use_case))
+                .service(join_board)
+                .service(modify_board),
+        )
+        .await;
+        let join = test::TestRequest::post()
+            .uri("/board/board/join")
+            .set_payload(serde_json::json!({ "name": "Alice" }).to_string())
+            .to_request();
+
+        let joined: serde_json::Value = test::call_and_read_body_json(&app, join).await;
+        let token = joined["token"].as_str().unwrap();
+        let alice = websocket::participant_id(token);
+        assert_eq!(joined["participant_id"], alice);
+
+        let reveal = serde_json::json!({ "RevealVotes": { "issued_by": alice } });
+        let reveal = test::TestRequest::post()
+            .uri("/board/board")
+            .insert_header((PARTICIPANT_TOKEN_HEADER, token))
+            .set_payload(reveal.to_string())
+            .to_request();
+        let body = test::call_and_read_body(&app, reveal).await;
+        assert!(String::from_utf8(body.to_vec())
+            .unwrap()
+            .contains("VotesRevealed"));
+    }
+}
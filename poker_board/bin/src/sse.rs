@@ -0,0 +1,131 @@
+use actix_web::rt::time::timeout;
+use actix_web::web::Bytes;
+use futures::{stream, Stream, StreamExt};
+use std::sync::Arc;
+use std::time::Duration;
+use websockets::store::{AheadOfStream, LoadUpdate, PresentationUpdate};
+use websockets::websocket::Updates;
+
+pub const LAST_EVENT_ID_HEADER: &str = "Last-Event-ID";
+
+/// Sent while a board is quiet, so that proxies do not close the connection as idle.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Frames an update as a server-sent event whose id is the board version, so a client
+/// reconnecting with `Last-Event-ID` resumes after it.
+fn encode(update: &PresentationUpdate) -> Result<Bytes, serde_json::Error> {
+    let data = serde_json::to_string(&update.presentation)?;
+    Ok(Bytes::from(format!(
+        "id: {}\ndata: {}\n\n",
+        update.version, data
+    )))
+}
+
+/// Streams the presentations of the board at `key` newer than version `since`. A `since`
+/// ahead of the board streams the board from scratch instead. A client that is not behind,
+/// including one opening the stream of a board without events, is sent the current
+/// presentation first, so every stream starts with the state of the board. The stream ends
+/// when the board stops broadcasting, and the client reconnects from the last id it saw.
+pub async fn board_events(
+    updates: Arc<Updates>,
+    key: String,
+    since: usize,
+) -> Result<impl Stream<Item = Result<Bytes, serde_json::Error>>, websockets::Error> {
+    let subscription = match updates.load_update(&key, since).await {
+        Err(e) if e.downcast_ref::<AheadOfStream>().is_some() => {
+            log::info!("Streaming board {} from scratch: {}", key, e);
+            updates.load_update(&key, 0).await?
+        }
+        subscription => subscription?,
+    };
+
+    let latest = subscription.latest();
+    let opening = (latest.version <= since).then(|| encode(&latest));
+
+    Ok(stream::iter(opening).chain(stream::unfold(
+        subscription,
+        |mut subscription| async move {
+            let event = match timeout(KEEP_ALIVE_INTERVAL, subscription.next()).await {
+                Ok(Some(update)) => encode(&update),
+                Ok(None) => return None,
+                Err(_) => Ok(Bytes::from_static(b": keep-alive\n\n")),
+            };
+            Some((event, subscription))
+        },
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use poker_board::command::event::BoardModifiedEvent;
+    use poker_board::query::presentation::BoardPresentation;
+    use util::envelope::Metadata;
+    use util::store::SaveEntity;
+    use websockets::store;
+
+    #[test]
+    fn it_should_frame_updates_with_their_version_as_the_event_id() {
+        let update = PresentationUpdate {
+            version: 42,
+            presentation: BoardPresentation::default(),
+            previous: 41,
+            patch: Vec::new(),
+        };
+        let data = serde_json::to_string(&update.presentation).unwrap();
+
+        assert_eq!(
+            encode(&update).unwrap(),
+            Bytes::from(format!("id: 42\ndata: {}\n\n", data))
+        );
+    }
+
+    async fn first_event_id(updates: Arc<Updates>, since: usize) -> String {
+        let events = board_events(updates, "board".to_string(), since)
+            .await
+            .unwrap();
+        let event = Box::pin(events).next().await.unwrap().unwrap();
+        let event = String::from_utf8(event.to_vec()).unwrap();
+        event.lines().next().unwrap().to_string()
+    }
+
+    #[actix::test]
+    async fn it_should_resume_after_the_last_event_id_or_from_scratch_when_it_is_ahead() {
+        let store = store::create_store();
+        let added = |id: &str| BoardModifiedEvent::ParticipantAdded {
+            participant_id: id.to_string(),
+            participant_name: id.to_string(),
+        };
+        store
+            .save_versioned(
+                &"board".to_string(),
+                vec![added("a"), added("b")],
+                0,
+                &Metadata::default(),
+            )
+            .await
+            .unwrap();
+        let updates: Arc<Updates> = Arc::new(store);
+
+        assert_eq!(first_event_id(updates.clone(), 1).await, "id: 2");
+        assert_eq!(first_event_id(updates, 7).await, "id: 2");
+    }
+
+    #[actix::test]
+    async fn it_should_open_every_stream_with_the_current_presentation() {
+        let store = store::create_store();
+        let updates: Arc<Updates> = Arc::new(store.clone());
+        assert_eq!(first_event_id(updates.clone(), 0).await, "id: 0");
+
+        let added = BoardModifiedEvent::ParticipantAdded {
+            participant_id: "a".to_string(),
+            participant_name: "a".to_string(),
+        };
+        store
+            .save_versioned(&"board".to_string(), vec![added], 0, &Metadata::default())
+            .await
+            .unwrap();
+        assert_eq!(first_event_id(updates, 1).await, "id: 1");
+    }
+}
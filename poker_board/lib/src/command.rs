@@ -1,10 +1,24 @@
 use crate::command::domain::add_participant::AddParticipantCommand;
+use crate::command::domain::add_story::AddStoryCommand;
+use crate::command::domain::add_vote_type::AddVoteTypeCommand;
+use crate::command::domain::assign_role::AssignRoleCommand;
 use crate::command::domain::clear_votes::ClearVotes;
+use crate::command::domain::record_estimate::RecordEstimateCommand;
 use crate::command::domain::remove_participant::RemoveParticipantCommand;
+use crate::command::domain::remove_vote_type::RemoveVoteTypeCommand;
+use crate::command::domain::rename_participant::RenameParticipantCommand;
+use crate::command::domain::reorder_stories::ReorderStoriesCommand;
+use crate::command::domain::reveal_votes::RevealVotes;
+use crate::command::domain::set_default_vote_type::SetDefaultVoteTypeCommand;
+use crate::command::domain::set_reveal_policy::SetRevealPolicyCommand;
+use crate::command::domain::start_estimating::StartEstimatingCommand;
 use crate::command::domain::vote::ParticipantVote;
 pub use crate::command::domain::Board;
 use crate::command::domain::CombinedDomain;
-use crate::command::event::{BoardModifiedEvent, Vote, VoteValue};
+use crate::command::event::{
+    BoardModifiedEvent, CombinedEvent, CommandRejectedReason, ParticipantKind, RevealPolicy, Role,
+    Vote, VoteValidation, VoteValue,
+};
 use serde::Deserialize;
 use util::command::Command;
 
@@ -12,39 +26,331 @@ pub mod adapter;
 mod domain;
 pub mod event;
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Hash, Deserialize)]
 pub enum BoardCommand {
     AddParticipant(AddParticipantCommand),
     ClearVotes(ClearVotes),
+    RevealVotes(RevealVotes),
+    SetRevealPolicy(SetRevealPolicyCommand),
     RemoveParticipant(RemoveParticipantCommand),
+    RenameParticipant(RenameParticipantCommand),
     Vote(ParticipantVote),
+    AddVoteType(AddVoteTypeCommand),
+    RemoveVoteType(RemoveVoteTypeCommand),
+    SetDefaultVoteType(SetDefaultVoteTypeCommand),
+    AddStory(AddStoryCommand),
+    ReorderStories(ReorderStoriesCommand),
+    StartEstimating(StartEstimatingCommand),
+    RecordEstimate(RecordEstimateCommand),
+    AssignRole(AssignRoleCommand),
     Noop,
 }
 
+impl BoardCommand {
+    /// The participant the command acts on behalf of, if it names one.
+    pub fn actor_id(&self) -> Option<&str> {
+        match self {
+            BoardCommand::AddParticipant(command) => command.participant_id(),
+            BoardCommand::RenameParticipant(command) => Some(command.participant_id()),
+            BoardCommand::Vote(command) => Some(command.participant_id.as_str()),
+            BoardCommand::RemoveParticipant(command) => Some(command.issued_by()),
+            _ => self.requires_facilitator(),
+        }
+    }
+
+    fn requires_facilitator(&self) -> Option<&str> {
+        match self {
+            BoardCommand::ClearVotes(command) => Some(command.issued_by()),
+            BoardCommand::RevealVotes(command) => Some(command.issued_by()),
+            BoardCommand::SetRevealPolicy(command) => Some(command.issued_by()),
+            BoardCommand::RemoveParticipant(command) if command.is_kick() => {
+                Some(command.issued_by())
+            }
+            BoardCommand::AddVoteType(command) => Some(command.issued_by()),
+            BoardCommand::RemoveVoteType(command) => Some(command.issued_by()),
+            BoardCommand::SetDefaultVoteType(command) => Some(command.issued_by()),
+            BoardCommand::AddStory(command) => Some(command.issued_by()),
+            BoardCommand::ReorderStories(command) => Some(command.issued_by()),
+            BoardCommand::StartEstimating(command) => Some(command.issued_by()),
+            BoardCommand::RecordEstimate(command) => Some(command.issued_by()),
+            BoardCommand::AssignRole(command) => Some(command.issued_by()),
+            _ => None,
+        }
+    }
+
+    fn authorize(&self, entity: &CombinedDomain) -> Option<BoardModifiedEvent> {
+        let (participant_id, allowed) = match self {
+            BoardCommand::Vote(command) => (
+                command.participant_id.as_str(),
+                entity.board().role_of(&command.participant_id) != Some(Role::Observer),
+            ),
+            _ => {
+                let issued_by = self.requires_facilitator()?;
+                (
+                    issued_by,
+                    entity.board().role_of(issued_by) == Some(Role::Facilitator),
+                )
+            }
+        };
+
+        match allowed {
+            true => None,
+            false => Some(BoardModifiedEvent::CommandRejected {
+                participant_id: participant_id.to_string(),
+                reason: CommandRejectedReason::NotAuthorized,
+            }),
+        }
+    }
+}
+
 impl Command for BoardCommand {
     type Entity = CombinedDomain;
-    type Event = BoardModifiedEvent;
+    type Event = CombinedEvent;
 
     fn apply(&self, entity: &Self::Entity) -> Vec<Self::Event> {
+        if let Some(rejected) = self.authorize(entity) {
+            return combine(vec![rejected]);
+        }
+
         match self {
-            BoardCommand::AddParticipant(command) => command.apply(entity.board()),
-            BoardCommand::ClearVotes(command) => command.apply(entity.board()),
-            BoardCommand::RemoveParticipant(command) => command.apply(entity.board()),
-            BoardCommand::Vote(command) => command.apply(entity),
+            BoardCommand::AddParticipant(command) => combine(command.apply(entity.board())),
+            BoardCommand::ClearVotes(command) => combine(command.apply(entity.board())),
+            BoardCommand::RevealVotes(command) => combine(command.apply(entity.board())),
+            BoardCommand::SetRevealPolicy(command) => combine(command.apply(entity.board())),
+            BoardCommand::RemoveParticipant(command) => combine(command.apply(entity.board())),
+            BoardCommand::RenameParticipant(command) => combine(command.apply(entity.board())),
+            BoardCommand::Vote(command) => combine(command.apply(entity)),
+            BoardCommand::AddVoteType(command) => combine(command.apply(entity.vote_type_list())),
+            BoardCommand::RemoveVoteType(command) => {
+                combine(command.apply(entity.vote_type_list()))
+            }
+            BoardCommand::SetDefaultVoteType(command) => {
+                combine(command.apply(entity.vote_type_list()))
+            }
+            BoardCommand::AddStory(command) => combine(command.apply(entity.backlog())),
+            BoardCommand::ReorderStories(command) => combine(command.apply(entity.backlog())),
+            BoardCommand::StartEstimating(command) => combine(command.apply(entity)),
+            BoardCommand::RecordEstimate(command) => combine(command.apply(entity.backlog())),
+            BoardCommand::AssignRole(command) => combine(command.apply(entity.board())),
             BoardCommand::Noop => vec![],
         }
     }
 }
 
-pub fn vote(vote: u8, vote_type: String, participant_id: String) -> BoardCommand {
-    let vote = Vote::new(vote_type, VoteValue::Number(vote));
-    BoardCommand::Vote(ParticipantVote::new(participant_id, vote))
+fn combine<E>(events: Vec<E>) -> Vec<CombinedEvent>
+where
+    E: Into<CombinedEvent>,
+{
+    events.into_iter().map(Into::into).collect()
+}
+
+pub fn vote(value: VoteValue, vote_type: Option<String>, participant_id: String) -> BoardCommand {
+    let command = match vote_type {
+        Some(vote_type) => ParticipantVote::new(participant_id, Vote::new(vote_type, value)),
+        None => ParticipantVote::with_default_type(participant_id, value),
+    };
+    BoardCommand::Vote(command)
 }
 
-pub fn add_participant(name: String, id: String) -> BoardCommand {
-    BoardCommand::AddParticipant(AddParticipantCommand::with_id(name, id))
+pub fn add_participant(name: String, id: String, kind: ParticipantKind) -> BoardCommand {
+    BoardCommand::AddParticipant(AddParticipantCommand::with_kind(name, id, kind))
+}
+
+pub fn rejoin_participant(name: String, id: String, kind: ParticipantKind) -> BoardCommand {
+    BoardCommand::AddParticipant(AddParticipantCommand::rejoin(name, id, kind))
+}
+
+pub fn rename_participant(id: String, name: String) -> BoardCommand {
+    BoardCommand::RenameParticipant(RenameParticipantCommand::new(id, name))
 }
 
 pub fn remove_participant(id: String) -> BoardCommand {
-    BoardCommand::RemoveParticipant(RemoveParticipantCommand::new(id))
+    BoardCommand::RemoveParticipant(RemoveParticipantCommand::new(id.clone(), id))
+}
+
+pub fn kick_participant(id: String, issued_by: String) -> BoardCommand {
+    BoardCommand::RemoveParticipant(RemoveParticipantCommand::new(id, issued_by))
+}
+
+pub fn assign_role(id: String, role: Role, issued_by: String) -> BoardCommand {
+    BoardCommand::AssignRole(AssignRoleCommand::new(id, role, issued_by))
+}
+
+pub fn clear_votes(issued_by: String) -> BoardCommand {
+    BoardCommand::ClearVotes(ClearVotes::new(issued_by))
+}
+
+pub fn reveal_votes(issued_by: String) -> BoardCommand {
+    BoardCommand::RevealVotes(RevealVotes::new(issued_by))
+}
+
+pub fn set_reveal_policy(policy: RevealPolicy, issued_by: String) -> BoardCommand {
+    BoardCommand::SetRevealPolicy(SetRevealPolicyCommand::new(policy, issued_by))
+}
+
+pub fn add_vote_type(
+    vote_type_id: String,
+    vote_validation: VoteValidation,
+    issued_by: String,
+) -> BoardCommand {
+    BoardCommand::AddVoteType(AddVoteTypeCommand::new(
+        vote_type_id,
+        vote_validation,
+        issued_by,
+    ))
+}
+
+pub fn remove_vote_type(vote_type_id: String, issued_by: String) -> BoardCommand {
+    BoardCommand::RemoveVoteType(RemoveVoteTypeCommand::new(vote_type_id, issued_by))
+}
+
+pub fn set_default_vote_type(vote_type_id: String, issued_by: String) -> BoardCommand {
+    BoardCommand::SetDefaultVoteType(SetDefaultVoteTypeCommand::new(vote_type_id, issued_by))
+}
+
+pub fn add_story(title: String, story_id: Option<String>, issued_by: String) -> BoardCommand {
+    let command = match story_id {
+        Some(story_id) => AddStoryCommand::with_id(title, story_id, issued_by),
+        None => AddStoryCommand::new(title, issued_by),
+    };
+    BoardCommand::AddStory(command)
+}
+
+pub fn reorder_stories(story_ids: Vec<String>, issued_by: String) -> BoardCommand {
+    BoardCommand::ReorderStories(ReorderStoriesCommand::new(story_ids, issued_by))
+}
+
+pub fn start_estimating(story_id: String, issued_by: String) -> BoardCommand {
+    BoardCommand::StartEstimating(StartEstimatingCommand::new(story_id, issued_by))
+}
+
+pub fn record_estimate(story_id: String, value: VoteValue, issued_by: String) -> BoardCommand {
+    BoardCommand::RecordEstimate(RecordEstimateCommand::new(story_id, value, issued_by))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::entity::EventSourced;
+
+    fn domain_after(commands: &[BoardCommand]) -> CombinedDomain {
+        let events = commands.iter().fold(Vec::new(), |mut events, command| {
+            events.extend(command.apply(&CombinedDomain::source(&events)));
+            events
+        });
+        CombinedDomain::source(&events)
+    }
+
+    fn board_with_facilitator() -> Vec<BoardCommand> {
+        vec![
+            add_participant(
+                "alice".to_string(),
+                "alice".to_string(),
+                ParticipantKind::Voter,
+            ),
+            add_participant("bob".to_string(), "bob".to_string(), ParticipantKind::Voter),
+        ]
+    }
+
+    fn rejected(participant_id: &str) -> Vec<CombinedEvent> {
+        combine(vec![BoardModifiedEvent::CommandRejected {
+            participant_id: participant_id.to_string(),
+            reason: CommandRejectedReason::NotAuthorized,
+        }])
+    }
+
+    #[test]
+    fn it_should_allow_the_facilitator_to_reveal_votes() {
+        let domain = domain_after(&board_with_facilitator());
+        let command = BoardCommand::RevealVotes(RevealVotes::new("alice".to_string()));
+        assert_eq!(
+            command.apply(&domain),
+            combine(vec![BoardModifiedEvent::VotesRevealed])
+        );
+    }
+
+    #[test]
+    fn it_should_reject_privileged_commands_from_voters() {
+        let domain = domain_after(&board_with_facilitator());
+        let command = BoardCommand::RevealVotes(RevealVotes::new("bob".to_string()));
+        assert_eq!(command.apply(&domain), rejected("bob"));
+    }
+
+    #[test]
+    fn it_should_let_participants_remove_themselves() {
+        let domain = domain_after(&board_with_facilitator());
+        let events = remove_participant("bob".to_string()).apply(&domain);
+        assert_eq!(
+            events,
+            combine(vec![BoardModifiedEvent::ParticipantRemoved {
+                participant_id: "bob".to_string(),
+            }])
+        );
+    }
+
+    #[test]
+    fn it_should_reject_kicks_from_voters() {
+        let domain = domain_after(&board_with_facilitator());
+        let command = BoardCommand::RemoveParticipant(RemoveParticipantCommand::new(
+            "alice".to_string(),
+            "bob".to_string(),
+        ));
+        assert_eq!(command.apply(&domain), rejected("bob"));
+    }
+
+    #[test]
+    fn it_should_reject_votes_from_observers() {
+        let mut commands = board_with_facilitator();
+        commands.push(BoardCommand::AssignRole(AssignRoleCommand::new(
+            "bob".to_string(),
+            Role::Observer,
+            "alice".to_string(),
+        )));
+        let domain = domain_after(&commands);
+        assert_eq!(
+            vote(VoteValue::Number(1), None, "bob".to_string()).apply(&domain),
+            rejected("bob")
+        );
+    }
+
+    #[test]
+    fn it_should_let_a_participant_who_left_rejoin_and_vote() {
+        let bob =
+            || rejoin_participant("bob".to_string(), "bob".to_string(), ParticipantKind::Voter);
+        let mut commands = board_with_facilitator();
+        commands.extend([
+            add_vote_type(
+                "numbers".to_string(),
+                VoteValidation::AnyNumber,
+                "alice".to_string(),
+            ),
+            set_default_vote_type("numbers".to_string(), "alice".to_string()),
+            remove_participant("bob".to_string()),
+            bob(),
+        ]);
+        let domain = domain_after(&commands);
+        assert_eq!(bob().apply(&domain), vec![]);
+
+        let events = vote(VoteValue::Number(3), None, "bob".to_string()).apply(&domain);
+        assert_eq!(
+            events,
+            combine(vec![BoardModifiedEvent::ParticipantVoted {
+                participant_id: "bob".to_string(),
+                vote: Vote::new("numbers".to_string(), VoteValue::Number(3)),
+            }])
+        );
+    }
+
+    #[test]
+    fn it_should_name_the_participant_issuing_the_command() {
+        assert_eq!(
+            kick_participant("bob".to_string(), "alice".to_string()).actor_id(),
+            Some("alice")
+        );
+        assert_eq!(
+            vote(VoteValue::Number(1), None, "bob".to_string()).actor_id(),
+            Some("bob")
+        );
+        assert_eq!(BoardCommand::Noop.actor_id(), None);
+    }
 }
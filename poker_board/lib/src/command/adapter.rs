@@ -1,17 +1,24 @@
 use crate::command::adapter::StoreError::CouldNotLockMutex;
 
-use crate::command::event::{BoardModifiedEvent, CombinedEvent, VoteTypeEvent};
+use crate::command::domain::CombinedDomain;
+use crate::command::event::CombinedEvent;
+use crate::query;
+use crate::query::rounds::RoundHistory;
 
 use async_trait::async_trait;
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::Display;
 use std::sync::{Arc, Mutex};
-use util::store::{LoadEntity, SaveEntity};
-use util::transaction::retry::{Instruction, RetryStrategy};
+use util::envelope::{Envelope, Metadata};
+use util::snapshot::{EveryNEvents, Snapshot, Snapshots, Snapshotted, Snapshotter};
+use util::store::{Conflict, LoadEntity, SaveEntity, Tail};
+use util::transaction::retry::{Instruction, RetryContext, RetryStrategy};
+
+pub mod jsonl;
 
 struct Store<T> {
-    store: HashMap<String, Vec<T>>,
+    store: HashMap<String, Vec<Envelope<T>>>,
 }
 
 impl<T> Store<T> {
@@ -21,12 +28,22 @@ impl<T> Store<T> {
         }
     }
 
-    pub fn get(&self, key: &str) -> Option<&Vec<T>> {
+    pub fn get(&self, key: &str) -> Option<&Vec<Envelope<T>>> {
         self.store.get(key)
     }
 
-    fn insert(&mut self, key: &String, value: Vec<T>) {
-        self.store.insert(key.to_string(), value);
+    fn version(&self, key: &str) -> usize {
+        self.store.get(key).map(Vec::len).unwrap_or(0)
+    }
+
+    /// Appends the events of `tail` past the ones already stored.
+    fn append(&mut self, key: &str, tail: &Tail<Vec<T>>, metadata: &Metadata)
+    where
+        T: Clone,
+    {
+        let stream = self.store.entry(key.to_string()).or_default();
+        let appended = tail.after(stream.len()).to_vec();
+        stream.extend(Envelope::wrap(stream.len(), appended, metadata));
     }
 }
 
@@ -69,11 +86,51 @@ where
     type Error = Box<dyn Error + Send + Sync>;
 
     async fn load(&self, key: &Self::Key) -> Result<Option<Vec<T>>, Self::Error> {
+        match self.0.lock() {
+            Ok(guard) => Ok(guard.get(key).map(|stream| Envelope::events(stream))),
+            Err(_) => Err(CouldNotLockMutex.into()),
+        }
+    }
+
+    async fn load_from(
+        &self,
+        key: &Self::Key,
+        from: &[usize],
+    ) -> Result<Option<Tail<Vec<T>>>, Self::Error> {
+        let guard = self.0.lock().map_err(|_| CouldNotLockMutex)?;
+        Ok(guard.get(key).map(|stream| {
+            let from = from.first().copied().unwrap_or(0).min(stream.len());
+            Tail::of_stream(from, Envelope::events(&stream[from..]))
+        }))
+    }
+}
+
+#[async_trait]
+impl<T> LoadEntity<Vec<Envelope<T>>> for ArcMutexStore<T>
+where
+    T: Send + Sync + 'static + Clone,
+{
+    type Key = String;
+    type Error = Box<dyn Error + Send + Sync>;
+
+    async fn load(&self, key: &Self::Key) -> Result<Option<Vec<Envelope<T>>>, Self::Error> {
         match self.0.lock() {
             Ok(guard) => Ok(guard.get(key).cloned()),
             Err(_) => Err(CouldNotLockMutex.into()),
         }
     }
+
+    async fn load_from(
+        &self,
+        key: &Self::Key,
+        from: &[usize],
+    ) -> Result<Option<Tail<Vec<Envelope<T>>>>, Self::Error> {
+        let guard = self.0.lock().map_err(|_| CouldNotLockMutex)?;
+        Ok(guard.get(key).map(|stream| {
+            let from = from.first().copied().unwrap_or(0).min(stream.len());
+            Tail::of_stream(from, stream[from..].to_vec())
+        }))
+    }
 }
 
 #[async_trait]
@@ -87,12 +144,38 @@ where
     async fn save(&self, key: &Self::Key, entity: Vec<T>) -> Result<Vec<T>, Self::Error> {
         match self.0.lock() {
             Ok(mut guard) => {
-                guard.insert(key, entity.clone());
-                Ok(entity)
+                let tail = Tail::whole(entity);
+                guard.append(key, &tail, &Metadata::new().recorded_now());
+                Ok(tail.entity)
             }
             Err(_) => Err(CouldNotLockMutex.into()),
         }
     }
+
+    async fn save_versioned(
+        &self,
+        key: &Self::Key,
+        entity: Vec<T>,
+        expected_version: usize,
+        metadata: &Metadata,
+    ) -> Result<Vec<T>, Self::Error> {
+        self.save_tail(key, Tail::whole(entity), expected_version, metadata)
+            .await
+            .map(|tail| tail.entity)
+    }
+
+    async fn save_tail(
+        &self,
+        key: &Self::Key,
+        tail: Tail<Vec<T>>,
+        expected_version: usize,
+        metadata: &Metadata,
+    ) -> Result<Tail<Vec<T>>, Self::Error> {
+        let mut guard = self.0.lock().map_err(|_| CouldNotLockMutex)?;
+        Conflict::check(expected_version, guard.version(key))?;
+        guard.append(key, &tail, metadata);
+        Ok(tail)
+    }
 }
 
 #[derive(Clone)]
@@ -132,116 +215,183 @@ where
     }
 }
 
-pub struct CombinedEventStore {
-    board_modified_load_store: Box<
-        dyn LoadEntity<Vec<BoardModifiedEvent>, Key = String, Error = Box<dyn Error + Send + Sync>>,
-    >,
-    vote_type_list_load_store:
-        Box<dyn LoadEntity<Vec<VoteTypeEvent>, Key = String, Error = Box<dyn Error + Send + Sync>>>,
-    board_modified_save_store: Box<
-        dyn SaveEntity<Vec<BoardModifiedEvent>, Key = String, Error = Box<dyn Error + Send + Sync>>,
-    >,
+/// Prepends a fixed set of events to every stream loaded from the wrapped store, so
+/// each key starts out from the same seed while anything appended is kept per key.
+#[derive(Clone)]
+pub struct SeededStore<S, T> {
+    seed: Vec<T>,
+    store: S,
 }
 
-impl CombinedEventStore {
-    pub fn new(
-        board_modified_load_store: impl LoadEntity<Vec<BoardModifiedEvent>, Key = String, Error = Box<dyn Error + Send + Sync>>
-            + 'static,
-        vote_type_list_load_store: impl LoadEntity<Vec<VoteTypeEvent>, Key = String, Error = Box<dyn Error + Send + Sync>>
-            + 'static,
-        board_modified_save_store: impl SaveEntity<Vec<BoardModifiedEvent>, Key = String, Error = Box<dyn Error + Send + Sync>>
-            + 'static,
-    ) -> Self {
-        Self {
-            board_modified_load_store: Box::new(board_modified_load_store),
-            vote_type_list_load_store: Box::new(vote_type_list_load_store),
-            board_modified_save_store: Box::new(board_modified_save_store),
-        }
+impl<S, T> SeededStore<S, T> {
+    pub fn new(seed: Vec<T>, store: S) -> Self {
+        Self { seed, store }
     }
 }
 
 #[async_trait]
-impl LoadEntity<Vec<BoardModifiedEvent>> for CombinedEventStore {
+impl<S, T> LoadEntity<Vec<T>> for SeededStore<S, T>
+where
+    S: LoadEntity<Vec<T>, Key = String, Error = Box<dyn Error + Send + Sync>>,
+    T: Send + Sync + 'static + Clone,
+{
     type Key = String;
     type Error = Box<dyn Error + Send + Sync>;
 
-    async fn load(&self, key: &Self::Key) -> Result<Option<Vec<BoardModifiedEvent>>, Self::Error> {
-        self.board_modified_load_store.load(key).await
+    async fn load(&self, key: &Self::Key) -> Result<Option<Vec<T>>, Self::Error> {
+        let mut events = self.seed.clone();
+        events.extend(self.store.load(key).await?.unwrap_or_default());
+        Ok(Some(events))
+    }
+
+    /// Skips into the wrapped store once the whole seed is skipped.
+    async fn load_from(
+        &self,
+        key: &Self::Key,
+        from: &[usize],
+    ) -> Result<Option<Tail<Vec<T>>>, Self::Error> {
+        let from = from.first().copied().unwrap_or(0);
+        let stored = self
+            .store
+            .load_from(key, &[from.saturating_sub(self.seed.len())])
+            .await?
+            .unwrap_or_default();
+        let stored_skipped = stored.skipped();
+        let seed_skipped = match stored_skipped {
+            0 => from.min(self.seed.len()),
+            _ => self.seed.len(),
+        };
+        let mut events = self.seed[seed_skipped..].to_vec();
+        events.extend(stored.entity);
+        Ok(Some(Tail::of_stream(seed_skipped + stored_skipped, events)))
     }
 }
 
 #[async_trait]
-impl LoadEntity<Vec<CombinedEvent>> for CombinedEventStore {
+impl<S, T> SaveEntity<Vec<T>> for SeededStore<S, T>
+where
+    S: SaveEntity<Vec<T>, Key = String, Error = Box<dyn Error + Send + Sync>>,
+    T: Send + Sync + 'static + Clone,
+{
     type Key = String;
     type Error = Box<dyn Error + Send + Sync>;
 
-    async fn load(&self, key: &Self::Key) -> Result<Option<Vec<CombinedEvent>>, Self::Error> {
-        let board_modified_events = self
-            .board_modified_load_store
-            .load_events::<CombinedEvent>(key)
+    async fn save(&self, key: &Self::Key, entity: Vec<T>) -> Result<Vec<T>, Self::Error> {
+        let stored = entity.iter().skip(self.seed.len()).cloned().collect();
+        self.store.save(key, stored).await?;
+        Ok(entity)
+    }
+
+    async fn save_versioned(
+        &self,
+        key: &Self::Key,
+        entity: Vec<T>,
+        expected_version: usize,
+        metadata: &Metadata,
+    ) -> Result<Vec<T>, Self::Error> {
+        let stored = entity.iter().skip(self.seed.len()).cloned().collect();
+        self.store
+            .save_versioned(
+                key,
+                stored,
+                expected_version.saturating_sub(self.seed.len()),
+                metadata,
+            )
             .await?;
+        Ok(entity)
+    }
 
-        let mut vote_type_events = self
-            .vote_type_list_load_store
-            .load_events::<CombinedEvent>(key)
+    async fn save_tail(
+        &self,
+        key: &Self::Key,
+        tail: Tail<Vec<T>>,
+        expected_version: usize,
+        metadata: &Metadata,
+    ) -> Result<Tail<Vec<T>>, Self::Error> {
+        let from = tail.skipped();
+        let seeded = self.seed.len().saturating_sub(from);
+        let stored = tail.entity.iter().skip(seeded).cloned().collect();
+        self.store
+            .save_tail(
+                key,
+                Tail::of_stream(from.saturating_sub(self.seed.len()), stored),
+                expected_version.saturating_sub(self.seed.len()),
+                metadata,
+            )
             .await?;
+        Ok(tail)
+    }
+}
 
-        vote_type_events.extend(board_modified_events);
-        Ok(Some(vote_type_events))
+#[derive(Clone)]
+pub struct ArcMutexSnapshotStore<S>(Arc<Mutex<HashMap<String, Snapshotted<S>>>>);
+
+impl<S> ArcMutexSnapshotStore<S> {
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(HashMap::new())))
+    }
+}
+
+impl<S> Default for ArcMutexSnapshotStore<S> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 #[async_trait]
-impl SaveEntity<Vec<CombinedEvent>> for CombinedEventStore {
+impl<S> LoadEntity<Snapshotted<S>> for ArcMutexSnapshotStore<S>
+where
+    S: Send + Sync + 'static + Clone,
+{
     type Key = String;
     type Error = Box<dyn Error + Send + Sync>;
 
-    async fn save(
-        &self,
-        key: &Self::Key,
-        entity: Vec<CombinedEvent>,
-    ) -> Result<Vec<CombinedEvent>, Self::Error> {
-        let board_modified_events = entity
-            .iter()
-            .filter_map(|event| match event {
-                CombinedEvent::BoardModifiedEvent(event) => Some(event.clone()),
-                _ => None,
-            })
-            .collect();
-
-        self.board_modified_save_store
-            .save(key, board_modified_events)
-            .await?;
-
-        Ok(entity)
+    async fn load(&self, key: &Self::Key) -> Result<Option<Snapshotted<S>>, Self::Error> {
+        match self.0.lock() {
+            Ok(guard) => Ok(guard.get(key).cloned()),
+            Err(_) => Err(CouldNotLockMutex.into()),
+        }
     }
 }
 
 #[async_trait]
-trait LoadEvent<T> {
-    async fn load_events<U>(&self, key: &str) -> Result<Vec<U>, Box<dyn Error + Send + Sync>>
-    where
-        U: From<T>;
+impl<S> SaveEntity<Snapshotted<S>> for ArcMutexSnapshotStore<S>
+where
+    S: Send + Sync + 'static + Clone,
+{
+    type Key = String;
+    type Error = Box<dyn Error + Send + Sync>;
+
+    async fn save(
+        &self,
+        key: &Self::Key,
+        entity: Snapshotted<S>,
+    ) -> Result<Snapshotted<S>, Self::Error> {
+        match self.0.lock() {
+            Ok(mut guard) => {
+                guard.insert(key.clone(), entity.clone());
+                Ok(entity)
+            }
+            Err(_) => Err(CouldNotLockMutex.into()),
+        }
+    }
 }
 
-#[async_trait]
-impl<T> LoadEvent<T>
-    for Box<dyn LoadEntity<Vec<T>, Key = String, Error = Box<dyn Error + Send + Sync>>>
+fn in_memory_snapshotter<S>(interval: usize) -> Snapshotter<S>
+where
+    S: Snapshot + Send + Sync + 'static,
+    S::Event: Send + Sync,
 {
-    async fn load_events<U>(&self, key: &str) -> Result<Vec<U>, Box<dyn Error + Send + Sync>>
-    where
-        U: From<T>,
-    {
-        let result = self
-            .load(&key.to_string())
-            .await?
-            .unwrap_or_default()
-            .into_iter()
-            .map(|event| event.into())
-            .collect();
+    let store = ArcMutexSnapshotStore::new();
+    Snapshotter::new(EveryNEvents::new(interval), store.clone(), store)
+}
 
-        Ok(result)
-    }
+/// Snapshots the command aggregate and every query model in memory every `interval` events.
+pub fn in_memory_snapshots(interval: usize) -> Snapshots {
+    Snapshots::new()
+        .with(in_memory_snapshotter::<CombinedDomain>(interval).with_streams(CombinedEvent::stream))
+        .with(in_memory_snapshotter::<query::Board>(interval))
+        .with(in_memory_snapshotter::<RoundHistory>(interval))
 }
 
 pub struct NoRetry;
@@ -259,11 +409,215 @@ impl Default for NoRetry {
 }
 
 impl RetryStrategy for NoRetry {
-    fn should_retry(
-        &self,
-        _previous_instruction: &Option<Instruction>,
-        _retry_count: &u8,
-    ) -> Instruction {
+    fn should_retry(&self, _context: &RetryContext) -> Instruction {
         Instruction::Abort
     }
 }
+
+#[cfg(test)]
+mod arc_mutex_store_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_should_stamp_appended_events_with_the_save_metadata() {
+        let store = ArcMutexStore::<u8>::new();
+        let key = "board".to_string();
+        let first = Metadata::new().with_actor_id("a").recorded_now();
+        let second = Metadata::new().with_command_id("c").recorded_now();
+
+        store
+            .save_versioned(&key, vec![1], 0, &first)
+            .await
+            .unwrap();
+        store
+            .save_versioned(&key, vec![1, 2, 3], 1, &second)
+            .await
+            .unwrap();
+
+        let envelopes: Option<Vec<Envelope<u8>>> = store.load(&key).await.unwrap();
+        assert_eq!(
+            envelopes,
+            Some(vec![
+                Envelope::new(0, &first, 1),
+                Envelope::new(1, &second, 2),
+                Envelope::new(2, &second, 3)
+            ])
+        );
+        let events: Option<Vec<u8>> = store.load(&key).await.unwrap();
+        assert_eq!(events, Some(vec![1, 2, 3]));
+    }
+}
+
+#[cfg(test)]
+mod seeded_store_tests {
+    use super::*;
+
+    async fn stored(store: &ArcMutexStore<u8>, key: &String) -> Option<Vec<u8>> {
+        store.load(key).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn it_should_prepend_the_seed_and_only_store_what_follows_it() {
+        let inner = ArcMutexStore::<u8>::new();
+        let store = SeededStore::new(vec![1, 2], inner.clone());
+        let key = "board".to_string();
+
+        let loaded = store.load(&key).await.unwrap().unwrap();
+        assert_eq!(loaded, vec![1, 2]);
+
+        store.save(&key, vec![1, 2, 3]).await.unwrap();
+        assert_eq!(stored(&inner, &key).await, Some(vec![3]));
+        assert_eq!(store.load(&key).await.unwrap(), Some(vec![1, 2, 3]));
+    }
+
+    #[tokio::test]
+    async fn it_should_check_the_version_past_the_seed() {
+        let inner = ArcMutexStore::<u8>::new();
+        let store = SeededStore::new(vec![1, 2], inner.clone());
+        let key = "board".to_string();
+        let metadata = Metadata::default();
+
+        store
+            .save_versioned(&key, vec![1, 2, 3], 2, &metadata)
+            .await
+            .unwrap();
+        let error = store
+            .save_versioned(&key, vec![1, 2, 4], 2, &metadata)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<Conflict>(),
+            Some(&Conflict {
+                expected_version: 0,
+                actual_version: 1
+            })
+        );
+        assert_eq!(stored(&inner, &key).await, Some(vec![3]));
+    }
+
+    #[tokio::test]
+    async fn it_should_load_and_save_a_tail_past_the_seed() {
+        let inner = ArcMutexStore::<u8>::new();
+        let store = SeededStore::new(vec![1, 2], inner.clone());
+        let key = "board".to_string();
+        let metadata = Metadata::default();
+        store
+            .save_versioned(&key, vec![1, 2, 3], 2, &metadata)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.load_from(&key, &[1]).await.unwrap(),
+            Some(Tail::of_stream(1, vec![2, 3]))
+        );
+        assert_eq!(
+            store.load_from(&key, &[3]).await.unwrap(),
+            Some(Tail::of_stream(3, Vec::new()))
+        );
+
+        store
+            .save_tail(&key, Tail::of_stream(3, vec![4]), 3, &metadata)
+            .await
+            .unwrap();
+        assert_eq!(stored(&inner, &key).await, Some(vec![3, 4]));
+    }
+}
+
+#[cfg(test)]
+mod combined_event_store_tests {
+    use super::*;
+    use crate::command::event::{BoardModifiedEvent, CombinedEvent, VoteTypeEvent};
+    use util::composite::CompositeStore;
+
+    fn added(id: &str) -> CombinedEvent {
+        BoardModifiedEvent::ParticipantAdded {
+            participant_id: id.to_string(),
+            participant_name: id.to_string(),
+        }
+        .into()
+    }
+
+    #[tokio::test]
+    async fn it_should_reject_a_save_based_on_a_stale_load() {
+        let board_store = ArcMutexStore::<BoardModifiedEvent>::new();
+        let vote_type_store = ArcMutexStore::<VoteTypeEvent>::new();
+        let store = CompositeStore::<CombinedEvent>::new()
+            .with_stream(vote_type_store.clone(), vote_type_store)
+            .with_stream(board_store.clone(), board_store.clone());
+        let key = "board".to_string();
+
+        store
+            .save_versioned(&key, vec![added("a")], 0, &Metadata::default())
+            .await
+            .unwrap();
+        let error = store
+            .save_versioned(&key, vec![added("b")], 0, &Metadata::default())
+            .await
+            .unwrap_err();
+
+        assert!(error.downcast_ref::<Conflict>().is_some());
+        let loaded: Option<Vec<CombinedEvent>> = store.load(&key).await.unwrap();
+        assert_eq!(loaded, Some(vec![added("a")]));
+    }
+}
+
+#[cfg(test)]
+mod snapshot_tests {
+    use super::*;
+    use crate::command::add_participant;
+    use crate::command::event::ParticipantKind;
+    use crate::command::event::{VoteTypeEvent, VoteValidation};
+    use util::command::Command;
+    use util::entity::EventSourced;
+
+    #[tokio::test]
+    async fn it_should_source_the_same_aggregate_when_vote_types_are_added_after_a_snapshot() {
+        let snapshots = in_memory_snapshots(2);
+        let mut vote_types: Vec<CombinedEvent> = Vec::new();
+        let mut board: Vec<CombinedEvent> = Vec::new();
+        // Loaded the way the composite store concatenates them: vote types, then the board.
+        let loaded =
+            |vote_types: &[CombinedEvent], board: &[CombinedEvent]| [vote_types, board].concat();
+
+        for (id, vote_type) in [
+            ("a", None),
+            ("b", Some("fib")),
+            ("c", None),
+            ("d", Some("t")),
+        ] {
+            let events = loaded(&vote_types, &board);
+            let domain: CombinedDomain = snapshots.source("board", &events).await.unwrap();
+            assert_eq!(domain, CombinedDomain::source(&events));
+
+            if let Some(vote_type) = vote_type {
+                vote_types.push(
+                    VoteTypeEvent::VoteTypeAdded {
+                        vote_type_id: vote_type.to_string(),
+                        vote_validation: VoteValidation::AnyNumber,
+                    }
+                    .into(),
+                );
+            }
+            let command = add_participant(id.to_string(), id.to_string(), ParticipantKind::Voter);
+            board.extend(command.apply(&domain));
+        }
+
+        let events = loaded(&vote_types, &board);
+        let sourced: CombinedDomain = snapshots.source("board", &events).await.unwrap();
+        assert_eq!(sourced, CombinedDomain::source(&events));
+    }
+
+    #[tokio::test]
+    async fn it_should_source_the_same_aggregate_as_a_replay() {
+        let snapshots = in_memory_snapshots(2);
+        let mut events: Vec<CombinedEvent> = Vec::new();
+        for id in ["a", "b", "c", "d", "e"] {
+            let domain: CombinedDomain = snapshots.source("board", &events).await.unwrap();
+            let command = add_participant(id.to_string(), id.to_string(), ParticipantKind::Voter);
+            events.extend(command.apply(&domain));
+        }
+
+        let sourced: CombinedDomain = snapshots.source("board", &events).await.unwrap();
+        assert_eq!(sourced, CombinedDomain::source(&events));
+    }
+}
@@ -0,0 +1,448 @@
+use async_trait::async_trait;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Display;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use util::envelope::{decode, encode, Envelope, Metadata};
+use util::schema::Schema;
+use util::store::{Conflict, LoadEntity, SaveEntity, Tail};
+
+#[derive(Debug, Clone, PartialEq)]
+enum JsonlStoreError {
+    CouldNotLockMutex,
+}
+
+impl Error for JsonlStoreError {}
+
+impl Display for JsonlStoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonlStoreError::CouldNotLockMutex => write!(f, "Could not lock mutex"),
+        }
+    }
+}
+
+/// Keeps one append-only JSON Lines log per key under `dir`, one [`Envelope`] per line.
+/// Every log is replayed into memory when the store is opened, and each save appends
+/// only the new events and fsyncs the file before returning. Events are upcast to the
+/// current schema as they are replayed, and bare events written before envelopes existed
+/// are still read.
+#[derive(Clone)]
+pub struct JsonlEventStore<T> {
+    dir: PathBuf,
+    streams: Arc<Mutex<HashMap<String, Vec<Envelope<T>>>>>,
+}
+
+impl<T> JsonlEventStore<T>
+where
+    T: Schema,
+{
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut streams = HashMap::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("jsonl") {
+                continue;
+            }
+            let key = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(decode_key);
+            if let Some(key) = key {
+                streams.insert(key, replay(&path)?);
+            }
+        }
+
+        Ok(Self {
+            dir,
+            streams: Arc::new(Mutex::new(streams)),
+        })
+    }
+}
+
+impl<T> JsonlEventStore<T> {
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.jsonl", encode_key(key)))
+    }
+}
+
+impl<T> JsonlEventStore<T>
+where
+    T: Clone + Schema + Serialize + Send + Sync + 'static,
+{
+    /// Appends on the blocking pool, as writing and fsyncing the log can stall on the disk.
+    async fn append(
+        &self,
+        key: &str,
+        tail: Tail<Vec<T>>,
+        expected_version: Option<usize>,
+        metadata: &Metadata,
+    ) -> Result<Tail<Vec<T>>, Box<dyn Error + Send + Sync>> {
+        let store = self.clone();
+        let key = key.to_string();
+        let metadata = metadata.clone();
+        tokio::task::spawn_blocking(move || {
+            store.append_blocking(&key, tail, expected_version, &metadata)
+        })
+        .await?
+    }
+
+    fn append_blocking(
+        &self,
+        key: &str,
+        tail: Tail<Vec<T>>,
+        expected_version: Option<usize>,
+        metadata: &Metadata,
+    ) -> Result<Tail<Vec<T>>, Box<dyn Error + Send + Sync>> {
+        let mut guard = self
+            .streams
+            .lock()
+            .map_err(|_| JsonlStoreError::CouldNotLockMutex)?;
+        let stored = guard.get(key).map(Vec::len).unwrap_or(0);
+        if let Some(expected_version) = expected_version {
+            Conflict::check(expected_version, stored)?;
+        }
+
+        let appended = Envelope::wrap(stored, tail.after(stored).to_vec(), metadata);
+        let mut lines = String::new();
+        for envelope in &appended {
+            lines.push_str(&encode(envelope)?);
+            lines.push('\n');
+        }
+
+        if !lines.is_empty() {
+            let path = self.path(key);
+            let created = !path.exists();
+            let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+            let committed = file.metadata()?.len();
+            if let Err(err) = write_synced(&mut file, lines.as_bytes()) {
+                // Cut off whatever part of the lines made it to disk, so the next append
+                // does not land on the end of a fragment.
+                file.set_len(committed)?;
+                return Err(err.into());
+            }
+            if created {
+                File::open(&self.dir)?.sync_all()?;
+            }
+        }
+
+        guard.entry(key.to_string()).or_default().extend(appended);
+        Ok(tail)
+    }
+}
+
+fn write_synced(file: &mut File, bytes: &[u8]) -> std::io::Result<()> {
+    file.write_all(bytes)?;
+    file.sync_data()
+}
+
+fn replay<T>(path: &Path) -> Result<Vec<Envelope<T>>, Box<dyn Error + Send + Sync>>
+where
+    T: Schema,
+{
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut events = Vec::new();
+    let mut complete = 0;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line)?;
+        // A line without its newline was cut off mid-write and never committed.
+        if read == 0 || !line.ends_with('\n') {
+            break;
+        }
+        events.push(decode(line.trim_end(), events.len())?);
+        complete += read;
+    }
+
+    let file = OpenOptions::new().write(true).open(path)?;
+    if file.metadata()?.len() > complete as u64 {
+        file.set_len(complete as u64)?;
+        file.sync_all()?;
+    }
+    Ok(events)
+}
+
+fn encode_key(key: &str) -> String {
+    key.bytes()
+        .map(|byte| match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn decode_key(encoded: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut chars = encoded.bytes();
+    while let Some(byte) = chars.next() {
+        match byte {
+            b'%' => {
+                let hex = [chars.next()?, chars.next()?];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            byte => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[async_trait]
+impl<T> LoadEntity<Vec<T>> for JsonlEventStore<T>
+where
+    T: Send + Sync + 'static + Clone,
+{
+    type Key = String;
+    type Error = Box<dyn Error + Send + Sync>;
+
+    async fn load(&self, key: &Self::Key) -> Result<Option<Vec<T>>, Self::Error> {
+        match self.streams.lock() {
+            Ok(guard) => Ok(guard.get(key).map(|stream| Envelope::events(stream))),
+            Err(_) => Err(JsonlStoreError::CouldNotLockMutex.into()),
+        }
+    }
+
+    async fn load_from(
+        &self,
+        key: &Self::Key,
+        from: &[usize],
+    ) -> Result<Option<Tail<Vec<T>>>, Self::Error> {
+        let guard = self
+            .streams
+            .lock()
+            .map_err(|_| JsonlStoreError::CouldNotLockMutex)?;
+        Ok(guard.get(key).map(|stream| {
+            let from = from.first().copied().unwrap_or(0).min(stream.len());
+            Tail::of_stream(from, Envelope::events(&stream[from..]))
+        }))
+    }
+}
+
+#[async_trait]
+impl<T> LoadEntity<Vec<Envelope<T>>> for JsonlEventStore<T>
+where
+    T: Send + Sync + 'static + Clone,
+{
+    type Key = String;
+    type Error = Box<dyn Error + Send + Sync>;
+
+    async fn load(&self, key: &Self::Key) -> Result<Option<Vec<Envelope<T>>>, Self::Error> {
+        match self.streams.lock() {
+            Ok(guard) => Ok(guard.get(key).cloned()),
+            Err(_) => Err(JsonlStoreError::CouldNotLockMutex.into()),
+        }
+    }
+
+    async fn load_from(
+        &self,
+        key: &Self::Key,
+        from: &[usize],
+    ) -> Result<Option<Tail<Vec<Envelope<T>>>>, Self::Error> {
+        let guard = self
+            .streams
+            .lock()
+            .map_err(|_| JsonlStoreError::CouldNotLockMutex)?;
+        Ok(guard.get(key).map(|stream| {
+            let from = from.first().copied().unwrap_or(0).min(stream.len());
+            Tail::of_stream(from, stream[from..].to_vec())
+        }))
+    }
+}
+
+#[async_trait]
+impl<T> SaveEntity<Vec<T>> for JsonlEventStore<T>
+where
+    T: Send + Sync + 'static + Clone + Schema + Serialize,
+{
+    type Key = String;
+    type Error = Box<dyn Error + Send + Sync>;
+
+    async fn save(&self, key: &Self::Key, entity: Vec<T>) -> Result<Vec<T>, Self::Error> {
+        self.append(
+            key,
+            Tail::whole(entity),
+            None,
+            &Metadata::new().recorded_now(),
+        )
+        .await
+        .map(|tail| tail.entity)
+    }
+
+    async fn save_versioned(
+        &self,
+        key: &Self::Key,
+        entity: Vec<T>,
+        expected_version: usize,
+        metadata: &Metadata,
+    ) -> Result<Vec<T>, Self::Error> {
+        self.save_tail(key, Tail::whole(entity), expected_version, metadata)
+            .await
+            .map(|tail| tail.entity)
+    }
+
+    async fn save_tail(
+        &self,
+        key: &Self::Key,
+        tail: Tail<Vec<T>>,
+        expected_version: usize,
+        metadata: &Metadata,
+    ) -> Result<Tail<Vec<T>>, Self::Error> {
+        self.append(key, tail, Some(expected_version), metadata)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod jsonl_store_tests {
+    use super::*;
+    use crate::command::event::BoardModifiedEvent;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("jsonl-store-{}-{}", name, uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    async fn events(
+        store: &JsonlEventStore<BoardModifiedEvent>,
+        key: &String,
+    ) -> Option<Vec<BoardModifiedEvent>> {
+        store.load(key).await.unwrap()
+    }
+
+    fn added(id: &str) -> BoardModifiedEvent {
+        BoardModifiedEvent::ParticipantAdded {
+            participant_id: id.to_string(),
+            participant_name: id.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_replay_saved_events_when_reopened() {
+        let dir = temp_dir("replay");
+        let key = "board/1".to_string();
+        let store = JsonlEventStore::<BoardModifiedEvent>::open(&dir).unwrap();
+        store.save(&key, vec![added("a")]).await.unwrap();
+        store
+            .save(&key, vec![added("a"), added("b")])
+            .await
+            .unwrap();
+
+        let reopened = JsonlEventStore::<BoardModifiedEvent>::open(&dir).unwrap();
+        assert_eq!(
+            events(&reopened, &key).await,
+            Some(vec![added("a"), added("b")])
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_should_drop_a_partially_written_line() {
+        let dir = temp_dir("partial");
+        let key = "1".to_string();
+        let store = JsonlEventStore::<BoardModifiedEvent>::open(&dir).unwrap();
+        store.save(&key, vec![added("a")]).await.unwrap();
+        OpenOptions::new()
+            .append(true)
+            .open(store.path(&key))
+            .unwrap()
+            .write_all(b"{\"ParticipantAdded\":{\"partic")
+            .unwrap();
+
+        let reopened = JsonlEventStore::<BoardModifiedEvent>::open(&dir).unwrap();
+        assert_eq!(events(&reopened, &key).await, Some(vec![added("a")]));
+        reopened
+            .save(&key, vec![added("a"), added("b")])
+            .await
+            .unwrap();
+
+        let reopened = JsonlEventStore::<BoardModifiedEvent>::open(&dir).unwrap();
+        assert_eq!(
+            events(&reopened, &key).await,
+            Some(vec![added("a"), added("b")])
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_should_not_append_when_the_log_moved_past_the_expected_version() {
+        let dir = temp_dir("conflict");
+        let key = "1".to_string();
+        let store = JsonlEventStore::<BoardModifiedEvent>::open(&dir).unwrap();
+        store
+            .save_versioned(&key, vec![added("a")], 0, &Metadata::default())
+            .await
+            .unwrap();
+
+        let error = store
+            .save_versioned(&key, vec![added("b")], 0, &Metadata::default())
+            .await
+            .unwrap_err();
+        assert!(error.downcast_ref::<Conflict>().is_some());
+
+        let reopened = JsonlEventStore::<BoardModifiedEvent>::open(&dir).unwrap();
+        assert_eq!(events(&reopened, &key).await, Some(vec![added("a")]));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_should_envelope_new_events_after_bare_ones() {
+        let dir = temp_dir("legacy");
+        let key = "1".to_string();
+        fs::write(
+            dir.join("1.jsonl"),
+            format!("{}\n", serde_json::to_string(&added("a")).unwrap()),
+        )
+        .unwrap();
+        let metadata = Metadata::new()
+            .with_actor_id("b")
+            .with_correlation_id("request")
+            .recorded_now();
+
+        let store = JsonlEventStore::<BoardModifiedEvent>::open(&dir).unwrap();
+        store
+            .save_versioned(&key, vec![added("a"), added("b")], 1, &metadata)
+            .await
+            .unwrap();
+
+        let reopened = JsonlEventStore::<BoardModifiedEvent>::open(&dir).unwrap();
+        let envelopes: Option<Vec<Envelope<BoardModifiedEvent>>> =
+            reopened.load(&key).await.unwrap();
+        assert_eq!(
+            envelopes,
+            Some(vec![
+                Envelope::new(0, &Metadata::default(), added("a")),
+                Envelope::new(1, &metadata, added("b"))
+            ])
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn it_should_refuse_to_open_events_from_a_newer_schema() {
+        let dir = temp_dir("newer");
+        fs::write(
+            dir.join("1.jsonl"),
+            "{\"schema_version\":99,\"sequence\":0,\"recorded_at\":0,\"event\":{}}\n",
+        )
+        .unwrap();
+
+        assert!(JsonlEventStore::<BoardModifiedEvent>::open(&dir).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn it_should_round_trip_keys_through_file_names() {
+        let key = "../board 1/ü";
+        assert!(!encode_key(key).contains('/'));
+        assert_eq!(decode_key(&encode_key(key)), Some(key.to_string()));
+    }
+}
@@ -1,21 +1,99 @@
 pub mod add_participant;
+pub mod add_story;
+pub mod add_vote_type;
+pub mod assign_role;
 pub mod clear_votes;
+pub mod record_estimate;
 pub mod remove_participant;
+pub mod remove_vote_type;
+pub mod rename_participant;
+pub mod reorder_stories;
+pub mod reveal_votes;
+pub mod set_default_vote_type;
+pub mod set_reveal_policy;
+pub mod start_estimating;
 pub mod vote;
 
-use crate::command::event::{BoardModifiedEvent, CombinedEvent, VoteTypeEvent, VoteValidation};
+use crate::command::event::{
+    reorder_stories, BoardModifiedEvent, CombinedEvent, Role, RoundVote, Vote, VoteStats,
+    VoteTypeEvent, VoteValidation, VoteValue,
+};
 use std::collections::HashMap;
+use util::composite::Composite;
 use util::entity::HandleEvent;
 
 #[derive(Default, Debug, PartialEq, Clone)]
 pub struct Board {
     participants: HashMap<String, Participant>,
+    closed_rounds: usize,
+    joined: usize,
 }
 
 impl Board {
     pub fn new() -> Self {
         Self {
             participants: HashMap::new(),
+            closed_rounds: 0,
+            joined: 0,
+        }
+    }
+
+    pub fn role_of(&self, participant_id: &str) -> Option<Role> {
+        self.participants
+            .get(participant_id)
+            .map(|participant| participant.role)
+    }
+
+    fn has_facilitator(&self) -> bool {
+        self.participants
+            .values()
+            .any(|participant| participant.role == Role::Facilitator)
+    }
+
+    fn longest_present_voter(&self, excluding: &str) -> Option<&String> {
+        self.participants
+            .iter()
+            .filter(|(participant_id, participant)| {
+                *participant_id != excluding && participant.role == Role::Voter
+            })
+            .min_by_key(|(_, participant)| participant.joined)
+            .map(|(participant_id, _)| participant_id)
+    }
+
+    fn current_round(&self) -> usize {
+        self.closed_rounds + 1
+    }
+
+    fn close_round(&self) -> Vec<BoardModifiedEvent> {
+        let mut votes: Vec<RoundVote> = self
+            .participants
+            .iter()
+            .filter_map(|(participant_id, participant)| {
+                participant.vote.as_ref().map(|vote| RoundVote {
+                    participant_id: participant_id.clone(),
+                    participant_name: participant.name.clone(),
+                    vote: vote.clone(),
+                })
+            })
+            .collect();
+        votes.sort_by(|a, b| a.participant_id.cmp(&b.participant_id));
+        let stats = VoteStats::from_votes(votes.iter().map(|round_vote| &round_vote.vote.value));
+
+        vec![
+            BoardModifiedEvent::RoundClosed {
+                round: self.current_round(),
+                votes,
+                stats,
+            },
+            BoardModifiedEvent::RoundStarted {
+                round: self.current_round() + 1,
+            },
+        ]
+    }
+
+    fn clear_votes(&mut self) {
+        for participant in self.participants.values_mut() {
+            participant.vote = None;
         }
     }
 }
@@ -23,11 +101,19 @@ impl Board {
 #[derive(Debug, PartialEq, Clone)]
 pub struct Participant {
     name: String,
+    vote: Option<Vote>,
+    role: Role,
+    joined: usize,
 }
 
 impl Participant {
-    pub fn new(name: String) -> Self {
-        Self { name }
+    pub fn new(name: String, joined: usize) -> Self {
+        Self {
+            name,
+            vote: None,
+            role: Role::default(),
+            joined,
+        }
     }
 }
 
@@ -40,18 +126,116 @@ impl HandleEvent for Board {
                 participant_id,
                 participant_name,
             } => {
-                let participant = Participant::new(participant_name.clone());
+                self.joined += 1;
+                let participant = Participant::new(participant_name.clone(), self.joined);
                 self.participants
                     .insert(participant_id.clone(), participant);
             }
             BoardModifiedEvent::ParticipantRemoved { participant_id } => {
                 self.participants.remove(participant_id);
             }
+            BoardModifiedEvent::ParticipantRoleAssigned {
+                participant_id,
+                role,
+            } => {
+                if let Some(participant) = self.participants.get_mut(participant_id) {
+                    participant.role = *role;
+                    if participant.role == Role::Observer {
+                        participant.vote = None;
+                    }
+                }
+            }
+            BoardModifiedEvent::ParticipantRenamed {
+                participant_id,
+                participant_name,
+            } => {
+                if let Some(participant) = self.participants.get_mut(participant_id) {
+                    participant.name = participant_name.clone();
+                }
+            }
+            BoardModifiedEvent::ParticipantNotRenamed { .. } => {}
+            BoardModifiedEvent::RoleNotAssigned { .. } => {}
+            BoardModifiedEvent::CommandRejected { .. } => {}
             BoardModifiedEvent::ParticipantCouldNotBeRemoved { .. } => {}
-            BoardModifiedEvent::ParticipantVoted { .. } => {}
+            BoardModifiedEvent::ParticipantVoted {
+                participant_id,
+                vote,
+            } => {
+                if let Some(participant) = self.participants.get_mut(participant_id) {
+                    participant.vote = Some(vote.clone());
+                }
+            }
             BoardModifiedEvent::ParticipantCouldNotVote { .. } => {}
-            BoardModifiedEvent::VotesCleared => {}
+            BoardModifiedEvent::VotesCleared => self.clear_votes(),
+            BoardModifiedEvent::RoundClosed { round, .. } => {
+                self.closed_rounds = *round;
+            }
+            BoardModifiedEvent::RoundStarted { .. } => self.clear_votes(),
+            BoardModifiedEvent::VotesRevealed => {}
+            BoardModifiedEvent::RevealPolicyChanged { .. } => {}
             BoardModifiedEvent::ParticipantNotAdded { .. } => {}
+            BoardModifiedEvent::StoryAdded { .. } => {}
+            BoardModifiedEvent::StoryNotAdded { .. } => {}
+            BoardModifiedEvent::StoriesReordered { .. } => {}
+            BoardModifiedEvent::StoriesNotReordered { .. } => {}
+            BoardModifiedEvent::EstimationStarted { .. } => {}
+            BoardModifiedEvent::EstimationNotStarted { .. } => {}
+            BoardModifiedEvent::EstimateRecorded { .. } => {}
+            BoardModifiedEvent::EstimateNotRecorded { .. } => {}
+        }
+    }
+}
+
+#[derive(Default, Debug, PartialEq, Clone)]
+pub struct Backlog {
+    stories: Vec<Story>,
+    current_story: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Story {
+    id: String,
+    title: String,
+    estimate: Option<VoteValue>,
+}
+
+impl Story {
+    pub fn new(id: String, title: String) -> Self {
+        Self {
+            id,
+            title,
+            estimate: None,
+        }
+    }
+}
+
+impl Backlog {
+    fn contains(&self, story_id: &str) -> bool {
+        self.stories.iter().any(|story| story.id == story_id)
+    }
+}
+
+impl HandleEvent for Backlog {
+    type Event = BoardModifiedEvent;
+
+    fn apply(&mut self, event: &Self::Event) {
+        match event {
+            BoardModifiedEvent::StoryAdded { story_id, title } => {
+                self.stories
+                    .push(Story::new(story_id.clone(), title.clone()));
+            }
+            BoardModifiedEvent::StoriesReordered { story_ids } => {
+                reorder_stories(&mut self.stories, story_ids, |story| &story.id);
+            }
+            BoardModifiedEvent::EstimationStarted { story_id } => {
+                self.current_story = Some(story_id.clone());
+            }
+            BoardModifiedEvent::EstimateRecorded { story_id, value } => {
+                if let Some(story) = self.stories.iter_mut().find(|story| story.id == *story_id) {
+                    story.estimate = Some(value.clone());
+                }
+            }
+            _ => {}
         }
     }
 }
@@ -59,6 +243,7 @@ impl HandleEvent for Board {
 #[derive(Default, Debug, PartialEq, Clone)]
 pub struct VoteTypeList {
     pub vote_types: HashMap<String, VoteType>,
+    pub default_vote_type: Option<String>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -71,6 +256,19 @@ impl VoteTypeList {
     pub fn add_vote_type(&mut self, vote_type: VoteType) {
         self.vote_types.insert(vote_type.id.clone(), vote_type);
     }
+
+    pub fn remove_vote_type(&mut self, vote_type_id: &str) {
+        self.vote_types.remove(vote_type_id);
+        if self.default_vote_type.as_deref() == Some(vote_type_id) {
+            self.default_vote_type = None;
+        }
+    }
+
+    pub fn default_vote_type(&self) -> Option<&VoteType> {
+        self.default_vote_type
+            .as_ref()
+            .and_then(|vote_type_id| self.vote_types.get(vote_type_id))
+    }
 }
 
 impl VoteType {
@@ -90,20 +288,37 @@ impl HandleEvent for VoteTypeList {
             } => {
                 self.add_vote_type(VoteType::new(vote_type_id.clone(), vote_validation.clone()));
             }
+            VoteTypeEvent::VoteTypeRemoved { vote_type_id } => {
+                self.remove_vote_type(vote_type_id);
+            }
+            VoteTypeEvent::DefaultVoteTypeSet { vote_type_id } => {
+                self.default_vote_type = Some(vote_type_id.clone());
+            }
+            VoteTypeEvent::VoteTypeNotAdded { .. } => {}
+            VoteTypeEvent::VoteTypeNotRemoved { .. } => {}
+            VoteTypeEvent::DefaultVoteTypeNotSet { .. } => {}
         }
     }
 }
 
 #[derive(Default, Debug, PartialEq, Clone)]
-pub struct CombinedDomain(VoteTypeList, Board);
+pub struct CombinedDomain(Composite<CombinedEvent, (VoteTypeList, Board, Backlog)>);
 
 impl CombinedDomain {
+    pub fn new(vote_type_list: VoteTypeList, board: Board, backlog: Backlog) -> Self {
+        Self(Composite::new((vote_type_list, board, backlog)))
+    }
+
     pub fn vote_type_list(&self) -> &VoteTypeList {
-        &self.0
+        &self.0.parts().0
     }
 
     pub fn board(&self) -> &Board {
-        &self.1
+        &self.0.parts().1
+    }
+
+    pub fn backlog(&self) -> &Backlog {
+        &self.0.parts().2
     }
 }
 
@@ -111,14 +326,7 @@ impl HandleEvent for CombinedDomain {
     type Event = CombinedEvent;
 
     fn apply(&mut self, event: &Self::Event) {
-        match event {
-            CombinedEvent::VoteTypeEvent(vote_type_event) => {
-                self.0.apply(vote_type_event);
-            }
-            CombinedEvent::BoardModifiedEvent(board_modified_event) => {
-                self.1.apply(board_modified_event);
-            }
-        }
+        self.0.apply(event);
     }
 }
 
@@ -187,6 +395,114 @@ mod board_tests {
         assert_eq!(board, expected);
     }
 
+    #[test]
+    pub fn it_should_assign_roles_to_participants() {
+        let board = Board::source(&[
+            BoardModifiedEvent::ParticipantAdded {
+                participant_id: "test".to_string(),
+                participant_name: "test".to_string(),
+            },
+            BoardModifiedEvent::ParticipantRoleAssigned {
+                participant_id: "test".to_string(),
+                role: Role::Facilitator,
+            },
+        ]);
+        assert_eq!(board.role_of("test"), Some(Role::Facilitator));
+        assert!(board.has_facilitator());
+    }
+
+    #[test]
+    pub fn it_should_pick_the_longest_present_voter() {
+        let board = Board::source(&[
+            BoardModifiedEvent::ParticipantAdded {
+                participant_id: "a".to_string(),
+                participant_name: "a".to_string(),
+            },
+            BoardModifiedEvent::ParticipantAdded {
+                participant_id: "b".to_string(),
+                participant_name: "b".to_string(),
+            },
+            BoardModifiedEvent::ParticipantAdded {
+                participant_id: "c".to_string(),
+                participant_name: "c".to_string(),
+            },
+        ]);
+        assert_eq!(board.longest_present_voter("a"), Some(&"b".to_string()));
+    }
+
+    #[test]
+    pub fn it_should_close_the_current_round_with_its_votes_and_start_the_next() {
+        let mut board = Board::source(&[
+            BoardModifiedEvent::ParticipantAdded {
+                participant_id: "a".to_string(),
+                participant_name: "Alice".to_string(),
+            },
+            BoardModifiedEvent::ParticipantAdded {
+                participant_id: "b".to_string(),
+                participant_name: "Bob".to_string(),
+            },
+            BoardModifiedEvent::ParticipantVoted {
+                participant_id: "a".to_string(),
+                vote: Vote::new("1".to_string(), VoteValue::Number(3)),
+            },
+        ]);
+
+        let events = board.close_round();
+        assert_eq!(
+            events,
+            vec![
+                BoardModifiedEvent::RoundClosed {
+                    round: 1,
+                    votes: vec![RoundVote {
+                        participant_id: "a".to_string(),
+                        participant_name: "Alice".to_string(),
+                        vote: Vote::new("1".to_string(), VoteValue::Number(3)),
+                    }],
+                    stats: Some(VoteStats {
+                        average: 3,
+                        max: 3,
+                        min: 3,
+                    }),
+                },
+                BoardModifiedEvent::RoundStarted { round: 2 },
+            ]
+        );
+
+        events.iter().for_each(|event| board.apply(event));
+        assert_eq!(board.current_round(), 2);
+        assert!(board.participants.values().all(|p| p.vote.is_none()));
+    }
+
+    #[test]
+    pub fn it_should_leave_the_vote_of_a_demoted_voter_out_of_the_round() {
+        let board = Board::source(&[
+            BoardModifiedEvent::ParticipantAdded {
+                participant_id: "a".to_string(),
+                participant_name: "Alice".to_string(),
+            },
+            BoardModifiedEvent::ParticipantVoted {
+                participant_id: "a".to_string(),
+                vote: Vote::new("1".to_string(), VoteValue::Number(3)),
+            },
+            BoardModifiedEvent::ParticipantRoleAssigned {
+                participant_id: "a".to_string(),
+                role: Role::Observer,
+            },
+        ]);
+
+        assert_eq!(
+            board.close_round(),
+            vec![
+                BoardModifiedEvent::RoundClosed {
+                    round: 1,
+                    votes: vec![],
+                    stats: None,
+                },
+                BoardModifiedEvent::RoundStarted { round: 2 },
+            ]
+        );
+    }
+
     #[test]
     pub fn it_should_reconstruct_from_event_stream() {
         let events = vec![
@@ -223,6 +539,30 @@ mod vote_list_tests {
         assert_eq!(vote_type_list.vote_types.len(), 1);
     }
 
+    #[test]
+    pub fn it_should_clear_the_default_when_the_default_vote_type_is_removed() {
+        let events = vec![
+            VoteTypeEvent::VoteTypeAdded {
+                vote_type_id: "test".to_string(),
+                vote_validation: VoteValidation::AnyNumber,
+            },
+            VoteTypeEvent::DefaultVoteTypeSet {
+                vote_type_id: "test".to_string(),
+            },
+        ];
+        let mut vote_type_list = VoteTypeList::source(&events);
+        assert_eq!(
+            vote_type_list.default_vote_type().map(|v| v.id.as_str()),
+            Some("test")
+        );
+
+        vote_type_list.apply(&VoteTypeEvent::VoteTypeRemoved {
+            vote_type_id: "test".to_string(),
+        });
+        assert!(vote_type_list.vote_types.is_empty());
+        assert_eq!(vote_type_list.default_vote_type, None);
+    }
+
     #[test]
     pub fn it_should_reconstruct_from_event_stream() {
         let events = vec![
@@ -252,6 +592,66 @@ mod vote_list_tests {
     }
 }
 
+#[cfg(test)]
+mod backlog_tests {
+    use super::*;
+    use util::entity::EventSourced;
+
+    #[test]
+    pub fn it_should_keep_stories_in_the_order_they_were_added() {
+        let backlog = Backlog::source(&[
+            BoardModifiedEvent::StoryAdded {
+                story_id: "a".to_string(),
+                title: "A".to_string(),
+            },
+            BoardModifiedEvent::StoryAdded {
+                story_id: "b".to_string(),
+                title: "B".to_string(),
+            },
+        ]);
+        let ids: Vec<&str> = backlog.stories.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b"]);
+    }
+
+    #[test]
+    pub fn it_should_reorder_stories() {
+        let backlog = Backlog::source(&[
+            BoardModifiedEvent::StoryAdded {
+                story_id: "a".to_string(),
+                title: "A".to_string(),
+            },
+            BoardModifiedEvent::StoryAdded {
+                story_id: "b".to_string(),
+                title: "B".to_string(),
+            },
+            BoardModifiedEvent::StoriesReordered {
+                story_ids: vec!["b".to_string(), "a".to_string()],
+            },
+        ]);
+        let ids: Vec<&str> = backlog.stories.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["b", "a"]);
+    }
+
+    #[test]
+    pub fn it_should_track_the_current_story_and_its_estimate() {
+        let backlog = Backlog::source(&[
+            BoardModifiedEvent::StoryAdded {
+                story_id: "a".to_string(),
+                title: "A".to_string(),
+            },
+            BoardModifiedEvent::EstimationStarted {
+                story_id: "a".to_string(),
+            },
+            BoardModifiedEvent::EstimateRecorded {
+                story_id: "a".to_string(),
+                value: VoteValue::Number(5),
+            },
+        ]);
+        assert_eq!(backlog.current_story, Some("a".to_string()));
+        assert_eq!(backlog.stories[0].estimate, Some(VoteValue::Number(5)));
+    }
+}
+
 #[cfg(test)]
 mod combined_domain_tests {
     use super::*;
@@ -273,7 +673,7 @@ mod combined_domain_tests {
         combined_domain.apply(&combined_event);
         let combined_event = CombinedEvent::BoardModifiedEvent(board_modified_event);
         combined_domain.apply(&combined_event);
-        assert_eq!(combined_domain.0.vote_types.len(), 1);
-        assert_eq!(combined_domain.1.participants.len(), 1);
+        assert_eq!(combined_domain.vote_type_list().vote_types.len(), 1);
+        assert_eq!(combined_domain.board().participants.len(), 1);
     }
 }
@@ -1,14 +1,18 @@
 use super::*;
-use crate::command::event::ParticipantNotAddedReason;
+use crate::command::event::{ParticipantKind, ParticipantNotAddedReason, Role};
 use serde::Deserialize;
 use util::command::Command;
 use util::validate::ValidateCommand;
 use uuid::Uuid;
 
-#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Deserialize, Hash)]
 pub struct AddParticipantCommand {
     participant_name: String,
     participant_id: Option<String>,
+    #[serde(default)]
+    kind: ParticipantKind,
+    #[serde(skip)]
+    rejoin: bool,
 }
 
 impl AddParticipantCommand {
@@ -16,6 +20,8 @@ impl AddParticipantCommand {
         Self {
             participant_name,
             participant_id: None,
+            kind: ParticipantKind::default(),
+            rejoin: false,
         }
     }
 
@@ -23,8 +29,36 @@ impl AddParticipantCommand {
         Self {
             participant_name,
             participant_id: Some(participant_id),
+            kind: ParticipantKind::default(),
+            rejoin: false,
         }
     }
+
+    pub fn with_kind(
+        participant_name: String,
+        participant_id: String,
+        kind: ParticipantKind,
+    ) -> Self {
+        Self {
+            participant_name,
+            participant_id: Some(participant_id),
+            kind,
+            rejoin: false,
+        }
+    }
+
+    /// Adds the participant unless they are still on the board, as when a participant
+    /// reconnects with their token.
+    pub fn rejoin(participant_name: String, participant_id: String, kind: ParticipantKind) -> Self {
+        Self {
+            rejoin: true,
+            ..Self::with_kind(participant_name, participant_id, kind)
+        }
+    }
+
+    pub fn participant_id(&self) -> Option<&str> {
+        self.participant_id.as_deref()
+    }
 }
 
 fn have_unique_id(
@@ -46,22 +80,41 @@ impl Command for AddParticipantCommand {
     type Event = BoardModifiedEvent;
 
     fn apply(&self, entity: &Self::Entity) -> Vec<Self::Event> {
+        if self.rejoin && have_unique_id(entity, self).is_some() {
+            return vec![];
+        }
+
         self.should(have_unique_id)
             .validate_against(entity)
-            .map(|command| BoardModifiedEvent::ParticipantAdded {
-                participant_id: command
+            .map(|command| {
+                let participant_id = command
                     .participant_id
                     .clone()
-                    .unwrap_or(Uuid::new_v4().to_string()),
-                participant_name: command.participant_name.clone(),
+                    .unwrap_or(Uuid::new_v4().to_string());
+                let mut events = vec![BoardModifiedEvent::ParticipantAdded {
+                    participant_id: participant_id.clone(),
+                    participant_name: command.participant_name.clone(),
+                }];
+                let role = match command.kind {
+                    ParticipantKind::Observer => Some(Role::Observer),
+                    ParticipantKind::Voter if !entity.has_facilitator() => Some(Role::Facilitator),
+                    ParticipantKind::Voter => None,
+                };
+                if let Some(role) = role {
+                    events.push(BoardModifiedEvent::ParticipantRoleAssigned {
+                        participant_id,
+                        role,
+                    });
+                }
+                events
             })
-            .unwrap_or_else(
-                |(_command, reasons)| BoardModifiedEvent::ParticipantNotAdded {
+            .unwrap_or_else(|(_command, reasons)| {
+                BoardModifiedEvent::ParticipantNotAdded {
                     participant_id: self.participant_id.clone().unwrap_or("".to_string()),
                     reason: reasons[0].clone(),
-                },
-            )
-            .into()
+                }
+                .into()
+            })
     }
 }
 
@@ -76,9 +129,14 @@ mod tests {
         let command = AddParticipantCommand {
             participant_name: "test".to_string(),
             participant_id: None,
+            kind: ParticipantKind::Voter,
+            rejoin: false,
         };
         let events = command.apply(&board);
-        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            events[0],
+            BoardModifiedEvent::ParticipantAdded { .. }
+        ));
     }
 
     #[test]
@@ -87,9 +145,11 @@ mod tests {
         let command = AddParticipantCommand {
             participant_name: "test".to_string(),
             participant_id: Some("test".to_string()),
+            kind: ParticipantKind::Voter,
+            rejoin: false,
         };
         let events = command.apply(&board);
-        assert_eq!(events.len(), 1);
+        assert_eq!(events.len(), 2);
         assert_eq!(
             events[0],
             BoardModifiedEvent::ParticipantAdded {
@@ -99,6 +159,61 @@ mod tests {
         );
     }
 
+    #[test]
+    pub fn it_should_make_the_first_participant_the_facilitator() {
+        let board = Board::new();
+        let command = AddParticipantCommand::with_id("test".to_string(), "test".to_string());
+        let events = command.apply(&board);
+        assert_eq!(
+            events[1],
+            BoardModifiedEvent::ParticipantRoleAssigned {
+                participant_id: "test".to_string(),
+                role: Role::Facilitator,
+            }
+        );
+    }
+
+    #[test]
+    pub fn it_should_add_later_participants_as_voters() {
+        let board = Board::source(&[
+            BoardModifiedEvent::ParticipantAdded {
+                participant_id: "first".to_string(),
+                participant_name: "first".to_string(),
+            },
+            BoardModifiedEvent::ParticipantRoleAssigned {
+                participant_id: "first".to_string(),
+                role: Role::Facilitator,
+            },
+        ]);
+        let command = AddParticipantCommand::with_id("test".to_string(), "test".to_string());
+        let events = command.apply(&board);
+        assert_eq!(
+            events,
+            vec![BoardModifiedEvent::ParticipantAdded {
+                participant_id: "test".to_string(),
+                participant_name: "test".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    pub fn it_should_add_observers_with_the_observer_role() {
+        let board = Board::new();
+        let command = AddParticipantCommand::with_kind(
+            "test".to_string(),
+            "test".to_string(),
+            ParticipantKind::Observer,
+        );
+        let events = command.apply(&board);
+        assert_eq!(
+            events[1],
+            BoardModifiedEvent::ParticipantRoleAssigned {
+                participant_id: "test".to_string(),
+                role: Role::Observer,
+            }
+        );
+    }
+
     #[test]
     pub fn it_should_not_add_a_participant_with_id_that_already_exists() {
         let events = vec![BoardModifiedEvent::ParticipantAdded {
@@ -109,6 +224,8 @@ mod tests {
         let command = AddParticipantCommand {
             participant_name: "test".to_string(),
             participant_id: Some("test".to_string()),
+            kind: ParticipantKind::Voter,
+            rejoin: false,
         };
         let events = command.apply(&board);
         assert_eq!(events.len(), 1);
@@ -120,4 +237,24 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    pub fn it_should_only_rejoin_a_participant_who_is_not_on_the_board() {
+        let command = AddParticipantCommand::rejoin(
+            "test".to_string(),
+            "test".to_string(),
+            ParticipantKind::Voter,
+        );
+        let events = command.apply(&Board::new());
+        assert_eq!(
+            events[0],
+            BoardModifiedEvent::ParticipantAdded {
+                participant_id: "test".to_string(),
+                participant_name: "test".to_string(),
+            }
+        );
+
+        let board = Board::source(&events);
+        assert_eq!(command.apply(&board), vec![]);
+    }
 }
@@ -0,0 +1,109 @@
+use super::*;
+use crate::command::event::StoryNotChangedReason;
+use serde::Deserialize;
+use util::command::Command;
+use util::validate::ValidateCommand;
+use uuid::Uuid;
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Hash)]
+pub struct AddStoryCommand {
+    title: String,
+    story_id: Option<String>,
+    issued_by: String,
+}
+
+impl AddStoryCommand {
+    pub fn new(title: String, issued_by: String) -> Self {
+        Self {
+            title,
+            story_id: None,
+            issued_by,
+        }
+    }
+
+    pub fn with_id(title: String, story_id: String, issued_by: String) -> Self {
+        Self {
+            title,
+            story_id: Some(story_id),
+            issued_by,
+        }
+    }
+
+    pub fn issued_by(&self) -> &str {
+        &self.issued_by
+    }
+}
+
+fn have_unique_id(entity: &Backlog, command: &AddStoryCommand) -> Option<StoryNotChangedReason> {
+    match &command.story_id {
+        Some(story_id) if entity.contains(story_id) => Some(StoryNotChangedReason::AlreadyExists),
+        _ => None,
+    }
+}
+
+impl Command for AddStoryCommand {
+    type Entity = Backlog;
+    type Event = BoardModifiedEvent;
+
+    fn apply(&self, entity: &Self::Entity) -> Vec<Self::Event> {
+        self.should(have_unique_id)
+            .validate_against(entity)
+            .map(|command| BoardModifiedEvent::StoryAdded {
+                story_id: command
+                    .story_id
+                    .clone()
+                    .unwrap_or(Uuid::new_v4().to_string()),
+                title: command.title.clone(),
+            })
+            .unwrap_or_else(|(command, reasons)| BoardModifiedEvent::StoryNotAdded {
+                story_id: command.story_id.clone().unwrap_or_default(),
+                reason: reasons[0].clone(),
+            })
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::entity::EventSourced;
+
+    #[test]
+    pub fn it_should_add_a_story() {
+        let backlog = Backlog::default();
+        let command = AddStoryCommand::with_id(
+            "Login page".to_string(),
+            "story".to_string(),
+            "facilitator".to_string(),
+        );
+        let events = command.apply(&backlog);
+        assert_eq!(
+            events,
+            vec![BoardModifiedEvent::StoryAdded {
+                story_id: "story".to_string(),
+                title: "Login page".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    pub fn it_should_not_add_a_story_with_an_id_that_already_exists() {
+        let backlog = Backlog::source(&[BoardModifiedEvent::StoryAdded {
+            story_id: "story".to_string(),
+            title: "Login page".to_string(),
+        }]);
+        let command = AddStoryCommand::with_id(
+            "Logout".to_string(),
+            "story".to_string(),
+            "facilitator".to_string(),
+        );
+        let events = command.apply(&backlog);
+        assert_eq!(
+            events,
+            vec![BoardModifiedEvent::StoryNotAdded {
+                story_id: "story".to_string(),
+                reason: StoryNotChangedReason::AlreadyExists,
+            }]
+        );
+    }
+}
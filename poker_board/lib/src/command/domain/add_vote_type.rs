@@ -0,0 +1,132 @@
+use super::*;
+use crate::command::event::VoteTypeNotAddedReason;
+use serde::Deserialize;
+use util::command::Command;
+use util::validate::ValidateCommand;
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Hash)]
+pub struct AddVoteTypeCommand {
+    vote_type_id: String,
+    vote_validation: VoteValidation,
+    issued_by: String,
+}
+
+impl AddVoteTypeCommand {
+    pub fn new(vote_type_id: String, vote_validation: VoteValidation, issued_by: String) -> Self {
+        Self {
+            vote_type_id,
+            vote_validation,
+            issued_by,
+        }
+    }
+
+    pub fn issued_by(&self) -> &str {
+        &self.issued_by
+    }
+}
+
+fn have_unique_id(
+    entity: &VoteTypeList,
+    command: &AddVoteTypeCommand,
+) -> Option<VoteTypeNotAddedReason> {
+    match entity.vote_types.contains_key(&command.vote_type_id) {
+        true => Some(VoteTypeNotAddedReason::AlreadyExists),
+        false => None,
+    }
+}
+
+fn have_a_non_empty_range(
+    _entity: &VoteTypeList,
+    command: &AddVoteTypeCommand,
+) -> Option<VoteTypeNotAddedReason> {
+    match command.vote_validation {
+        VoteValidation::Range { min, max } if min > max => {
+            Some(VoteTypeNotAddedReason::EmptyRange { min, max })
+        }
+        _ => None,
+    }
+}
+
+impl Command for AddVoteTypeCommand {
+    type Entity = VoteTypeList;
+    type Event = VoteTypeEvent;
+
+    fn apply(&self, entity: &Self::Entity) -> Vec<Self::Event> {
+        let event = self
+            .should(have_unique_id)
+            .should(have_a_non_empty_range)
+            .validate_against(entity)
+            .map(|command| VoteTypeEvent::VoteTypeAdded {
+                vote_type_id: command.vote_type_id.clone(),
+                vote_validation: command.vote_validation.clone(),
+            })
+            .unwrap_or_else(|(command, reasons)| VoteTypeEvent::VoteTypeNotAdded {
+                vote_type_id: command.vote_type_id.clone(),
+                reason: reasons[0].clone(),
+            });
+        vec![event]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::entity::EventSourced;
+
+    #[test]
+    pub fn it_should_add_a_vote_type() {
+        let vote_type_list = VoteTypeList::default();
+        let command = AddVoteTypeCommand::new(
+            "fib".to_string(),
+            VoteValidation::fibonacci(),
+            "facilitator".to_string(),
+        );
+        let events = command.apply(&vote_type_list);
+        assert_eq!(
+            events,
+            vec![VoteTypeEvent::VoteTypeAdded {
+                vote_type_id: "fib".to_string(),
+                vote_validation: VoteValidation::fibonacci(),
+            }]
+        );
+    }
+
+    #[test]
+    pub fn it_should_not_add_a_vote_type_that_already_exists() {
+        let vote_type_list = VoteTypeList::source(&[VoteTypeEvent::VoteTypeAdded {
+            vote_type_id: "fib".to_string(),
+            vote_validation: VoteValidation::AnyNumber,
+        }]);
+        let command = AddVoteTypeCommand::new(
+            "fib".to_string(),
+            VoteValidation::fibonacci(),
+            "facilitator".to_string(),
+        );
+        let events = command.apply(&vote_type_list);
+        assert_eq!(
+            events,
+            vec![VoteTypeEvent::VoteTypeNotAdded {
+                vote_type_id: "fib".to_string(),
+                reason: VoteTypeNotAddedReason::AlreadyExists,
+            }]
+        );
+    }
+
+    #[test]
+    pub fn it_should_not_add_a_range_that_accepts_no_votes() {
+        let vote_type_list = VoteTypeList::default();
+        let command = AddVoteTypeCommand::new(
+            "range".to_string(),
+            VoteValidation::Range { min: 10, max: 1 },
+            "facilitator".to_string(),
+        );
+        let events = command.apply(&vote_type_list);
+        assert_eq!(
+            events,
+            vec![VoteTypeEvent::VoteTypeNotAdded {
+                vote_type_id: "range".to_string(),
+                reason: VoteTypeNotAddedReason::EmptyRange { min: 10, max: 1 },
+            }]
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use super::*;
+use crate::command::event::RoleNotAssignedReason;
+use serde::Deserialize;
+use util::command::Command;
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Hash)]
+pub struct AssignRoleCommand {
+    participant_id: String,
+    role: Role,
+    issued_by: String,
+}
+
+impl AssignRoleCommand {
+    pub fn new(participant_id: String, role: Role, issued_by: String) -> Self {
+        Self {
+            participant_id,
+            role,
+            issued_by,
+        }
+    }
+
+    pub fn issued_by(&self) -> &str {
+        &self.issued_by
+    }
+}
+
+impl Command for AssignRoleCommand {
+    type Entity = Board;
+    type Event = BoardModifiedEvent;
+
+    fn apply(&self, entity: &Self::Entity) -> Vec<Self::Event> {
+        let AssignRoleCommand {
+            participant_id,
+            role,
+            ..
+        } = self.clone();
+
+        if !entity.participants.contains_key(&participant_id) {
+            return vec![BoardModifiedEvent::RoleNotAssigned {
+                participant_id,
+                reason: RoleNotAssignedReason::DoesNotExist,
+            }];
+        }
+
+        vec![BoardModifiedEvent::ParticipantRoleAssigned {
+            participant_id,
+            role,
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::entity::EventSourced;
+
+    #[test]
+    pub fn it_should_assign_a_role() {
+        let board = Board::source(&[BoardModifiedEvent::ParticipantAdded {
+            participant_id: "test".to_string(),
+            participant_name: "test".to_string(),
+        }]);
+        let command = AssignRoleCommand::new(
+            "test".to_string(),
+            Role::Observer,
+            "facilitator".to_string(),
+        );
+        let events = command.apply(&board);
+        assert_eq!(
+            events,
+            vec![BoardModifiedEvent::ParticipantRoleAssigned {
+                participant_id: "test".to_string(),
+                role: Role::Observer,
+            }]
+        );
+    }
+
+    #[test]
+    pub fn it_should_not_assign_a_role_to_a_participant_that_does_not_exist() {
+        let board = Board::new();
+        let command = AssignRoleCommand::new(
+            "test".to_string(),
+            Role::Observer,
+            "facilitator".to_string(),
+        );
+        let events = command.apply(&board);
+        assert_eq!(
+            events,
+            vec![BoardModifiedEvent::RoleNotAssigned {
+                participant_id: "test".to_string(),
+                reason: RoleNotAssignedReason::DoesNotExist,
+            }]
+        );
+    }
+}
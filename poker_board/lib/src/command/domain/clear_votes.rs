@@ -3,18 +3,18 @@ use serde::Deserialize;
 use util::command::Command;
 use util::HandleCommand;
 
-#[derive(Deserialize, Debug, Clone, PartialEq)]
-pub struct ClearVotes {}
+#[derive(Deserialize, Debug, Clone, PartialEq, Hash)]
+pub struct ClearVotes {
+    issued_by: String,
+}
 
 impl ClearVotes {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(issued_by: String) -> Self {
+        Self { issued_by }
     }
-}
 
-impl Default for ClearVotes {
-    fn default() -> Self {
-        Self::new()
+    pub fn issued_by(&self) -> &str {
+        &self.issued_by
     }
 }
 
@@ -22,7 +22,7 @@ impl HandleCommand<ClearVotes> for Board {
     type Event = BoardModifiedEvent;
 
     fn execute(&self, _command: ClearVotes) -> Vec<Self::Event> {
-        vec![BoardModifiedEvent::VotesCleared]
+        self.close_round()
     }
 }
 
@@ -40,11 +40,20 @@ mod tests {
     use super::*;
 
     #[test]
-    pub fn it_should_clear_votes() {
+    pub fn it_should_clear_votes_by_closing_the_round() {
         let board = Board::new();
-        let command = ClearVotes {};
+        let command = ClearVotes::new("facilitator".to_string());
         let events = board.execute(command);
-        assert_eq!(events.len(), 1);
-        assert_eq!(events[0], BoardModifiedEvent::VotesCleared);
+        assert_eq!(
+            events,
+            vec![
+                BoardModifiedEvent::RoundClosed {
+                    round: 1,
+                    votes: vec![],
+                    stats: None,
+                },
+                BoardModifiedEvent::RoundStarted { round: 2 },
+            ]
+        );
     }
 }
@@ -0,0 +1,89 @@
+use super::*;
+use crate::command::event::StoryNotChangedReason;
+use serde::Deserialize;
+use util::command::Command;
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Hash)]
+pub struct RecordEstimateCommand {
+    story_id: String,
+    value: VoteValue,
+    issued_by: String,
+}
+
+impl RecordEstimateCommand {
+    pub fn new(story_id: String, value: VoteValue, issued_by: String) -> Self {
+        Self {
+            story_id,
+            value,
+            issued_by,
+        }
+    }
+
+    pub fn issued_by(&self) -> &str {
+        &self.issued_by
+    }
+}
+
+impl Command for RecordEstimateCommand {
+    type Entity = Backlog;
+    type Event = BoardModifiedEvent;
+
+    fn apply(&self, entity: &Self::Entity) -> Vec<Self::Event> {
+        let RecordEstimateCommand {
+            story_id, value, ..
+        } = self.clone();
+
+        if !entity.contains(&story_id) {
+            return vec![BoardModifiedEvent::EstimateNotRecorded {
+                story_id,
+                reason: StoryNotChangedReason::DoesNotExist,
+            }];
+        }
+
+        vec![BoardModifiedEvent::EstimateRecorded { story_id, value }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::entity::EventSourced;
+
+    #[test]
+    pub fn it_should_record_an_estimate() {
+        let backlog = Backlog::source(&[BoardModifiedEvent::StoryAdded {
+            story_id: "a".to_string(),
+            title: "A".to_string(),
+        }]);
+        let command = RecordEstimateCommand::new(
+            "a".to_string(),
+            VoteValue::Number(8),
+            "facilitator".to_string(),
+        );
+        let events = command.apply(&backlog);
+        assert_eq!(
+            events,
+            vec![BoardModifiedEvent::EstimateRecorded {
+                story_id: "a".to_string(),
+                value: VoteValue::Number(8),
+            }]
+        );
+    }
+
+    #[test]
+    pub fn it_should_not_record_an_estimate_for_a_story_that_does_not_exist() {
+        let command = RecordEstimateCommand::new(
+            "a".to_string(),
+            VoteValue::Number(8),
+            "facilitator".to_string(),
+        );
+        let events = command.apply(&Backlog::default());
+        assert_eq!(
+            events,
+            vec![BoardModifiedEvent::EstimateNotRecorded {
+                story_id: "a".to_string(),
+                reason: StoryNotChangedReason::DoesNotExist,
+            }]
+        );
+    }
+}
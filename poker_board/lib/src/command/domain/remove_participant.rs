@@ -3,14 +3,26 @@ use crate::command::event::ParticipantNotRemovedReason;
 use serde::Deserialize;
 use util::command::Command;
 
-#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Deserialize, Hash)]
 pub struct RemoveParticipantCommand {
     participant_id: String,
+    issued_by: String,
 }
 
 impl RemoveParticipantCommand {
-    pub fn new(participant_id: String) -> Self {
-        Self { participant_id }
+    pub fn new(participant_id: String, issued_by: String) -> Self {
+        Self {
+            participant_id,
+            issued_by,
+        }
+    }
+
+    pub fn issued_by(&self) -> &str {
+        &self.issued_by
+    }
+
+    pub fn is_kick(&self) -> bool {
+        self.participant_id != self.issued_by
     }
 }
 
@@ -19,7 +31,7 @@ impl Command for RemoveParticipantCommand {
     type Event = BoardModifiedEvent;
 
     fn apply(&self, entity: &Self::Entity) -> Vec<Self::Event> {
-        let RemoveParticipantCommand { participant_id } = self.clone();
+        let RemoveParticipantCommand { participant_id, .. } = self.clone();
 
         if !entity.participants.contains_key(&participant_id) {
             return vec![BoardModifiedEvent::ParticipantCouldNotBeRemoved {
@@ -28,7 +40,19 @@ impl Command for RemoveParticipantCommand {
             }];
         }
 
-        vec![BoardModifiedEvent::ParticipantRemoved { participant_id }]
+        let successor = match entity.role_of(&participant_id) {
+            Some(Role::Facilitator) => entity.longest_present_voter(&participant_id),
+            _ => None,
+        };
+
+        let mut events = vec![BoardModifiedEvent::ParticipantRemoved { participant_id }];
+        if let Some(successor) = successor {
+            events.push(BoardModifiedEvent::ParticipantRoleAssigned {
+                participant_id: successor.clone(),
+                role: Role::Facilitator,
+            });
+        }
+        events
     }
 }
 
@@ -45,9 +69,8 @@ mod tests {
         }];
 
         let board = Board::source(&events);
-        let command = RemoveParticipantCommand {
-            participant_id: board.participants.keys().next().unwrap().to_string(),
-        };
+        let participant_id = board.participants.keys().next().unwrap().to_string();
+        let command = RemoveParticipantCommand::new(participant_id.clone(), participant_id);
         let events = command.apply(&board);
         assert_eq!(events.len(), 1);
         assert_eq!(
@@ -61,9 +84,7 @@ mod tests {
     #[test]
     pub fn it_should_not_remove_a_participant_that_does_not_exist() {
         let board = Board::new();
-        let command = RemoveParticipantCommand {
-            participant_id: "test".to_string(),
-        };
+        let command = RemoveParticipantCommand::new("test".to_string(), "test".to_string());
         let events = command.apply(&board);
         assert_eq!(events.len(), 1);
         assert_eq!(
@@ -74,4 +95,36 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    pub fn it_should_hand_the_facilitator_role_on_when_the_facilitator_leaves() {
+        let board = Board::source(&[
+            BoardModifiedEvent::ParticipantAdded {
+                participant_id: "a".to_string(),
+                participant_name: "a".to_string(),
+            },
+            BoardModifiedEvent::ParticipantRoleAssigned {
+                participant_id: "a".to_string(),
+                role: Role::Facilitator,
+            },
+            BoardModifiedEvent::ParticipantAdded {
+                participant_id: "b".to_string(),
+                participant_name: "b".to_string(),
+            },
+        ]);
+        let command = RemoveParticipantCommand::new("a".to_string(), "a".to_string());
+        let events = command.apply(&board);
+        assert_eq!(
+            events,
+            vec![
+                BoardModifiedEvent::ParticipantRemoved {
+                    participant_id: "a".to_string(),
+                },
+                BoardModifiedEvent::ParticipantRoleAssigned {
+                    participant_id: "b".to_string(),
+                    role: Role::Facilitator,
+                },
+            ]
+        );
+    }
 }
@@ -0,0 +1,196 @@
+use super::*;
+use crate::command::event::ParticipantNotRenamedReason;
+use serde::Deserialize;
+use util::command::Command;
+use util::validate::ValidateCommand;
+
+pub const MAX_NAME_LENGTH: usize = 32;
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Hash)]
+pub struct RenameParticipantCommand {
+    participant_id: String,
+    participant_name: String,
+}
+
+impl RenameParticipantCommand {
+    pub fn new(participant_id: String, participant_name: String) -> Self {
+        Self {
+            participant_id,
+            participant_name,
+        }
+    }
+
+    pub fn participant_id(&self) -> &str {
+        &self.participant_id
+    }
+
+    fn name(&self) -> &str {
+        self.participant_name.trim()
+    }
+}
+
+fn have_existing_participant(
+    entity: &Board,
+    command: &RenameParticipantCommand,
+) -> Option<ParticipantNotRenamedReason> {
+    match entity.participants.contains_key(&command.participant_id) {
+        true => None,
+        false => Some(ParticipantNotRenamedReason::DoesNotExist),
+    }
+}
+
+fn have_a_name(
+    _entity: &Board,
+    command: &RenameParticipantCommand,
+) -> Option<ParticipantNotRenamedReason> {
+    match command.name().is_empty() {
+        true => Some(ParticipantNotRenamedReason::NameEmpty),
+        false => None,
+    }
+}
+
+fn fit_the_length_limit(
+    _entity: &Board,
+    command: &RenameParticipantCommand,
+) -> Option<ParticipantNotRenamedReason> {
+    match command.name().chars().count() > MAX_NAME_LENGTH {
+        true => Some(ParticipantNotRenamedReason::NameTooLong {
+            max: MAX_NAME_LENGTH,
+        }),
+        false => None,
+    }
+}
+
+fn have_a_unique_name(
+    entity: &Board,
+    command: &RenameParticipantCommand,
+) -> Option<ParticipantNotRenamedReason> {
+    let taken = entity
+        .participants
+        .iter()
+        .filter(|(participant_id, _)| **participant_id != command.participant_id)
+        .any(|(_, participant)| participant.name.eq_ignore_ascii_case(command.name()));
+    match taken {
+        true => Some(ParticipantNotRenamedReason::NameTaken),
+        false => None,
+    }
+}
+
+impl Command for RenameParticipantCommand {
+    type Entity = Board;
+    type Event = BoardModifiedEvent;
+
+    fn apply(&self, entity: &Self::Entity) -> Vec<Self::Event> {
+        self.should(have_existing_participant)
+            .should(have_a_name)
+            .should(fit_the_length_limit)
+            .should(have_a_unique_name)
+            .validate_against(entity)
+            .map(|command| BoardModifiedEvent::ParticipantRenamed {
+                participant_id: command.participant_id.clone(),
+                participant_name: command.name().to_string(),
+            })
+            .unwrap_or_else(|(_, reasons)| BoardModifiedEvent::ParticipantNotRenamed {
+                participant_id: self.participant_id.clone(),
+                reasons,
+            })
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::entity::EventSourced;
+
+    fn board() -> Board {
+        Board::source(&[
+            BoardModifiedEvent::ParticipantAdded {
+                participant_id: "a".to_string(),
+                participant_name: "Alice".to_string(),
+            },
+            BoardModifiedEvent::ParticipantAdded {
+                participant_id: "b".to_string(),
+                participant_name: "Bob".to_string(),
+            },
+        ])
+    }
+
+    #[test]
+    pub fn it_should_rename_a_participant() {
+        let command = RenameParticipantCommand::new("a".to_string(), "  Alicia ".to_string());
+        let events = command.apply(&board());
+        assert_eq!(
+            events,
+            vec![BoardModifiedEvent::ParticipantRenamed {
+                participant_id: "a".to_string(),
+                participant_name: "Alicia".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    pub fn it_should_not_rename_a_participant_that_does_not_exist() {
+        let command = RenameParticipantCommand::new("c".to_string(), "Carol".to_string());
+        let events = command.apply(&board());
+        assert_eq!(
+            events,
+            vec![BoardModifiedEvent::ParticipantNotRenamed {
+                participant_id: "c".to_string(),
+                reasons: vec![ParticipantNotRenamedReason::DoesNotExist],
+            }]
+        );
+    }
+
+    #[test]
+    pub fn it_should_not_accept_an_empty_name() {
+        let command = RenameParticipantCommand::new("a".to_string(), "   ".to_string());
+        let events = command.apply(&board());
+        assert_eq!(
+            events,
+            vec![BoardModifiedEvent::ParticipantNotRenamed {
+                participant_id: "a".to_string(),
+                reasons: vec![ParticipantNotRenamedReason::NameEmpty],
+            }]
+        );
+    }
+
+    #[test]
+    pub fn it_should_not_accept_a_name_over_the_length_limit() {
+        let command = RenameParticipantCommand::new("a".to_string(), "x".repeat(33));
+        let events = command.apply(&board());
+        assert_eq!(
+            events,
+            vec![BoardModifiedEvent::ParticipantNotRenamed {
+                participant_id: "a".to_string(),
+                reasons: vec![ParticipantNotRenamedReason::NameTooLong { max: 32 }],
+            }]
+        );
+    }
+
+    #[test]
+    pub fn it_should_not_accept_a_name_another_participant_has() {
+        let command = RenameParticipantCommand::new("a".to_string(), "bob".to_string());
+        let events = command.apply(&board());
+        assert_eq!(
+            events,
+            vec![BoardModifiedEvent::ParticipantNotRenamed {
+                participant_id: "a".to_string(),
+                reasons: vec![ParticipantNotRenamedReason::NameTaken],
+            }]
+        );
+    }
+
+    #[test]
+    pub fn it_should_allow_a_participant_to_change_the_case_of_their_own_name() {
+        let command = RenameParticipantCommand::new("a".to_string(), "ALICE".to_string());
+        let events = command.apply(&board());
+        assert_eq!(
+            events,
+            vec![BoardModifiedEvent::ParticipantRenamed {
+                participant_id: "a".to_string(),
+                participant_name: "ALICE".to_string(),
+            }]
+        );
+    }
+}
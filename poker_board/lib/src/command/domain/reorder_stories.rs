@@ -0,0 +1,96 @@
+use super::*;
+use crate::command::event::StoryNotChangedReason;
+use serde::Deserialize;
+use std::collections::HashSet;
+use util::command::Command;
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Hash)]
+pub struct ReorderStoriesCommand {
+    story_ids: Vec<String>,
+    issued_by: String,
+}
+
+impl ReorderStoriesCommand {
+    pub fn new(story_ids: Vec<String>, issued_by: String) -> Self {
+        Self {
+            story_ids,
+            issued_by,
+        }
+    }
+
+    pub fn issued_by(&self) -> &str {
+        &self.issued_by
+    }
+}
+
+impl Command for ReorderStoriesCommand {
+    type Entity = Backlog;
+    type Event = BoardModifiedEvent;
+
+    fn apply(&self, entity: &Self::Entity) -> Vec<Self::Event> {
+        let requested: HashSet<&String> = self.story_ids.iter().collect();
+        let existing: HashSet<&String> = entity.stories.iter().map(|story| &story.id).collect();
+
+        if requested.len() != self.story_ids.len() || requested != existing {
+            return vec![BoardModifiedEvent::StoriesNotReordered {
+                reason: StoryNotChangedReason::OrderDoesNotMatchStories,
+            }];
+        }
+
+        vec![BoardModifiedEvent::StoriesReordered {
+            story_ids: self.story_ids.clone(),
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::entity::EventSourced;
+
+    fn backlog() -> Backlog {
+        Backlog::source(&[
+            BoardModifiedEvent::StoryAdded {
+                story_id: "a".to_string(),
+                title: "A".to_string(),
+            },
+            BoardModifiedEvent::StoryAdded {
+                story_id: "b".to_string(),
+                title: "B".to_string(),
+            },
+        ])
+    }
+
+    #[test]
+    pub fn it_should_reorder_stories() {
+        let command = ReorderStoriesCommand::new(
+            vec!["b".to_string(), "a".to_string()],
+            "facilitator".to_string(),
+        );
+        let events = command.apply(&backlog());
+        assert_eq!(
+            events,
+            vec![BoardModifiedEvent::StoriesReordered {
+                story_ids: vec!["b".to_string(), "a".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    pub fn it_should_not_reorder_when_the_order_does_not_list_every_story_once() {
+        for story_ids in [
+            vec!["a".to_string()],
+            vec!["a".to_string(), "a".to_string()],
+            vec!["a".to_string(), "b".to_string(), "c".to_string()],
+        ] {
+            let events =
+                ReorderStoriesCommand::new(story_ids, "facilitator".to_string()).apply(&backlog());
+            assert_eq!(
+                events,
+                vec![BoardModifiedEvent::StoriesNotReordered {
+                    reason: StoryNotChangedReason::OrderDoesNotMatchStories,
+                }]
+            );
+        }
+    }
+}
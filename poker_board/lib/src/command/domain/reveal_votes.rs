@@ -0,0 +1,50 @@
+use super::*;
+use serde::Deserialize;
+use util::command::Command;
+use util::HandleCommand;
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Hash)]
+pub struct RevealVotes {
+    issued_by: String,
+}
+
+impl RevealVotes {
+    pub fn new(issued_by: String) -> Self {
+        Self { issued_by }
+    }
+
+    pub fn issued_by(&self) -> &str {
+        &self.issued_by
+    }
+}
+
+impl HandleCommand<RevealVotes> for Board {
+    type Event = BoardModifiedEvent;
+
+    fn execute(&self, _command: RevealVotes) -> Vec<Self::Event> {
+        vec![BoardModifiedEvent::VotesRevealed]
+    }
+}
+
+impl Command for RevealVotes {
+    type Entity = Board;
+    type Event = BoardModifiedEvent;
+
+    fn apply(&self, entity: &Self::Entity) -> Vec<Self::Event> {
+        entity.execute(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_should_reveal_votes() {
+        let board = Board::new();
+        let command = RevealVotes::new("facilitator".to_string());
+        let events = board.execute(command);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0], BoardModifiedEvent::VotesRevealed);
+    }
+}
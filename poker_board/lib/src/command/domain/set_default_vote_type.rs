@@ -0,0 +1,77 @@
+use super::*;
+use crate::command::event::DefaultVoteTypeNotSetReason;
+use serde::Deserialize;
+use util::command::Command;
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Hash)]
+pub struct SetDefaultVoteTypeCommand {
+    vote_type_id: String,
+    issued_by: String,
+}
+
+impl SetDefaultVoteTypeCommand {
+    pub fn new(vote_type_id: String, issued_by: String) -> Self {
+        Self {
+            vote_type_id,
+            issued_by,
+        }
+    }
+
+    pub fn issued_by(&self) -> &str {
+        &self.issued_by
+    }
+}
+
+impl Command for SetDefaultVoteTypeCommand {
+    type Entity = VoteTypeList;
+    type Event = VoteTypeEvent;
+
+    fn apply(&self, entity: &Self::Entity) -> Vec<Self::Event> {
+        let SetDefaultVoteTypeCommand { vote_type_id, .. } = self.clone();
+
+        if !entity.vote_types.contains_key(&vote_type_id) {
+            return vec![VoteTypeEvent::DefaultVoteTypeNotSet {
+                vote_type_id,
+                reason: DefaultVoteTypeNotSetReason::DoesNotExist,
+            }];
+        }
+
+        vec![VoteTypeEvent::DefaultVoteTypeSet { vote_type_id }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::entity::EventSourced;
+
+    #[test]
+    pub fn it_should_set_the_default_vote_type() {
+        let vote_type_list = VoteTypeList::source(&[VoteTypeEvent::VoteTypeAdded {
+            vote_type_id: "test".to_string(),
+            vote_validation: VoteValidation::AnyNumber,
+        }]);
+        let command = SetDefaultVoteTypeCommand::new("test".to_string(), "facilitator".to_string());
+        let events = command.apply(&vote_type_list);
+        assert_eq!(
+            events,
+            vec![VoteTypeEvent::DefaultVoteTypeSet {
+                vote_type_id: "test".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    pub fn it_should_not_set_a_default_vote_type_that_does_not_exist() {
+        let vote_type_list = VoteTypeList::default();
+        let command = SetDefaultVoteTypeCommand::new("test".to_string(), "facilitator".to_string());
+        let events = command.apply(&vote_type_list);
+        assert_eq!(
+            events,
+            vec![VoteTypeEvent::DefaultVoteTypeNotSet {
+                vote_type_id: "test".to_string(),
+                reason: DefaultVoteTypeNotSetReason::DoesNotExist,
+            }]
+        );
+    }
+}
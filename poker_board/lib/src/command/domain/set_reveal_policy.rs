@@ -0,0 +1,49 @@
+use super::*;
+use crate::command::event::RevealPolicy;
+use serde::Deserialize;
+use util::command::Command;
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Hash)]
+pub struct SetRevealPolicyCommand {
+    policy: RevealPolicy,
+    issued_by: String,
+}
+
+impl SetRevealPolicyCommand {
+    pub fn new(policy: RevealPolicy, issued_by: String) -> Self {
+        Self { policy, issued_by }
+    }
+
+    pub fn issued_by(&self) -> &str {
+        &self.issued_by
+    }
+}
+
+impl Command for SetRevealPolicyCommand {
+    type Entity = Board;
+    type Event = BoardModifiedEvent;
+
+    fn apply(&self, _entity: &Self::Entity) -> Vec<Self::Event> {
+        vec![BoardModifiedEvent::RevealPolicyChanged {
+            policy: self.policy,
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn it_should_change_the_reveal_policy() {
+        let board = Board::new();
+        let command = SetRevealPolicyCommand::new(RevealPolicy::Manual, "facilitator".to_string());
+        let events = command.apply(&board);
+        assert_eq!(
+            events,
+            vec![BoardModifiedEvent::RevealPolicyChanged {
+                policy: RevealPolicy::Manual,
+            }]
+        );
+    }
+}
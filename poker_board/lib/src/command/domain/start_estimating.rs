@@ -0,0 +1,87 @@
+use super::*;
+use crate::command::event::StoryNotChangedReason;
+use serde::Deserialize;
+use util::command::Command;
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Hash)]
+pub struct StartEstimatingCommand {
+    story_id: String,
+    issued_by: String,
+}
+
+impl StartEstimatingCommand {
+    pub fn new(story_id: String, issued_by: String) -> Self {
+        Self {
+            story_id,
+            issued_by,
+        }
+    }
+
+    pub fn issued_by(&self) -> &str {
+        &self.issued_by
+    }
+}
+
+impl Command for StartEstimatingCommand {
+    type Entity = CombinedDomain;
+    type Event = BoardModifiedEvent;
+
+    fn apply(&self, entity: &Self::Entity) -> Vec<Self::Event> {
+        let StartEstimatingCommand { story_id, .. } = self.clone();
+
+        if !entity.backlog().contains(&story_id) {
+            return vec![BoardModifiedEvent::EstimationNotStarted {
+                story_id,
+                reason: StoryNotChangedReason::DoesNotExist,
+            }];
+        }
+
+        let mut events = vec![BoardModifiedEvent::EstimationStarted { story_id }];
+        events.extend(entity.board().close_round());
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::entity::EventSourced;
+
+    #[test]
+    pub fn it_should_start_estimating_in_a_new_round() {
+        let domain = CombinedDomain::source(&[BoardModifiedEvent::StoryAdded {
+            story_id: "a".to_string(),
+            title: "A".to_string(),
+        }
+        .into()]);
+        let events =
+            StartEstimatingCommand::new("a".to_string(), "facilitator".to_string()).apply(&domain);
+        assert_eq!(
+            events,
+            vec![
+                BoardModifiedEvent::EstimationStarted {
+                    story_id: "a".to_string(),
+                },
+                BoardModifiedEvent::RoundClosed {
+                    round: 1,
+                    votes: vec![],
+                    stats: None,
+                },
+                BoardModifiedEvent::RoundStarted { round: 2 },
+            ]
+        );
+    }
+
+    #[test]
+    pub fn it_should_not_start_estimating_a_story_that_does_not_exist() {
+        let events = StartEstimatingCommand::new("a".to_string(), "facilitator".to_string())
+            .apply(&CombinedDomain::default());
+        assert_eq!(
+            events,
+            vec![BoardModifiedEvent::EstimationNotStarted {
+                story_id: "a".to_string(),
+                reason: StoryNotChangedReason::DoesNotExist,
+            }]
+        );
+    }
+}
@@ -5,34 +5,102 @@ use serde::Deserialize;
 use util::command::Command;
 use util::validate::ValidateCommand;
 
-#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Deserialize, Hash)]
+#[serde(from = "ParticipantVoteBody")]
 pub struct ParticipantVote {
     pub participant_id: String,
-    pub vote: Vote,
+    pub vote_type_id: Option<String>,
+    pub value: VoteValue,
+}
+
+/// Votes were posted with a nested `vote` before the vote type became optional, and
+/// clients may still send them that way.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ParticipantVoteBody {
+    Nested {
+        participant_id: String,
+        vote: Vote,
+    },
+    Flat {
+        participant_id: String,
+        #[serde(default)]
+        vote_type_id: Option<String>,
+        value: VoteValue,
+    },
+}
+
+impl From<ParticipantVoteBody> for ParticipantVote {
+    fn from(body: ParticipantVoteBody) -> Self {
+        match body {
+            ParticipantVoteBody::Nested {
+                participant_id,
+                vote,
+            } => Self::new(participant_id, vote),
+            ParticipantVoteBody::Flat {
+                participant_id,
+                vote_type_id,
+                value,
+            } => Self {
+                participant_id,
+                vote_type_id,
+                value,
+            },
+        }
+    }
 }
 
 impl ParticipantVote {
     pub fn new(participant_id: String, vote: Vote) -> Self {
         Self {
             participant_id,
-            vote,
+            vote_type_id: Some(vote.vote_type_id),
+            value: vote.value,
+        }
+    }
+
+    pub fn with_default_type(participant_id: String, value: VoteValue) -> Self {
+        Self {
+            participant_id,
+            vote_type_id: None,
+            value,
+        }
+    }
+
+    fn vote_type<'a>(
+        &self,
+        vote_type_list: &'a VoteTypeList,
+    ) -> Result<&'a VoteType, ParticipantNotVotedReason> {
+        match &self.vote_type_id {
+            Some(vote_type_id) => vote_type_list.vote_types.get(vote_type_id).ok_or(
+                ParticipantNotVotedReason::VoteTypeDoesNotExist(vote_type_id.clone()),
+            ),
+            None => vote_type_list
+                .default_vote_type()
+                .ok_or(ParticipantNotVotedReason::NoDefaultVoteType),
         }
     }
 }
 
 impl VoteValidation {
     fn valid_vote(&self, vote: &VoteValue) -> Option<ParticipantNotVotedReason> {
-        match self {
-            VoteValidation::AnyNumber => {
-                if let VoteValue::Number(_) = vote {
-                    None
-                } else {
-                    Some(ParticipantNotVotedReason::InvalidVote {
-                        expected: self.clone(),
-                        received: vote.clone(),
-                    })
-                }
+        let valid = match (self, vote) {
+            (VoteValidation::AnyNumber, VoteValue::Number(_)) => true,
+            (VoteValidation::OneOf(cards), vote) => cards.contains(vote),
+            (VoteValidation::Range { min, max }, VoteValue::Number(number)) => {
+                (min..=max).contains(&number)
             }
+            (VoteValidation::Labels(labels), VoteValue::String(label)) => labels.contains(label),
+            _ => false,
+        };
+
+        if valid {
+            None
+        } else {
+            Some(ParticipantNotVotedReason::InvalidVote {
+                expected: self.clone(),
+                received: vote.clone(),
+            })
         }
     }
 }
@@ -47,21 +115,21 @@ fn be_valid_vote(
     entity: &CombinedDomain,
     command: &ParticipantVote,
 ) -> Option<ParticipantNotVotedReason> {
-    entity
-        .0
-        .vote_types
-        .get(&command.vote.vote_type_id)
-        .map(|v| v.validation.valid_vote(&command.vote.value))
-        .unwrap_or(Some(ParticipantNotVotedReason::VoteTypeDoesNotExist(
-            command.vote.vote_type_id.clone(),
-        )))
+    command
+        .vote_type(entity.vote_type_list())
+        .map(|v| v.validation.valid_vote(&command.value))
+        .unwrap_or_else(Some)
 }
 
 fn have_existing_participant(
     entity: &CombinedDomain,
     command: &ParticipantVote,
 ) -> Option<ParticipantNotVotedReason> {
-    match entity.1.participants.contains_key(&command.participant_id) {
+    match entity
+        .board()
+        .participants
+        .contains_key(&command.participant_id)
+    {
         true => None,
         false => Some(ParticipantNotVotedReason::DoesNotExist),
     }
@@ -75,9 +143,12 @@ impl Command for ParticipantVote {
         self.should(be_valid_vote)
             .should(have_existing_participant)
             .validate_against(entity)
-            .map(|_| ParticipantVoted {
-                participant_id: self.participant_id.clone(),
-                vote: self.vote.clone(),
+            .and_then(|command| match command.vote_type(entity.vote_type_list()) {
+                Ok(vote_type) => Ok(ParticipantVoted {
+                    participant_id: command.participant_id.clone(),
+                    vote: Vote::new(vote_type.id.clone(), command.value.clone()),
+                }),
+                Err(reason) => Err((command, vec![reason])),
             })
             .unwrap_or_else(|(_, errors)| BoardModifiedEvent::ParticipantCouldNotVote {
                 participant_id: self.participant_id.clone(),
@@ -92,6 +163,28 @@ mod tests {
     use super::*;
     use util::entity::EventSourced;
 
+    #[test]
+    pub fn it_should_read_votes_with_or_without_a_nested_vote() {
+        let nested: ParticipantVote = serde_json::from_str(
+            r#"{"participant_id":"test","vote":{"vote_type_id":"1","value":{"Number":3}}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            nested,
+            ParticipantVote::new(
+                "test".to_string(),
+                Vote::new("1".to_string(), VoteValue::Number(3))
+            )
+        );
+
+        let flat: ParticipantVote =
+            serde_json::from_str(r#"{"participant_id":"test","value":{"Number":3}}"#).unwrap();
+        assert_eq!(
+            flat,
+            ParticipantVote::with_default_type("test".to_string(), VoteValue::Number(3))
+        );
+    }
+
     #[test]
     pub fn it_should_vote_for_a_participant() {
         let events = vec![BoardModifiedEvent::ParticipantAdded {
@@ -107,12 +200,16 @@ mod tests {
                 validation: VoteValidation::AnyNumber,
             },
         );
-        let vote_type_list = VoteTypeList { vote_types };
-        let combined_domain = CombinedDomain(vote_type_list, board.clone());
-        let command = ParticipantVote {
-            participant_id: board.participants.keys().next().unwrap().to_string(),
-            vote: Vote::new("test".to_string(), VoteValue::Number(1)),
+        let vote_type_list = VoteTypeList {
+            vote_types,
+            default_vote_type: None,
         };
+        let combined_domain =
+            CombinedDomain::new(vote_type_list, board.clone(), Backlog::default());
+        let command = ParticipantVote::new(
+            board.participants.keys().next().unwrap().to_string(),
+            Vote::new("test".to_string(), VoteValue::Number(1)),
+        );
 
         let events = command.apply(&combined_domain);
         assert_eq!(events.len(), 1);
@@ -128,10 +225,10 @@ mod tests {
     #[test]
     pub fn it_should_not_vote_for_a_participant_that_does_not_exist() {
         let board = Board::new();
-        let command = ParticipantVote {
-            participant_id: "test".to_string(),
-            vote: Vote::new("test".to_string(), VoteValue::Number(1)),
-        };
+        let command = ParticipantVote::new(
+            "test".to_string(),
+            Vote::new("test".to_string(), VoteValue::Number(1)),
+        );
         let mut vote_types = HashMap::new();
         vote_types.insert(
             "test".to_string(),
@@ -140,8 +237,11 @@ mod tests {
                 validation: VoteValidation::AnyNumber,
             },
         );
-        let vote_type_list = VoteTypeList { vote_types };
-        let combined_domain = CombinedDomain(vote_type_list, board);
+        let vote_type_list = VoteTypeList {
+            vote_types,
+            default_vote_type: None,
+        };
+        let combined_domain = CombinedDomain::new(vote_type_list, board, Backlog::default());
         let events = command.apply(&combined_domain);
         assert_eq!(events.len(), 1);
         assert_eq!(
@@ -160,10 +260,10 @@ mod tests {
             participant_name: "test".to_string(),
         }];
         let board = Board::source(&events);
-        let command = ParticipantVote {
-            participant_id: "test".to_string(),
-            vote: Vote::new("not_present".to_string(), VoteValue::Number(1)),
-        };
+        let command = ParticipantVote::new(
+            "test".to_string(),
+            Vote::new("not_present".to_string(), VoteValue::Number(1)),
+        );
         let mut vote_types = HashMap::new();
         vote_types.insert(
             "test".to_string(),
@@ -172,8 +272,11 @@ mod tests {
                 validation: VoteValidation::AnyNumber,
             },
         );
-        let vote_type_list = VoteTypeList { vote_types };
-        let combined_domain = CombinedDomain(vote_type_list, board);
+        let vote_type_list = VoteTypeList {
+            vote_types,
+            default_vote_type: None,
+        };
+        let combined_domain = CombinedDomain::new(vote_type_list, board, Backlog::default());
         let events = command.apply(&combined_domain);
         assert_eq!(events.len(), 1);
         assert_eq!(
@@ -194,10 +297,10 @@ mod tests {
             participant_name: "test".to_string(),
         }];
         let board = Board::source(&events);
-        let command = ParticipantVote {
-            participant_id: "test".to_string(),
-            vote: Vote::new("test".to_string(), VoteValue::String("test".to_string())),
-        };
+        let command = ParticipantVote::new(
+            "test".to_string(),
+            Vote::new("test".to_string(), VoteValue::String("test".to_string())),
+        );
         let mut vote_types = HashMap::new();
         vote_types.insert(
             "test".to_string(),
@@ -206,8 +309,11 @@ mod tests {
                 validation: VoteValidation::AnyNumber,
             },
         );
-        let vote_type_list = VoteTypeList { vote_types };
-        let combined_domain = CombinedDomain(vote_type_list, board);
+        let vote_type_list = VoteTypeList {
+            vote_types,
+            default_vote_type: None,
+        };
+        let combined_domain = CombinedDomain::new(vote_type_list, board, Backlog::default());
         let events = command.apply(&combined_domain);
         assert_eq!(events.len(), 1);
         assert_eq!(
@@ -221,4 +327,101 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    pub fn it_should_vote_with_the_default_vote_type_when_none_is_given() {
+        let board = Board::source(&[BoardModifiedEvent::ParticipantAdded {
+            participant_id: "test".to_string(),
+            participant_name: "test".to_string(),
+        }]);
+        let vote_type_list = VoteTypeList::source(&[
+            VoteTypeEvent::VoteTypeAdded {
+                vote_type_id: "fib".to_string(),
+                vote_validation: VoteValidation::fibonacci(),
+            },
+            VoteTypeEvent::DefaultVoteTypeSet {
+                vote_type_id: "fib".to_string(),
+            },
+        ]);
+        let combined_domain = CombinedDomain::new(vote_type_list, board, Backlog::default());
+        let command = ParticipantVote::with_default_type("test".to_string(), VoteValue::Number(8));
+        let events = command.apply(&combined_domain);
+        assert_eq!(
+            events,
+            vec![ParticipantVoted {
+                participant_id: "test".to_string(),
+                vote: Vote::new("fib".to_string(), VoteValue::Number(8)),
+            }]
+        );
+    }
+
+    #[test]
+    pub fn it_should_not_vote_without_a_vote_type_when_there_is_no_default() {
+        let board = Board::source(&[BoardModifiedEvent::ParticipantAdded {
+            participant_id: "test".to_string(),
+            participant_name: "test".to_string(),
+        }]);
+        let combined_domain =
+            CombinedDomain::new(VoteTypeList::default(), board, Backlog::default());
+        let command = ParticipantVote::with_default_type("test".to_string(), VoteValue::Number(8));
+        let events = command.apply(&combined_domain);
+        assert_eq!(
+            events,
+            vec![BoardModifiedEvent::ParticipantCouldNotVote {
+                participant_id: "test".to_string(),
+                reasons: vec![ParticipantNotVotedReason::NoDefaultVoteType],
+            }]
+        );
+    }
+
+    mod valid_vote {
+        use super::*;
+
+        #[test]
+        pub fn it_should_accept_a_card_from_the_deck() {
+            let validation = VoteValidation::modified_fibonacci();
+            assert_eq!(validation.valid_vote(&VoteValue::Number(13)), None);
+            assert_eq!(
+                validation.valid_vote(&VoteValue::String("½".to_string())),
+                None
+            );
+        }
+
+        #[test]
+        pub fn it_should_list_the_deck_when_the_card_is_not_in_it() {
+            let validation = VoteValidation::fibonacci();
+            assert_eq!(
+                validation.valid_vote(&VoteValue::Number(4)),
+                Some(ParticipantNotVotedReason::InvalidVote {
+                    expected: validation.clone(),
+                    received: VoteValue::Number(4),
+                })
+            );
+        }
+
+        #[test]
+        pub fn it_should_accept_numbers_within_an_inclusive_range() {
+            let validation = VoteValidation::Range { min: 1, max: 10 };
+            assert_eq!(validation.valid_vote(&VoteValue::Number(1)), None);
+            assert_eq!(validation.valid_vote(&VoteValue::Number(10)), None);
+            assert!(validation.valid_vote(&VoteValue::Number(0)).is_some());
+            assert!(validation.valid_vote(&VoteValue::Number(11)).is_some());
+            assert!(validation
+                .valid_vote(&VoteValue::String("5".to_string()))
+                .is_some());
+        }
+
+        #[test]
+        pub fn it_should_only_accept_known_labels() {
+            let validation = VoteValidation::t_shirt_sizes();
+            assert_eq!(
+                validation.valid_vote(&VoteValue::String("XL".to_string())),
+                None
+            );
+            assert!(validation
+                .valid_vote(&VoteValue::String("XXXL".to_string()))
+                .is_some());
+            assert!(validation.valid_vote(&VoteValue::Number(1)).is_some());
+        }
+    }
 }
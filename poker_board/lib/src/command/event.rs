@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
 use std::fmt::Display;
+use util::composite::SubEvent;
+use util::schema::{Schema, INITIAL_VERSION};
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum BoardModifiedEvent {
     ParticipantAdded {
         participant_id: String,
@@ -14,6 +16,26 @@ pub enum BoardModifiedEvent {
     ParticipantRemoved {
         participant_id: String,
     },
+    ParticipantRenamed {
+        participant_id: String,
+        participant_name: String,
+    },
+    ParticipantNotRenamed {
+        participant_id: String,
+        reasons: Vec<ParticipantNotRenamedReason>,
+    },
+    ParticipantRoleAssigned {
+        participant_id: String,
+        role: Role,
+    },
+    RoleNotAssigned {
+        participant_id: String,
+        reason: RoleNotAssignedReason,
+    },
+    CommandRejected {
+        participant_id: String,
+        reason: CommandRejectedReason,
+    },
     ParticipantCouldNotBeRemoved {
         participant_id: String,
         reason: ParticipantNotRemovedReason,
@@ -27,6 +49,47 @@ pub enum BoardModifiedEvent {
         reasons: Vec<ParticipantNotVotedReason>,
     },
     VotesCleared,
+    RoundClosed {
+        round: usize,
+        votes: Vec<RoundVote>,
+        stats: Option<VoteStats>,
+    },
+    RoundStarted {
+        round: usize,
+    },
+    VotesRevealed,
+    RevealPolicyChanged {
+        policy: RevealPolicy,
+    },
+    StoryAdded {
+        story_id: String,
+        title: String,
+    },
+    StoryNotAdded {
+        story_id: String,
+        reason: StoryNotChangedReason,
+    },
+    StoriesReordered {
+        story_ids: Vec<String>,
+    },
+    StoriesNotReordered {
+        reason: StoryNotChangedReason,
+    },
+    EstimationStarted {
+        story_id: String,
+    },
+    EstimationNotStarted {
+        story_id: String,
+        reason: StoryNotChangedReason,
+    },
+    EstimateRecorded {
+        story_id: String,
+        value: VoteValue,
+    },
+    EstimateNotRecorded {
+        story_id: String,
+        reason: StoryNotChangedReason,
+    },
 }
 
 impl Display for BoardModifiedEvent {
@@ -35,13 +98,67 @@ impl Display for BoardModifiedEvent {
     }
 }
 
+/// Applies a `StoriesReordered` event to `stories`, putting them in the order of
+/// `story_ids`. Stories it does not list keep their relative order at the end.
+pub(crate) fn reorder_stories<S>(stories: &mut [S], story_ids: &[String], id: impl Fn(&S) -> &str) {
+    stories.sort_by_key(|story| {
+        story_ids
+            .iter()
+            .position(|story_id| story_id == id(story))
+            .unwrap_or(usize::MAX)
+    });
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RoundVote {
+    pub participant_id: String,
+    pub participant_name: String,
+    pub vote: Vote,
+}
+
+#[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct VoteStats {
+    pub average: usize,
+    pub max: usize,
+    pub min: usize,
+}
+
+impl VoteStats {
+    pub fn from_votes<'a>(votes: impl Iterator<Item = &'a VoteValue>) -> Option<Self> {
+        let mut votes = votes
+            .filter_map(|vote| match vote {
+                VoteValue::Number(number) if *number != 0 => Some(*number),
+                _ => None,
+            })
+            .collect::<Vec<u8>>();
+        votes.sort();
+
+        let min = *votes.first()?;
+        let max = *votes.last()?;
+        let average = votes[votes.len() / 2];
+
+        Some(Self {
+            average: average as usize,
+            max: max as usize,
+            min: min as usize,
+        })
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize, Serialize, Hash)]
+pub enum RevealPolicy {
+    #[default]
+    WhenAllVoted,
+    Manual,
+}
+
 #[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
 pub struct Vote {
     pub vote_type_id: String,
     pub value: VoteValue,
 }
 
-#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize, Hash)]
 pub enum VoteValue {
     Number(u8),
     String(String),
@@ -56,45 +173,162 @@ impl Vote {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize, Serialize, Hash)]
+pub enum Role {
+    Facilitator,
+    #[default]
+    Voter,
+    Observer,
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize, Serialize, Hash)]
+pub enum ParticipantKind {
+    #[default]
+    Voter,
+    Observer,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RoleNotAssignedReason {
+    DoesNotExist,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CommandRejectedReason {
+    NotAuthorized,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ParticipantNotAddedReason {
     AlreadyExists,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ParticipantNotRemovedReason {
     DoesNotExist,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ParticipantNotRenamedReason {
+    DoesNotExist,
+    NameEmpty,
+    NameTooLong { max: usize },
+    NameTaken,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StoryNotChangedReason {
+    AlreadyExists,
+    DoesNotExist,
+    OrderDoesNotMatchStories,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ParticipantNotVotedReason {
     DoesNotExist,
     VoteTypeDoesNotExist(String),
+    NoDefaultVoteType,
     InvalidVote {
         expected: VoteValidation,
         received: VoteValue,
     },
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum VoteTypeEvent {
     VoteTypeAdded {
         vote_type_id: String,
         vote_validation: VoteValidation,
     },
+    VoteTypeNotAdded {
+        vote_type_id: String,
+        reason: VoteTypeNotAddedReason,
+    },
+    VoteTypeRemoved {
+        vote_type_id: String,
+    },
+    VoteTypeNotRemoved {
+        vote_type_id: String,
+        reason: VoteTypeNotRemovedReason,
+    },
+    DefaultVoteTypeSet {
+        vote_type_id: String,
+    },
+    DefaultVoteTypeNotSet {
+        vote_type_id: String,
+        reason: DefaultVoteTypeNotSetReason,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum VoteTypeNotAddedReason {
+    AlreadyExists,
+    EmptyRange { min: u8, max: u8 },
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum VoteTypeNotRemovedReason {
+    DoesNotExist,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DefaultVoteTypeNotSetReason {
+    DoesNotExist,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Hash)]
 pub enum VoteValidation {
     AnyNumber,
+    OneOf(Vec<VoteValue>),
+    Range { min: u8, max: u8 },
+    Labels(Vec<String>),
+}
+
+impl VoteValidation {
+    pub fn fibonacci() -> Self {
+        Self::numbers(&[0, 1, 2, 3, 5, 8, 13, 21, 34, 55, 89])
+    }
+
+    pub fn modified_fibonacci() -> Self {
+        let mut cards = vec![VoteValue::Number(0), VoteValue::String("½".to_string())];
+        cards.extend([1, 2, 3, 5, 8, 13, 20, 40, 100].map(VoteValue::Number));
+        Self::OneOf(cards)
+    }
+
+    pub fn powers_of_two() -> Self {
+        Self::numbers(&[0, 1, 2, 4, 8, 16, 32, 64])
+    }
+
+    pub fn t_shirt_sizes() -> Self {
+        Self::Labels(
+            ["XS", "S", "M", "L", "XL", "XXL"]
+                .map(String::from)
+                .to_vec(),
+        )
+    }
+
+    fn numbers(cards: &[u8]) -> Self {
+        Self::OneOf(cards.iter().copied().map(VoteValue::Number).collect())
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum CombinedEvent {
     BoardModifiedEvent(BoardModifiedEvent),
     VoteTypeEvent(VoteTypeEvent),
 }
 
+impl CombinedEvent {
+    /// Tells apart the sub-streams combined events are stored in, so snapshots count the
+    /// events of each on its own.
+    pub fn stream(&self) -> usize {
+        match self {
+            CombinedEvent::VoteTypeEvent(_) => 0,
+            CombinedEvent::BoardModifiedEvent(_) => 1,
+        }
+    }
+}
+
 impl From<BoardModifiedEvent> for CombinedEvent {
     fn from(event: BoardModifiedEvent) -> Self {
         Self::BoardModifiedEvent(event)
@@ -106,3 +340,115 @@ impl From<VoteTypeEvent> for CombinedEvent {
         Self::VoteTypeEvent(event)
     }
 }
+
+impl SubEvent<CombinedEvent> for BoardModifiedEvent {
+    fn from_composite(event: &CombinedEvent) -> Option<&Self> {
+        match event {
+            CombinedEvent::BoardModifiedEvent(event) => Some(event),
+            _ => None,
+        }
+    }
+}
+
+impl SubEvent<CombinedEvent> for VoteTypeEvent {
+    fn from_composite(event: &CombinedEvent) -> Option<&Self> {
+        match event {
+            CombinedEvent::VoteTypeEvent(event) => Some(event),
+            _ => None,
+        }
+    }
+}
+
+/// Events are stored externally tagged, as `"Variant"` or `{"Variant": {...}}`, the shape
+/// they had when they were first persisted and the one clients receive.
+impl Schema for BoardModifiedEvent {
+    const VERSION: u32 = INITIAL_VERSION;
+}
+
+impl Schema for VoteTypeEvent {
+    const VERSION: u32 = INITIAL_VERSION;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_put_stories_missing_from_the_order_last() {
+        let mut stories = vec!["a", "b", "c", "d"];
+        reorder_stories(&mut stories, &["c".to_string(), "a".to_string()], |story| {
+            story
+        });
+        assert_eq!(stories, vec!["c", "a", "b", "d"]);
+    }
+
+    #[test]
+    fn it_should_tag_board_events_with_their_variant() {
+        let event = BoardModifiedEvent::ParticipantAdded {
+            participant_id: "a".to_string(),
+            participant_name: "Alice".to_string(),
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "ParticipantAdded": {
+                    "participant_id": "a",
+                    "participant_name": "Alice",
+                }
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<BoardModifiedEvent>(json).unwrap(),
+            event
+        );
+    }
+
+    #[test]
+    fn it_should_tag_unit_board_events_with_their_variant() {
+        let json = serde_json::to_value(BoardModifiedEvent::VotesRevealed).unwrap();
+        assert_eq!(json, serde_json::json!("VotesRevealed"));
+    }
+
+    #[test]
+    fn it_should_tag_vote_type_events_with_their_variant() {
+        let event = VoteTypeEvent::DefaultVoteTypeSet {
+            vote_type_id: "1".to_string(),
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "DefaultVoteTypeSet": { "vote_type_id": "1" } })
+        );
+        assert_eq!(
+            serde_json::from_value::<VoteTypeEvent>(json).unwrap(),
+            event
+        );
+    }
+
+    #[test]
+    fn it_should_read_events_stored_before_they_had_envelopes() {
+        let stored = r#"{"ParticipantAdded":{"participant_id":"a","participant_name":"Alice"}}"#;
+        let envelope = util::envelope::decode::<BoardModifiedEvent>(stored, 0).unwrap();
+        assert_eq!(
+            envelope.event,
+            BoardModifiedEvent::ParticipantAdded {
+                participant_id: "a".to_string(),
+                participant_name: "Alice".to_string(),
+            }
+        );
+
+        let stored = r#""VotesRevealed""#;
+        let envelope = util::envelope::decode::<BoardModifiedEvent>(stored, 1).unwrap();
+        assert_eq!(envelope.event, BoardModifiedEvent::VotesRevealed);
+
+        let stored = r#"{"schema_version":1,"sequence":2,"recorded_at":0,"event":{"DefaultVoteTypeSet":{"vote_type_id":"1"}}}"#;
+        let envelope = util::envelope::decode::<VoteTypeEvent>(stored, 2).unwrap();
+        assert_eq!(
+            envelope.event,
+            VoteTypeEvent::DefaultVoteTypeSet {
+                vote_type_id: "1".to_string(),
+            }
+        );
+    }
+}
@@ -1,76 +1,183 @@
-use crate::command::event::{BoardModifiedEvent, VoteValue};
-use serde::Serialize;
+use crate::command::event::{reorder_stories, BoardModifiedEvent, RevealPolicy, Role, VoteValue};
 use std::collections::HashMap;
 use util::entity::HandleEvent;
 
+pub mod rounds;
+
 pub mod presentation {
-    use crate::query::{Board, Participant};
+    use crate::command::event::{Role, VoteStats, VoteValue};
+    use crate::query::{Board, Participant, Story};
     use serde::Serialize;
-    use std::borrow::{Borrow, BorrowMut};
     use util::query::PresentationOf;
 
     #[derive(Default, Debug, PartialEq, Clone, Serialize)]
     pub struct BoardPresentation {
-        participants: Vec<Participant>,
+        participants: Vec<ParticipantPresentation>,
+        observers: Vec<ObserverPresentation>,
         #[serde(flatten, skip_serializing_if = "Option::is_none")]
-        stats: Option<Stats>,
+        stats: Option<VoteStats>,
         voting_complete: bool,
+        votes_revealed: bool,
+        current_story: Option<StoryPresentation>,
+        story_queue: Vec<StoryPresentation>,
     }
 
-    #[derive(Default, Debug, PartialEq, Clone, Serialize)]
-    struct Stats {
-        average: usize,
-        max: usize,
-        min: usize,
+    #[derive(Debug, PartialEq, Clone, Serialize)]
+    pub struct StoryPresentation {
+        id: String,
+        title: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        estimate: Option<Card>,
+    }
+
+    impl From<&Story> for StoryPresentation {
+        fn from(story: &Story) -> Self {
+            Self {
+                id: story.id.clone(),
+                title: story.title.clone(),
+                estimate: story.estimate.as_ref().map(Card::from),
+            }
+        }
+    }
+
+    #[derive(Debug, PartialEq, Clone, Serialize)]
+    pub struct ParticipantPresentation {
+        participant_id: String,
+        name: String,
+        role: Role,
+        voted: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        vote: Option<Card>,
+    }
+
+    #[derive(Debug, PartialEq, Clone, Serialize)]
+    pub struct ObserverPresentation {
+        participant_id: String,
+        name: String,
+    }
+
+    impl From<(&String, &Participant)> for ObserverPresentation {
+        fn from((participant_id, participant): (&String, &Participant)) -> Self {
+            Self {
+                participant_id: participant_id.clone(),
+                name: participant.name.clone(),
+            }
+        }
+    }
+
+    #[derive(Debug, PartialEq, Clone, Serialize)]
+    #[serde(untagged)]
+    pub(crate) enum Card {
+        Number(u8),
+        Label(String),
+    }
+
+    impl From<&VoteValue> for Card {
+        fn from(value: &VoteValue) -> Self {
+            match value {
+                VoteValue::Number(number) => Card::Number(*number),
+                VoteValue::String(label) => Card::Label(label.clone()),
+            }
+        }
+    }
+
+    impl ParticipantPresentation {
+        fn new(participant_id: &str, participant: &Participant, revealed: bool) -> Self {
+            Self {
+                participant_id: participant_id.to_string(),
+                name: participant.name.clone(),
+                role: participant.role,
+                voted: participant.vote.is_some(),
+                vote: participant
+                    .vote
+                    .as_ref()
+                    .filter(|_| revealed)
+                    .map(Card::from),
+            }
+        }
     }
 
     impl PresentationOf for BoardPresentation {
         type Model = Board;
         fn from_model(model: &Self::Model) -> Self {
-            BoardPresentation::new(
-                model.participants.values().cloned().collect(),
+            let mut participants: Vec<(&String, &Participant)> =
+                model.participants.iter().collect();
+            participants
+                .sort_by_key(|(participant_id, participant)| (participant.joined, *participant_id));
+            let (observers, voters): (Vec<_>, Vec<_>) = participants
+                .into_iter()
+                .partition(|(_, participant)| participant.is_observer());
+            let mut presentation = BoardPresentation::new(
+                voters
+                    .into_iter()
+                    .map(|(participant_id, participant)| {
+                        (participant_id.clone(), participant.clone())
+                    })
+                    .collect(),
                 model.voting_complete,
-            )
+                model.votes_revealed,
+            );
+            presentation.observers = observers
+                .into_iter()
+                .map(ObserverPresentation::from)
+                .collect();
+            presentation.current_story = model.current_story().map(StoryPresentation::from);
+            presentation.story_queue = model.story_queue().map(StoryPresentation::from).collect();
+            presentation
         }
     }
 
-    fn stats(participants: Vec<Participant>) -> Option<Stats> {
-        let mut votes = participants
-            .iter()
-            .map(|p| p.vote)
-            .filter_map(|v| v)
-            .collect::<Vec<u8>>();
-
-        let votes = votes.iter().filter(|v| **v != 0);
-        let max = votes.clone().max().copied()?;
-        let min = votes.clone().min().copied()?;
-        let average = average(votes.copied())?;
-
-        Some(Stats {
-            average: average as usize,
-            max: max as usize,
-            min: min as usize,
-        })
+    fn stats(participants: Vec<Participant>) -> Option<VoteStats> {
+        VoteStats::from_votes(participants.iter().filter_map(|p| p.vote.as_ref()))
     }
 
-    fn average<'a>(votes: impl Iterator<Item = u8>) -> Option<u8> {
-        let mut votes = votes.collect::<Vec<u8>>();
-        if votes.len() == 0 {
-            None
-        } else {
-            votes.sort();
-            let middle = (votes.len() / 2);
-            Some(votes[middle])
+    impl BoardPresentation {
+        /// Whether the participant is on the board, voting or observing.
+        pub fn includes(&self, participant_id: &str) -> bool {
+            self.participants
+                .iter()
+                .map(|participant| &participant.participant_id)
+                .chain(
+                    self.observers
+                        .iter()
+                        .map(|observer| &observer.participant_id),
+                )
+                .any(|id| id == participant_id)
+        }
+
+        pub fn new(
+            participants: Vec<(String, Participant)>,
+            voting_complete: bool,
+            votes_revealed: bool,
+        ) -> Self {
+            Self {
+                participants: participants
+                    .iter()
+                    .map(|(participant_id, participant)| {
+                        ParticipantPresentation::new(participant_id, participant, votes_revealed)
+                    })
+                    .collect(),
+                stats: votes_revealed
+                    .then(|| participants.into_iter().map(|(_, p)| p).collect())
+                    .and_then(stats),
+                observers: Vec::new(),
+                voting_complete,
+                votes_revealed,
+                current_story: None,
+                story_queue: Vec::new(),
+            }
         }
     }
 
     #[cfg(test)]
     mod presentation_tests {
-        use crate::command::event::{BoardModifiedEvent, Vote, VoteValue};
-        use crate::query::presentation::BoardPresentation;
+        use crate::command::event::{BoardModifiedEvent, RevealPolicy, Role, VoteValue};
+        use crate::query::presentation::{
+            BoardPresentation, Card, ObserverPresentation, ParticipantPresentation,
+        };
         use crate::query::{Board, Participant};
         use std::collections::HashMap;
-        use util::entity::HandleEvent;
+        use util::entity::EventSourced;
         use util::query::PresentAs;
 
         #[test]
@@ -79,6 +186,9 @@ pub mod presentation {
                 participants: HashMap::new(),
                 voting_complete: false,
                 number_voted: 0,
+                votes_revealed: false,
+                reveal_policy: RevealPolicy::default(),
+                ..Board::default()
             };
 
             let presentation: BoardPresentation = board.present_as();
@@ -94,10 +204,12 @@ pub mod presentation {
             let participants = {
                 let mut map = HashMap::new();
                 for (i, participant) in vec![
-                    Participant::new("John".into()),
+                    Participant::new("John".into(), 0),
                     Participant {
                         name: "Jane".to_string(),
-                        vote: Some(1),
+                        vote: Some(VoteValue::Number(1)),
+                        role: Role::Voter,
+                        joined: 0,
                     },
                 ]
                 .into_iter()
@@ -108,30 +220,166 @@ pub mod presentation {
                 map
             };
 
-            let mut board = Board {
+            let board = Board {
                 participants,
                 voting_complete: false,
                 number_voted: 0,
+                votes_revealed: false,
+                reveal_policy: RevealPolicy::default(),
+                ..Board::default()
             };
             let presentation: BoardPresentation = board.present_as();
             assert!(presentation.stats.is_none());
         }
 
+        #[test]
+        fn it_should_only_show_that_a_participant_voted_until_votes_are_revealed() {
+            let mut participants = HashMap::new();
+            participants.insert(
+                "jane".to_string(),
+                Participant {
+                    name: "Jane".to_string(),
+                    vote: Some(VoteValue::String("XL".to_string())),
+                    role: Role::Voter,
+                    joined: 0,
+                },
+            );
+            let mut board = Board {
+                participants,
+                voting_complete: true,
+                number_voted: 1,
+                votes_revealed: false,
+                reveal_policy: RevealPolicy::Manual,
+                ..Board::default()
+            };
+
+            let presentation: BoardPresentation = board.present_as();
+            assert_eq!(
+                presentation.participants,
+                vec![ParticipantPresentation {
+                    participant_id: "jane".to_string(),
+                    name: "Jane".to_string(),
+                    role: Role::Voter,
+                    voted: true,
+                    vote: None,
+                }]
+            );
+
+            board.votes_revealed = true;
+            let presentation: BoardPresentation = board.present_as();
+            assert_eq!(
+                presentation.participants[0].vote,
+                Some(Card::Label("XL".to_string()))
+            );
+        }
+
+        #[test]
+        fn it_should_present_the_current_story_apart_from_the_queue() {
+            let board = Board::source(&[
+                BoardModifiedEvent::StoryAdded {
+                    story_id: "a".to_string(),
+                    title: "A".to_string(),
+                },
+                BoardModifiedEvent::StoryAdded {
+                    story_id: "b".to_string(),
+                    title: "B".to_string(),
+                },
+                BoardModifiedEvent::StoryAdded {
+                    story_id: "c".to_string(),
+                    title: "C".to_string(),
+                },
+                BoardModifiedEvent::EstimationStarted {
+                    story_id: "a".to_string(),
+                },
+                BoardModifiedEvent::EstimateRecorded {
+                    story_id: "a".to_string(),
+                    value: VoteValue::Number(3),
+                },
+                BoardModifiedEvent::EstimationStarted {
+                    story_id: "c".to_string(),
+                },
+            ]);
+
+            let presentation: BoardPresentation = board.present_as();
+            let current = presentation.current_story.unwrap();
+            assert_eq!(current.id, "c");
+            let queue: Vec<String> = presentation
+                .story_queue
+                .into_iter()
+                .map(|story| story.id)
+                .collect();
+            assert_eq!(queue, vec!["b".to_string()]);
+        }
+
+        #[test]
+        fn it_should_present_observers_apart_from_participants() {
+            let board = Board::source(&[
+                BoardModifiedEvent::ParticipantAdded {
+                    participant_id: "voter".to_string(),
+                    participant_name: "Voter".to_string(),
+                },
+                BoardModifiedEvent::ParticipantAdded {
+                    participant_id: "observer".to_string(),
+                    participant_name: "Observer".to_string(),
+                },
+                BoardModifiedEvent::ParticipantRoleAssigned {
+                    participant_id: "observer".to_string(),
+                    role: Role::Observer,
+                },
+            ]);
+
+            let presentation: BoardPresentation = board.present_as();
+            assert_eq!(presentation.participants.len(), 1);
+            assert_eq!(presentation.participants[0].name, "Voter");
+            assert_eq!(
+                presentation.observers,
+                vec![ObserverPresentation {
+                    participant_id: "observer".to_string(),
+                    name: "Observer".to_string(),
+                }]
+            );
+        }
+
+        #[test]
+        fn it_should_present_participants_in_the_order_they_joined() {
+            let names = ["Zoe", "Adam", "Mia", "Bob", "Lea", "Kai", "Eve", "Tom"];
+            let events: Vec<BoardModifiedEvent> = names
+                .iter()
+                .map(|name| BoardModifiedEvent::ParticipantAdded {
+                    participant_id: name.to_lowercase(),
+                    participant_name: name.to_string(),
+                })
+                .collect();
+            let board = Board::source(&events);
+
+            let presentation: BoardPresentation = board.present_as();
+            let ids: Vec<&str> = presentation
+                .participants
+                .iter()
+                .map(|participant| participant.participant_id.as_str())
+                .collect();
+            assert_eq!(
+                ids,
+                vec!["zoe", "adam", "mia", "bob", "lea", "kai", "eve", "tom"]
+            );
+        }
+
         mod stats {
             use super::super::stats;
+            use crate::command::event::VoteValue;
             use crate::query::Participant;
             #[test]
             fn it_should_ignore_0_votes() {
                 let mut participants = vec![
-                    Participant::new("John".into()),
-                    Participant::new("Jane".into()),
-                    Participant::new("Jack".into()),
-                    Participant::new("Jill".into()),
+                    Participant::new("John".into(), 0),
+                    Participant::new("Jane".into(), 0),
+                    Participant::new("Jack".into(), 0),
+                    Participant::new("Jill".into(), 0),
                 ];
-                participants[0].vote = Some(0);
-                participants[1].vote = Some(4);
-                participants[2].vote = Some(5);
-                participants[3].vote = Some(6);
+                participants[0].vote = Some(VoteValue::Number(0));
+                participants[1].vote = Some(VoteValue::Number(4));
+                participants[2].vote = Some(VoteValue::Number(5));
+                participants[3].vote = Some(VoteValue::Number(6));
                 let stats = stats(participants);
                 assert!(stats.is_some());
                 let stats = stats.unwrap();
@@ -149,7 +397,7 @@ pub mod presentation {
 
             #[test]
             fn it_should_return_none_when_no_votes() {
-                let participants = vec![Participant::new("John".into())];
+                let participants = vec![Participant::new("John".into(), 0)];
                 let stats = stats(participants);
                 assert_eq!(stats, None);
             }
@@ -157,12 +405,12 @@ pub mod presentation {
             #[test]
             fn it_should_ignore_non_voted_participants_in_calculation() {
                 let mut participants = vec![
-                    Participant::new("John".into()),
-                    Participant::new("Jane".into()),
-                    Participant::new("Jack".into()),
+                    Participant::new("John".into(), 0),
+                    Participant::new("Jane".into(), 0),
+                    Participant::new("Jack".into(), 0),
                 ];
-                participants[0].vote = Some(1);
-                participants[1].vote = Some(2);
+                participants[0].vote = Some(VoteValue::Number(1));
+                participants[1].vote = Some(VoteValue::Number(2));
                 let stats = stats(participants);
                 assert!(stats.is_some());
                 let stats = stats.unwrap();
@@ -174,13 +422,13 @@ pub mod presentation {
             #[test]
             fn it_should_return_some_stats_if_all_particpants_have_voted() {
                 let mut participants = vec![
-                    Participant::new("John".into()),
-                    Participant::new("Jane".into()),
-                    Participant::new("Jack".into()),
+                    Participant::new("John".into(), 0),
+                    Participant::new("Jane".into(), 0),
+                    Participant::new("Jack".into(), 0),
                 ];
-                participants[0].vote = Some(1);
-                participants[1].vote = Some(2);
-                participants[2].vote = Some(3);
+                participants[0].vote = Some(VoteValue::Number(1));
+                participants[1].vote = Some(VoteValue::Number(2));
+                participants[2].vote = Some(VoteValue::Number(3));
                 let stats = stats(participants);
                 assert!(stats.is_some());
                 let stats = stats.unwrap();
@@ -190,18 +438,6 @@ pub mod presentation {
             }
         }
     }
-
-    impl BoardPresentation {
-        pub fn new(participants: Vec<Participant>, voting_complete: bool) -> Self {
-            Self {
-                stats: voting_complete
-                    .then_some(participants.clone())
-                    .and_then(stats),
-                participants,
-                voting_complete,
-            }
-        }
-    }
 }
 
 #[derive(Default, Debug, PartialEq, Clone)]
@@ -209,6 +445,11 @@ pub struct Board {
     participants: HashMap<String, Participant>,
     voting_complete: bool,
     number_voted: usize,
+    votes_revealed: bool,
+    reveal_policy: RevealPolicy,
+    stories: Vec<Story>,
+    current_story: Option<String>,
+    joined: usize,
 }
 
 impl Board {
@@ -217,23 +458,74 @@ impl Board {
             participants: HashMap::new(),
             voting_complete: false,
             number_voted: 0,
+            votes_revealed: false,
+            reveal_policy: RevealPolicy::default(),
+            stories: Vec::new(),
+            current_story: None,
+            joined: 0,
+        }
+    }
+
+    fn current_story(&self) -> Option<&Story> {
+        self.current_story
+            .as_ref()
+            .and_then(|story_id| self.stories.iter().find(|story| story.id == *story_id))
+    }
+
+    fn story_queue(&self) -> impl Iterator<Item = &Story> {
+        self.stories.iter().filter(|story| {
+            story.estimate.is_none() && Some(&story.id) != self.current_story.as_ref()
+        })
+    }
+
+    fn voters(&self) -> impl Iterator<Item = &Participant> {
+        self.participants
+            .values()
+            .filter(|participant| !participant.is_observer())
+    }
+
+    fn update_voting_complete(&mut self) {
+        self.voting_complete = self.number_voted > 0 && self.number_voted == self.voters().count();
+        self.reveal_if_complete();
+    }
+
+    fn reveal_if_complete(&mut self) {
+        if self.voting_complete && self.reveal_policy == RevealPolicy::WhenAllVoted {
+            self.votes_revealed = true;
         }
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Serialize)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Participant {
     name: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    vote: Option<u8>,
+    vote: Option<VoteValue>,
+    role: Role,
+    joined: usize,
 }
 
 impl Participant {
-    pub fn new(name: String) -> Self {
-        Self { name, vote: None }
+    pub fn new(name: String, joined: usize) -> Self {
+        Self {
+            name,
+            vote: None,
+            role: Role::default(),
+            joined,
+        }
+    }
+
+    fn is_observer(&self) -> bool {
+        self.role == Role::Observer
     }
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub struct Story {
+    id: String,
+    title: String,
+    estimate: Option<VoteValue>,
+}
+
 impl HandleEvent for Board {
     type Event = BoardModifiedEvent;
 
@@ -243,7 +535,8 @@ impl HandleEvent for Board {
                 participant_id,
                 participant_name,
             } => {
-                let participant = Participant::new(participant_name.clone());
+                self.joined += 1;
+                let participant = Participant::new(participant_name.clone(), self.joined);
                 self.participants
                     .insert(participant_id.clone(), participant);
             }
@@ -254,8 +547,32 @@ impl HandleEvent for Board {
                         self.number_voted -= 1;
                     }
                 }
+                self.update_voting_complete();
             }
 
+            BoardModifiedEvent::ParticipantRoleAssigned {
+                participant_id,
+                role,
+            } => {
+                if let Some(participant) = self.participants.get_mut(participant_id) {
+                    participant.role = *role;
+                    if participant.is_observer() && participant.vote.take().is_some() {
+                        self.number_voted -= 1;
+                    }
+                }
+                self.update_voting_complete();
+            }
+            BoardModifiedEvent::ParticipantRenamed {
+                participant_id,
+                participant_name,
+            } => {
+                if let Some(participant) = self.participants.get_mut(participant_id) {
+                    participant.name = participant_name.clone();
+                }
+            }
+            BoardModifiedEvent::ParticipantNotRenamed { .. } => {}
+            BoardModifiedEvent::RoleNotAssigned { .. } => {}
+            BoardModifiedEvent::CommandRejected { .. } => {}
             BoardModifiedEvent::ParticipantCouldNotBeRemoved { .. } => {}
             BoardModifiedEvent::ParticipantVoted {
                 participant_id,
@@ -265,33 +582,58 @@ impl HandleEvent for Board {
                     if participant.vote.is_none() {
                         self.number_voted += 1;
                     }
-                    participant.vote = match vote.value {
-                        VoteValue::Number(number) => Some(number),
-                        VoteValue::String(_) => None,
-                    };
-                }
-
-                if self.number_voted == self.participants.len() {
-                    self.voting_complete = true;
+                    participant.vote = Some(vote.value.clone());
                 }
+                self.update_voting_complete();
             }
             BoardModifiedEvent::ParticipantCouldNotVote { .. } => {}
-            BoardModifiedEvent::VotesCleared => {
+            BoardModifiedEvent::VotesCleared | BoardModifiedEvent::RoundStarted { .. } => {
                 for participant in self.participants.values_mut() {
                     participant.vote = None;
                 }
                 self.number_voted = 0;
                 self.voting_complete = false;
+                self.votes_revealed = false;
+            }
+            BoardModifiedEvent::RoundClosed { .. } => {}
+            BoardModifiedEvent::VotesRevealed => {
+                self.votes_revealed = true;
+            }
+            BoardModifiedEvent::RevealPolicyChanged { policy } => {
+                self.reveal_policy = *policy;
+                self.reveal_if_complete();
             }
             BoardModifiedEvent::ParticipantNotAdded { .. } => {}
+            BoardModifiedEvent::StoryAdded { story_id, title } => {
+                self.stories.push(Story {
+                    id: story_id.clone(),
+                    title: title.clone(),
+                    estimate: None,
+                });
+            }
+            BoardModifiedEvent::StoryNotAdded { .. } => {}
+            BoardModifiedEvent::StoriesReordered { story_ids } => {
+                reorder_stories(&mut self.stories, story_ids, |story| &story.id);
+            }
+            BoardModifiedEvent::StoriesNotReordered { .. } => {}
+            BoardModifiedEvent::EstimationStarted { story_id } => {
+                self.current_story = Some(story_id.clone());
+            }
+            BoardModifiedEvent::EstimationNotStarted { .. } => {}
+            BoardModifiedEvent::EstimateRecorded { story_id, value } => {
+                if let Some(story) = self.stories.iter_mut().find(|story| story.id == *story_id) {
+                    story.estimate = Some(value.clone());
+                }
+            }
+            BoardModifiedEvent::EstimateNotRecorded { .. } => {}
         }
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
-    use crate::command::event;
     use crate::command::event::{ParticipantNotRemovedReason, ParticipantNotVotedReason, Vote};
     use util::entity::EventSourced;
 
@@ -336,7 +678,10 @@ mod tests {
         board.apply(&event);
         assert_eq!(board.participants.len(), 1);
         assert!(board.participants.get("test").unwrap().vote.is_some());
-        assert_eq!(board.participants.get("test").unwrap().vote.unwrap(), 1);
+        assert_eq!(
+            board.participants.get("test").unwrap().vote,
+            Some(VoteValue::Number(1))
+        );
     }
 
     #[test]
@@ -578,6 +923,72 @@ mod tests {
         assert_eq!(board.voting_complete, false);
     }
 
+    #[test]
+    fn it_should_reveal_votes_when_all_participants_have_voted_by_default() {
+        let board = Board::source(&[
+            BoardModifiedEvent::ParticipantAdded {
+                participant_id: "test".to_string(),
+                participant_name: "test".to_string(),
+            },
+            BoardModifiedEvent::ParticipantVoted {
+                participant_id: "test".to_string(),
+                vote: Vote::new("test".to_string(), VoteValue::Number(1)),
+            },
+        ]);
+
+        assert!(board.votes_revealed);
+    }
+
+    #[test]
+    fn it_should_wait_for_votes_revealed_when_reveal_policy_is_manual() {
+        let mut board = Board::source(&[
+            BoardModifiedEvent::RevealPolicyChanged {
+                policy: RevealPolicy::Manual,
+            },
+            BoardModifiedEvent::ParticipantAdded {
+                participant_id: "test".to_string(),
+                participant_name: "test".to_string(),
+            },
+            BoardModifiedEvent::ParticipantVoted {
+                participant_id: "test".to_string(),
+                vote: Vote::new("test".to_string(), VoteValue::Number(1)),
+            },
+        ]);
+
+        assert!(board.voting_complete);
+        assert!(!board.votes_revealed);
+
+        board.apply(&BoardModifiedEvent::VotesRevealed);
+        assert!(board.votes_revealed);
+
+        board.apply(&BoardModifiedEvent::VotesCleared);
+        assert!(!board.votes_revealed);
+    }
+
+    #[test]
+    fn it_should_clear_votes_when_a_new_round_starts() {
+        let board = Board::source(&[
+            BoardModifiedEvent::ParticipantAdded {
+                participant_id: "test".to_string(),
+                participant_name: "test".to_string(),
+            },
+            BoardModifiedEvent::ParticipantVoted {
+                participant_id: "test".to_string(),
+                vote: Vote::new("test".to_string(), VoteValue::Number(1)),
+            },
+            BoardModifiedEvent::RoundClosed {
+                round: 1,
+                votes: vec![],
+                stats: None,
+            },
+            BoardModifiedEvent::RoundStarted { round: 2 },
+        ]);
+
+        assert!(board.participants.get("test").unwrap().vote.is_none());
+        assert!(!board.voting_complete);
+        assert!(!board.votes_revealed);
+    }
+
     #[test]
     fn it_should_not_complete_if_participant_is_removed_after_voting_another_is_added_and_votes() {
         let mut board = Board::default();
@@ -620,4 +1031,67 @@ mod tests {
 
         assert_eq!(board.voting_complete, true);
     }
+
+    #[test]
+    fn it_should_rename_a_participant() {
+        let board = Board::source(&[
+            BoardModifiedEvent::ParticipantAdded {
+                participant_id: "test".to_string(),
+                participant_name: "test".to_string(),
+            },
+            BoardModifiedEvent::ParticipantRenamed {
+                participant_id: "test".to_string(),
+                participant_name: "renamed".to_string(),
+            },
+        ]);
+        assert_eq!(board.participants.get("test").unwrap().name, "renamed");
+    }
+
+    #[test]
+    fn it_should_complete_voting_without_waiting_for_observers() {
+        let board = Board::source(&[
+            BoardModifiedEvent::ParticipantAdded {
+                participant_id: "voter".to_string(),
+                participant_name: "voter".to_string(),
+            },
+            BoardModifiedEvent::ParticipantAdded {
+                participant_id: "observer".to_string(),
+                participant_name: "observer".to_string(),
+            },
+            BoardModifiedEvent::ParticipantRoleAssigned {
+                participant_id: "observer".to_string(),
+                role: Role::Observer,
+            },
+            BoardModifiedEvent::ParticipantVoted {
+                participant_id: "voter".to_string(),
+                vote: Vote::new("test".to_string(), VoteValue::Number(1)),
+            },
+        ]);
+
+        assert!(board.voting_complete);
+    }
+
+    #[test]
+    fn it_should_complete_voting_when_the_last_non_voter_becomes_an_observer() {
+        let board = Board::source(&[
+            BoardModifiedEvent::ParticipantAdded {
+                participant_id: "voter".to_string(),
+                participant_name: "voter".to_string(),
+            },
+            BoardModifiedEvent::ParticipantAdded {
+                participant_id: "other".to_string(),
+                participant_name: "other".to_string(),
+            },
+            BoardModifiedEvent::ParticipantVoted {
+                participant_id: "voter".to_string(),
+                vote: Vote::new("test".to_string(), VoteValue::Number(1)),
+            },
+            BoardModifiedEvent::ParticipantRoleAssigned {
+                participant_id: "other".to_string(),
+                role: Role::Observer,
+            },
+        ]);
+
+        assert!(board.voting_complete);
+    }
 }
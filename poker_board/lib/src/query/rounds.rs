@@ -0,0 +1,142 @@
+use crate::command::event::{BoardModifiedEvent, RoundVote, VoteStats};
+use util::entity::HandleEvent;
+
+pub mod presentation {
+    use crate::command::event::VoteStats;
+    use crate::query::presentation::Card;
+    use crate::query::rounds::{Round, RoundHistory};
+    use serde::Serialize;
+    use util::query::PresentationOf;
+
+    #[derive(Default, Debug, PartialEq, Clone, Serialize)]
+    pub struct RoundHistoryPresentation {
+        rounds: Vec<RoundPresentation>,
+    }
+
+    #[derive(Debug, PartialEq, Clone, Serialize)]
+    pub struct RoundPresentation {
+        round: usize,
+        votes: Vec<RoundVotePresentation>,
+        #[serde(flatten, skip_serializing_if = "Option::is_none")]
+        stats: Option<VoteStats>,
+    }
+
+    #[derive(Debug, PartialEq, Clone, Serialize)]
+    pub struct RoundVotePresentation {
+        name: String,
+        vote: Card,
+    }
+
+    impl From<&Round> for RoundPresentation {
+        fn from(round: &Round) -> Self {
+            Self {
+                round: round.round,
+                votes: round
+                    .votes
+                    .iter()
+                    .map(|round_vote| RoundVotePresentation {
+                        name: round_vote.participant_name.clone(),
+                        vote: Card::from(&round_vote.vote.value),
+                    })
+                    .collect(),
+                stats: round.stats.clone(),
+            }
+        }
+    }
+
+    impl PresentationOf for RoundHistoryPresentation {
+        type Model = RoundHistory;
+        fn from_model(model: &Self::Model) -> Self {
+            Self {
+                rounds: model.rounds.iter().map(RoundPresentation::from).collect(),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::command::event::{BoardModifiedEvent, RoundVote, Vote, VoteValue};
+        use util::entity::EventSourced;
+        use util::query::PresentAs;
+
+        #[test]
+        fn it_should_present_each_vote_by_participant_name() {
+            let history = RoundHistory::source(&[BoardModifiedEvent::RoundClosed {
+                round: 1,
+                votes: vec![RoundVote {
+                    participant_id: "a".to_string(),
+                    participant_name: "Alice".to_string(),
+                    vote: Vote::new("1".to_string(), VoteValue::Number(5)),
+                }],
+                stats: None,
+            }]);
+
+            let presentation: RoundHistoryPresentation = history.present_as();
+            assert_eq!(
+                presentation.rounds[0].votes,
+                vec![RoundVotePresentation {
+                    name: "Alice".to_string(),
+                    vote: Card::Number(5),
+                }]
+            );
+        }
+    }
+}
+
+#[derive(Default, Debug, PartialEq, Clone)]
+pub struct RoundHistory {
+    rounds: Vec<Round>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Round {
+    round: usize,
+    votes: Vec<RoundVote>,
+    stats: Option<VoteStats>,
+}
+
+impl HandleEvent for RoundHistory {
+    type Event = BoardModifiedEvent;
+
+    fn apply(&mut self, event: &Self::Event) {
+        if let BoardModifiedEvent::RoundClosed {
+            round,
+            votes,
+            stats,
+        } = event
+        {
+            self.rounds.push(Round {
+                round: *round,
+                votes: votes.clone(),
+                stats: stats.clone(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::entity::EventSourced;
+
+    #[test]
+    fn it_should_record_each_closed_round_in_order() {
+        let history = RoundHistory::source(&[
+            BoardModifiedEvent::RoundClosed {
+                round: 1,
+                votes: vec![],
+                stats: None,
+            },
+            BoardModifiedEvent::RoundStarted { round: 2 },
+            BoardModifiedEvent::RoundClosed {
+                round: 2,
+                votes: vec![],
+                stats: None,
+            },
+        ]);
+
+        let rounds: Vec<usize> = history.rounds.iter().map(|r| r.round).collect();
+        assert_eq!(rounds, vec![1, 2]);
+    }
+}
@@ -0,0 +1,452 @@
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use util::envelope::{decode, encode, Envelope, Metadata};
+use util::schema::Schema;
+use util::store::{Conflict, LoadEntity, SaveEntity, Tail};
+
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum StoreError {
+    CouldNotLockConnection,
+}
+
+impl std::error::Error for StoreError {}
+
+impl Display for StoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreError::CouldNotLockConnection => write!(f, "Could not lock sqlite connection"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Database {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl Database {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(connection: Connection) -> Result<Self, Error> {
+        connection.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS events (
+                 stream TEXT NOT NULL,
+                 key TEXT NOT NULL,
+                 position INTEGER NOT NULL,
+                 payload TEXT NOT NULL,
+                 PRIMARY KEY (stream, key, position)
+             );",
+        )?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    pub fn store<T>(&self, stream: &str) -> SqliteEventStore<T> {
+        SqliteEventStore {
+            database: self.clone(),
+            stream: stream.to_string(),
+            event: PhantomData,
+        }
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Connection>, Error> {
+        self.connection
+            .lock()
+            .map_err(|_| StoreError::CouldNotLockConnection.into())
+    }
+
+    /// Runs `work` against the connection on the blocking pool, so waiting on the lock or
+    /// the disk never holds up an async worker.
+    async fn with_connection<R, F>(&self, work: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut Connection) -> Result<R, Error> + Send + 'static,
+        R: Send + 'static,
+    {
+        let database = self.clone();
+        tokio::task::spawn_blocking(move || work(&mut *database.lock()?)).await?
+    }
+}
+
+/// An event stream per key, kept in one table and ordered by position within each
+/// `(stream, key)`, with each payload an [`Envelope`]. Saving a vector appends only the events
+/// past what is already stored. A versioned save fails with [`Conflict`] when the stream has
+/// moved past the expected version.
+#[derive(Clone)]
+pub struct SqliteEventStore<T> {
+    database: Database,
+    stream: String,
+    event: PhantomData<fn() -> T>,
+}
+
+/// How many events the `(stream, key)` holds.
+fn stored(connection: &Connection, stream: &str, key: &str) -> Result<usize, Error> {
+    Ok(connection
+        .query_row(
+            "SELECT MAX(position) + 1 FROM events WHERE stream = ?1 AND key = ?2",
+            params![stream, key],
+            |row| row.get::<_, Option<usize>>(0),
+        )
+        .optional()?
+        .flatten()
+        .unwrap_or(0))
+}
+
+impl<T> SqliteEventStore<T>
+where
+    T: Schema + Send + 'static,
+{
+    /// Loads the envelopes from position `from` on, only reading and decoding those rows,
+    /// along with where they start.
+    async fn load_envelopes(
+        &self,
+        key: &str,
+        from: usize,
+    ) -> Result<Option<Tail<Vec<Envelope<T>>>>, Error> {
+        let stream = self.stream.clone();
+        let key = key.to_string();
+        self.database
+            .with_connection(move |connection| {
+                let stored = stored(connection, &stream, &key)?;
+                if stored == 0 {
+                    return Ok(None);
+                }
+                let from = from.min(stored);
+
+                let mut statement = connection.prepare_cached(
+                    "SELECT position, payload FROM events WHERE stream = ?1 AND key = ?2 AND position >= ?3 ORDER BY position",
+                )?;
+                let rows = statement
+                    .query_map(params![stream, key, from], |row| {
+                        Ok((row.get::<_, usize>(0)?, row.get::<_, String>(1)?))
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                rows.iter()
+                    .map(|(position, payload)| decode(payload, *position))
+                    .collect::<Result<Vec<_>, Error>>()
+                    .map(|envelopes| Some(Tail::of_stream(from, envelopes)))
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl<T> LoadEntity<Vec<T>> for SqliteEventStore<T>
+where
+    T: Schema + Send + 'static,
+{
+    type Key = String;
+    type Error = Error;
+
+    async fn load(&self, key: &Self::Key) -> Result<Option<Vec<T>>, Self::Error> {
+        Ok(self
+            .load_from(key, &[])
+            .await?
+            .map(|tail: Tail<Vec<T>>| tail.entity))
+    }
+
+    async fn load_from(
+        &self,
+        key: &Self::Key,
+        from: &[usize],
+    ) -> Result<Option<Tail<Vec<T>>>, Self::Error> {
+        let from = from.first().copied().unwrap_or(0);
+        Ok(self
+            .load_envelopes(key, from)
+            .await?
+            .map(|Tail { from, entity }| Tail {
+                from,
+                entity: entity.into_iter().map(|envelope| envelope.event).collect(),
+            }))
+    }
+}
+
+#[async_trait]
+impl<T> LoadEntity<Vec<Envelope<T>>> for SqliteEventStore<T>
+where
+    T: Schema + Send + 'static,
+{
+    type Key = String;
+    type Error = Error;
+
+    async fn load(&self, key: &Self::Key) -> Result<Option<Vec<Envelope<T>>>, Self::Error> {
+        Ok(self.load_envelopes(key, 0).await?.map(|tail| tail.entity))
+    }
+
+    async fn load_from(
+        &self,
+        key: &Self::Key,
+        from: &[usize],
+    ) -> Result<Option<Tail<Vec<Envelope<T>>>>, Self::Error> {
+        self.load_envelopes(key, from.first().copied().unwrap_or(0))
+            .await
+    }
+}
+
+impl<T> SqliteEventStore<T>
+where
+    T: Schema + Serialize + Clone + Send + 'static,
+{
+    async fn append(
+        &self,
+        key: &str,
+        tail: Tail<Vec<T>>,
+        expected_version: Option<usize>,
+        metadata: &Metadata,
+    ) -> Result<Tail<Vec<T>>, Error> {
+        let stream = self.stream.clone();
+        let key = key.to_string();
+        let metadata = metadata.clone();
+        self.database
+            .with_connection(move |connection| {
+                let transaction = connection.transaction()?;
+                let stored = stored(&transaction, &stream, &key)?;
+                if let Some(expected_version) = expected_version {
+                    Conflict::check(expected_version, stored)?;
+                }
+
+                {
+                    let mut insert = transaction.prepare_cached(
+                        "INSERT INTO events (stream, key, position, payload) VALUES (?1, ?2, ?3, ?4)",
+                    )?;
+                    let appended = tail.after(stored).to_vec();
+                    for envelope in Envelope::wrap(stored, appended, &metadata) {
+                        insert.execute(params![
+                            stream,
+                            key,
+                            envelope.sequence,
+                            encode(&envelope)?
+                        ])?;
+                    }
+                }
+
+                transaction.commit()?;
+                Ok(tail)
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl<T> SaveEntity<Vec<T>> for SqliteEventStore<T>
+where
+    T: Schema + Serialize + Clone + Send + 'static,
+{
+    type Key = String;
+    type Error = Error;
+
+    async fn save(&self, key: &Self::Key, entity: Vec<T>) -> Result<Vec<T>, Self::Error> {
+        self.append(
+            key,
+            Tail::whole(entity),
+            None,
+            &Metadata::new().recorded_now(),
+        )
+        .await
+        .map(|tail| tail.entity)
+    }
+
+    async fn save_versioned(
+        &self,
+        key: &Self::Key,
+        entity: Vec<T>,
+        expected_version: usize,
+        metadata: &Metadata,
+    ) -> Result<Vec<T>, Self::Error> {
+        self.save_tail(key, Tail::whole(entity), expected_version, metadata)
+            .await
+            .map(|tail| tail.entity)
+    }
+
+    async fn save_tail(
+        &self,
+        key: &Self::Key,
+        tail: Tail<Vec<T>>,
+        expected_version: usize,
+        metadata: &Metadata,
+    ) -> Result<Tail<Vec<T>>, Self::Error> {
+        self.append(key, tail, Some(expected_version), metadata)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    enum TestEvent {
+        Happened(u8),
+    }
+
+    impl Schema for TestEvent {
+        const VERSION: u32 = 1;
+    }
+
+    async fn stored(store: &SqliteEventStore<TestEvent>, key: &String) -> Option<Vec<TestEvent>> {
+        store.load(key).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn it_should_load_nothing_for_an_unknown_key() {
+        let store = Database::open_in_memory()
+            .unwrap()
+            .store::<TestEvent>("test");
+        assert_eq!(stored(&store, &"key".to_string()).await, None);
+    }
+
+    #[tokio::test]
+    async fn it_should_load_saved_events_in_order() {
+        let store = Database::open_in_memory()
+            .unwrap()
+            .store::<TestEvent>("test");
+        let key = "key".to_string();
+        let events = vec![TestEvent::Happened(2), TestEvent::Happened(1)];
+        store.save(&key, events.clone()).await.unwrap();
+        assert_eq!(stored(&store, &key).await, Some(events));
+    }
+
+    #[tokio::test]
+    async fn it_should_only_append_events_that_are_not_stored_yet() {
+        let store = Database::open_in_memory()
+            .unwrap()
+            .store::<TestEvent>("test");
+        let key = "key".to_string();
+        store
+            .save(&key, vec![TestEvent::Happened(1)])
+            .await
+            .unwrap();
+        store
+            .save(&key, vec![TestEvent::Happened(1), TestEvent::Happened(2)])
+            .await
+            .unwrap();
+        assert_eq!(
+            stored(&store, &key).await,
+            Some(vec![TestEvent::Happened(1), TestEvent::Happened(2)])
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_reject_a_save_expecting_an_older_version() {
+        let store = Database::open_in_memory()
+            .unwrap()
+            .store::<TestEvent>("test");
+        let key = "key".to_string();
+        store
+            .save_versioned(&key, vec![TestEvent::Happened(1)], 0, &Metadata::default())
+            .await
+            .unwrap();
+
+        let error = store
+            .save_versioned(&key, vec![TestEvent::Happened(2)], 0, &Metadata::default())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<Conflict>(),
+            Some(&Conflict {
+                expected_version: 0,
+                actual_version: 1
+            })
+        );
+        assert_eq!(
+            stored(&store, &key).await,
+            Some(vec![TestEvent::Happened(1)])
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_not_read_the_rows_before_the_position_loaded_from() {
+        let database = Database::open_in_memory().unwrap();
+        database
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO events (stream, key, position, payload) VALUES ('test', 'key', 0, 'not json')",
+                [],
+            )
+            .unwrap();
+        let store = database.store::<TestEvent>("test");
+        let key = "key".to_string();
+        store
+            .save_tail(
+                &key,
+                Tail::of_stream(1, vec![TestEvent::Happened(2)]),
+                1,
+                &Metadata::default(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.load_from(&key, &[1]).await.unwrap(),
+            Some(Tail::of_stream(1, vec![TestEvent::Happened(2)]))
+        );
+        assert_eq!(
+            store.load_from(&key, &[5]).await.unwrap(),
+            Some(Tail::of_stream(2, Vec::<TestEvent>::new()))
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_keep_streams_apart() {
+        let database = Database::open_in_memory().unwrap();
+        let first = database.store::<TestEvent>("first");
+        let second = database.store::<TestEvent>("second");
+        let key = "key".to_string();
+        first
+            .save(&key, vec![TestEvent::Happened(1)])
+            .await
+            .unwrap();
+        assert_eq!(stored(&second, &key).await, None);
+    }
+
+    #[tokio::test]
+    async fn it_should_keep_the_metadata_of_new_events_next_to_bare_ones() {
+        let database = Database::open_in_memory().unwrap();
+        database
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO events (stream, key, position, payload) VALUES ('test', 'key', 0, ?1)",
+                params![serde_json::to_string(&TestEvent::Happened(1)).unwrap()],
+            )
+            .unwrap();
+        let store = database.store::<TestEvent>("test");
+        let key = "key".to_string();
+        let metadata = Metadata::new().with_actor_id("actor").recorded_now();
+        store
+            .save_versioned(
+                &key,
+                vec![TestEvent::Happened(1), TestEvent::Happened(2)],
+                1,
+                &metadata,
+            )
+            .await
+            .unwrap();
+
+        let envelopes: Option<Vec<Envelope<TestEvent>>> = store.load(&key).await.unwrap();
+        assert_eq!(
+            envelopes,
+            Some(vec![
+                Envelope::new(0, &Metadata::default(), TestEvent::Happened(1)),
+                Envelope::new(1, &metadata, TestEvent::Happened(2))
+            ])
+        );
+    }
+}
@@ -1,6 +1,9 @@
 use std::fmt::Display;
 
 mod message;
+pub mod patch;
+pub mod presence;
+pub mod protocol;
 pub mod sidecar;
 pub mod store;
 pub mod websocket;
@@ -0,0 +1,157 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// A JSON Patch (RFC 6902) operation.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+}
+
+/// The operations that turn `from` into `to`. Arrays are compared index by index, so an
+/// insertion in the middle of one replaces every element after it.
+pub fn diff(from: &Value, to: &Value) -> Vec<PatchOperation> {
+    let mut operations = Vec::new();
+    diff_at("", from, to, &mut operations);
+    operations
+}
+
+fn pointer(path: &str, token: &str) -> String {
+    format!("{}/{}", path, token.replace('~', "~0").replace('/', "~1"))
+}
+
+fn diff_at(path: &str, from: &Value, to: &Value, operations: &mut Vec<PatchOperation>) {
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => diff_objects(path, from, to, operations),
+        (Value::Array(from), Value::Array(to)) => diff_arrays(path, from, to, operations),
+        (from, to) if from != to => operations.push(PatchOperation::Replace {
+            path: path.to_string(),
+            value: to.clone(),
+        }),
+        _ => {}
+    }
+}
+
+fn diff_objects(
+    path: &str,
+    from: &Map<String, Value>,
+    to: &Map<String, Value>,
+    operations: &mut Vec<PatchOperation>,
+) {
+    for key in from.keys().filter(|key| !to.contains_key(*key)) {
+        operations.push(PatchOperation::Remove {
+            path: pointer(path, key),
+        });
+    }
+    for (key, value) in to {
+        match from.get(key) {
+            Some(previous) => diff_at(&pointer(path, key), previous, value, operations),
+            None => operations.push(PatchOperation::Add {
+                path: pointer(path, key),
+                value: value.clone(),
+            }),
+        }
+    }
+}
+
+fn diff_arrays(path: &str, from: &[Value], to: &[Value], operations: &mut Vec<PatchOperation>) {
+    for (index, (previous, value)) in from.iter().zip(to).enumerate() {
+        diff_at(
+            &pointer(path, &index.to_string()),
+            previous,
+            value,
+            operations,
+        );
+    }
+    // Removing from the end keeps the indices of the remaining elements valid.
+    for index in (to.len()..from.len()).rev() {
+        operations.push(PatchOperation::Remove {
+            path: pointer(path, &index.to_string()),
+        });
+    }
+    for (index, value) in to.iter().enumerate().skip(from.len()) {
+        operations.push(PatchOperation::Add {
+            path: pointer(path, &index.to_string()),
+            value: value.clone(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parent<'a>(document: &'a mut Value, path: &str) -> (&'a mut Value, String) {
+        let (parent, token) = path.rsplit_once('/').unwrap();
+        let token = token.replace("~1", "/").replace("~0", "~");
+        (document.pointer_mut(parent).unwrap(), token)
+    }
+
+    fn apply(mut document: Value, operations: &[PatchOperation]) -> Value {
+        for operation in operations {
+            match operation {
+                PatchOperation::Replace { path, value } => {
+                    *document.pointer_mut(path).unwrap() = value.clone();
+                }
+                PatchOperation::Add { path, value } => match parent(&mut document, path) {
+                    (Value::Object(object), key) => {
+                        object.insert(key, value.clone());
+                    }
+                    (Value::Array(array), index) => {
+                        array.insert(index.parse().unwrap(), value.clone());
+                    }
+                    _ => panic!("cannot add at {}", path),
+                },
+                PatchOperation::Remove { path } => match parent(&mut document, path) {
+                    (Value::Object(object), key) => {
+                        object.remove(&key);
+                    }
+                    (Value::Array(array), index) => {
+                        array.remove(index.parse().unwrap());
+                    }
+                    _ => panic!("cannot remove at {}", path),
+                },
+            }
+        }
+        document
+    }
+
+    #[test]
+    fn it_should_only_describe_what_changed() {
+        let from =
+            json!({"votes_revealed": false, "participants": [{"name": "a", "voted": false}]});
+        let to = json!({"votes_revealed": false, "participants": [{"name": "a", "voted": true}]});
+
+        assert_eq!(
+            diff(&from, &to),
+            vec![PatchOperation::Replace {
+                path: "/participants/0/voted".to_string(),
+                value: json!(true)
+            }]
+        );
+        assert_eq!(diff(&to, &to), vec![]);
+    }
+
+    #[test]
+    fn it_should_produce_patches_that_turn_one_document_into_the_other() {
+        let from = json!({"a/b": 1, "gone": true, "list": [1, 2, 3], "nested": {"x": [4]}});
+        let to = json!({"a/b": 2, "added": null, "list": [1], "nested": {"x": [4, 5, 6]}});
+
+        assert_eq!(apply(from.clone(), &diff(&from, &to)), to);
+        assert_eq!(apply(to.clone(), &diff(&to, &from)), from);
+    }
+
+    #[test]
+    fn it_should_serialize_as_json_patch() {
+        let operation = PatchOperation::Remove {
+            path: "/a~1b".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_value(operation).unwrap(),
+            json!({"op": "remove", "path": "/a~1b"})
+        );
+    }
+}
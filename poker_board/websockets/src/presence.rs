@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub const GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Connections {
+    open: usize,
+    generation: u64,
+}
+
+#[derive(Debug, Default)]
+struct Participants {
+    connections: HashMap<(String, String), Connections>,
+    /// Counts connections across all participants, so a participant who left and came
+    /// back is not mistaken for one who disconnected before they left.
+    generation: u64,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Presence {
+    participants: Arc<Mutex<Participants>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Disconnected {
+    generation: u64,
+}
+
+impl Presence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn connect(&self, board_id: &str, participant_id: &str) {
+        let mut participants = self.participants.lock().unwrap();
+        participants.generation += 1;
+        let generation = participants.generation;
+        let entry = participants
+            .connections
+            .entry((board_id.to_string(), participant_id.to_string()))
+            .or_default();
+        entry.open += 1;
+        entry.generation = generation;
+    }
+
+    pub fn disconnect(&self, board_id: &str, participant_id: &str) -> Disconnected {
+        let mut participants = self.participants.lock().unwrap();
+        let entry = participants
+            .connections
+            .entry((board_id.to_string(), participant_id.to_string()))
+            .or_default();
+        entry.open = entry.open.saturating_sub(1);
+        Disconnected {
+            generation: entry.generation,
+        }
+    }
+
+    /// Forgets a participant who is no longer on the board, because they left or were
+    /// kicked, so that nothing waits on their connections any more.
+    pub fn leave(&self, board_id: &str, participant_id: &str) {
+        let mut participants = self.participants.lock().unwrap();
+        participants
+            .connections
+            .remove(&(board_id.to_string(), participant_id.to_string()));
+    }
+
+    pub fn has_left(
+        &self,
+        board_id: &str,
+        participant_id: &str,
+        disconnected: Disconnected,
+    ) -> bool {
+        let mut participants = self.participants.lock().unwrap();
+        let key = (board_id.to_string(), participant_id.to_string());
+        match participants.connections.get(&key) {
+            Some(entry) if entry.open == 0 && entry.generation == disconnected.generation => {
+                participants.connections.remove(&key);
+                true
+            }
+            Some(_) => false,
+            None => true,
+        }
+    }
+
+    #[cfg(test)]
+    fn is_tracking(&self, board_id: &str, participant_id: &str) -> bool {
+        self.participants
+            .lock()
+            .unwrap()
+            .connections
+            .contains_key(&(board_id.to_string(), participant_id.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_report_a_participant_that_did_not_reconnect_as_left() {
+        let presence = Presence::new();
+        presence.connect("board", "participant");
+        let disconnected = presence.disconnect("board", "participant");
+        assert!(presence.has_left("board", "participant", disconnected));
+    }
+
+    #[test]
+    fn it_should_not_report_a_participant_that_reconnected_as_left() {
+        let presence = Presence::new();
+        presence.connect("board", "participant");
+        let disconnected = presence.disconnect("board", "participant");
+        presence.connect("board", "participant");
+        assert!(!presence.has_left("board", "participant", disconnected));
+    }
+
+    #[test]
+    fn it_should_only_honour_the_latest_disconnect() {
+        let presence = Presence::new();
+        presence.connect("board", "participant");
+        let first = presence.disconnect("board", "participant");
+        presence.connect("board", "participant");
+        let second = presence.disconnect("board", "participant");
+        assert!(!presence.has_left("board", "participant", first));
+        assert!(presence.has_left("board", "participant", second));
+    }
+
+    #[test]
+    fn it_should_forget_a_participant_that_left() {
+        let presence = Presence::new();
+        presence.connect("board", "participant");
+        let before_leaving = presence.disconnect("board", "participant");
+        presence.leave("board", "participant");
+        assert!(!presence.is_tracking("board", "participant"));
+
+        presence.connect("board", "participant");
+        presence.disconnect("board", "participant");
+        assert!(!presence.has_left("board", "participant", before_leaving));
+    }
+}
@@ -0,0 +1,220 @@
+use poker_board::command;
+use poker_board::command::event::{RevealPolicy, Role, VoteValidation, VoteValue};
+use poker_board::command::BoardCommand;
+use serde::Deserialize;
+
+pub const PROTOCOL_VERSION: u32 = 1;
+
+fn default_version() -> u32 {
+    PROTOCOL_VERSION
+}
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct Command {
+    #[serde(default = "default_version")]
+    pub version: u32,
+    #[serde(default)]
+    pub command_id: Option<String>,
+    #[serde(flatten)]
+    pub command: WsCommand,
+}
+
+impl Command {
+    pub fn is_supported(&self) -> bool {
+        self.version == PROTOCOL_VERSION
+    }
+}
+
+/// How a connection receives board updates.
+#[derive(Clone, Copy, Deserialize, Debug, Default, PartialEq)]
+pub enum UpdateMode {
+    /// The whole presentation on every change.
+    #[default]
+    Full,
+    /// A snapshot first, then JSON patches against the previous version.
+    Delta,
+}
+
+/// Keeps a connection in [`UpdateMode::Delta`] in step with the server.
+#[derive(Clone, Deserialize, Debug, PartialEq)]
+pub enum SyncMessage {
+    Acknowledged { version: usize },
+    ResyncRequested,
+}
+
+#[derive(Clone, Deserialize, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum WsVote {
+    Number(u8),
+    Label(String),
+}
+
+impl From<WsVote> for VoteValue {
+    fn from(vote: WsVote) -> Self {
+        match vote {
+            WsVote::Number(number) => VoteValue::Number(number),
+            WsVote::Label(label) => VoteValue::String(label),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Debug, PartialEq)]
+pub enum WsCommand {
+    ParticipantVoted {
+        vote: WsVote,
+        #[serde(default)]
+        vote_type: Option<String>,
+    },
+    ParticipantRenamed {
+        name: String,
+    },
+    ParticipantLeft,
+    ParticipantKicked {
+        participant_id: String,
+    },
+    RoleAssigned {
+        participant_id: String,
+        role: Role,
+    },
+    VotesCleared,
+    VotesRevealed,
+    RevealPolicySet {
+        policy: RevealPolicy,
+    },
+    VoteTypeAdded {
+        vote_type_id: String,
+        vote_validation: VoteValidation,
+    },
+    VoteTypeRemoved {
+        vote_type_id: String,
+    },
+    DefaultVoteTypeSet {
+        vote_type_id: String,
+    },
+    StoryAdded {
+        title: String,
+        #[serde(default)]
+        story_id: Option<String>,
+    },
+    StoriesReordered {
+        story_ids: Vec<String>,
+    },
+    EstimationStarted {
+        story_id: String,
+    },
+    EstimateRecorded {
+        story_id: String,
+        value: WsVote,
+    },
+}
+
+impl WsCommand {
+    pub fn into_board_command(self, participant_id: &str) -> BoardCommand {
+        let issued_by = participant_id.to_string();
+        match self {
+            WsCommand::ParticipantVoted { vote, vote_type } => {
+                command::vote(vote.into(), vote_type, issued_by)
+            }
+            WsCommand::ParticipantRenamed { name } => command::rename_participant(issued_by, name),
+            WsCommand::ParticipantLeft => command::remove_participant(issued_by),
+            WsCommand::ParticipantKicked { participant_id } => {
+                command::kick_participant(participant_id, issued_by)
+            }
+            WsCommand::RoleAssigned {
+                participant_id,
+                role,
+            } => command::assign_role(participant_id, role, issued_by),
+            WsCommand::VotesCleared => command::clear_votes(issued_by),
+            WsCommand::VotesRevealed => command::reveal_votes(issued_by),
+            WsCommand::RevealPolicySet { policy } => command::set_reveal_policy(policy, issued_by),
+            WsCommand::VoteTypeAdded {
+                vote_type_id,
+                vote_validation,
+            } => command::add_vote_type(vote_type_id, vote_validation, issued_by),
+            WsCommand::VoteTypeRemoved { vote_type_id } => {
+                command::remove_vote_type(vote_type_id, issued_by)
+            }
+            WsCommand::DefaultVoteTypeSet { vote_type_id } => {
+                command::set_default_vote_type(vote_type_id, issued_by)
+            }
+            WsCommand::StoryAdded { title, story_id } => {
+                command::add_story(title, story_id, issued_by)
+            }
+            WsCommand::StoriesReordered { story_ids } => {
+                command::reorder_stories(story_ids, issued_by)
+            }
+            WsCommand::EstimationStarted { story_id } => {
+                command::start_estimating(story_id, issued_by)
+            }
+            WsCommand::EstimateRecorded { story_id, value } => {
+                command::record_estimate(story_id, value.into(), issued_by)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_accept_unversioned_numeric_votes() {
+        let command: Command = serde_json::from_str(r#"{"ParticipantVoted":{"vote":3}}"#).unwrap();
+        assert!(command.is_supported());
+        assert_eq!(
+            command.command,
+            WsCommand::ParticipantVoted {
+                vote: WsVote::Number(3),
+                vote_type: None,
+            }
+        );
+    }
+
+    #[test]
+    fn it_should_accept_labelled_votes_with_a_vote_type() {
+        let command: Command = serde_json::from_str(
+            r#"{"version":1,"ParticipantVoted":{"vote":"XL","vote_type":"t-shirt"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            command.command,
+            WsCommand::ParticipantVoted {
+                vote: WsVote::Label("XL".to_string()),
+                vote_type: Some("t-shirt".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn it_should_accept_unit_commands() {
+        let command: Command = serde_json::from_str(r#"{"VotesCleared":null}"#).unwrap();
+        assert_eq!(command.command, WsCommand::VotesCleared);
+        assert_eq!(command.command_id, None);
+    }
+
+    #[test]
+    fn it_should_accept_a_client_command_id() {
+        let command: Command =
+            serde_json::from_str(r#"{"command_id":"abc","VotesRevealed":null}"#).unwrap();
+        assert_eq!(command.command_id, Some("abc".to_string()));
+        assert_eq!(command.command, WsCommand::VotesRevealed);
+    }
+
+    #[test]
+    fn it_should_tell_sync_messages_from_commands() {
+        let sync: SyncMessage = serde_json::from_str(r#"{"Acknowledged":{"version":4}}"#).unwrap();
+        assert_eq!(sync, SyncMessage::Acknowledged { version: 4 });
+        let sync: SyncMessage = serde_json::from_str(r#"{"ResyncRequested":null}"#).unwrap();
+        assert_eq!(sync, SyncMessage::ResyncRequested);
+        assert!(
+            serde_json::from_str::<SyncMessage>(r#"{"version":1,"VotesRevealed":null}"#).is_err()
+        );
+    }
+
+    #[test]
+    fn it_should_not_support_other_versions() {
+        let command: Command =
+            serde_json::from_str(r#"{"version":2,"VotesRevealed":null}"#).unwrap();
+        assert!(!command.is_supported());
+    }
+}
@@ -1,55 +1,287 @@
 use crate::websocket::{ServerMessage, UseCaseMessage};
-use crate::{as_basic_error, Error};
+use futures::future::BoxFuture;
+use futures::FutureExt;
 use poker_board::command::event::CombinedEvent;
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::Duration;
+use tokio::sync::mpsc::{self, error::TrySendError};
 use util::use_case::UseCase;
 
-pub fn start_usecase_sidecar(
-    use_case: Arc<UseCase<CombinedEvent>>,
-) -> std::sync::mpsc::Sender<UseCaseMessage> {
-    let (tx, rx) = std::sync::mpsc::channel::<UseCaseMessage>();
+/// How many commands a board can have queued before senders are pushed back.
+const BOARD_QUEUE_CAPACITY: usize = 64;
+const WORKER_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
 
-    tokio::spawn(async move {
-        let rx = Arc::new(Mutex::new(rx));
+type Handler<M> = Arc<dyn Fn(M) -> BoxFuture<'static, ()> + Send + Sync>;
+type Queues<M> = Mutex<HashMap<String, mpsc::Sender<M>>>;
+
+fn lock<M>(queues: &Queues<M>) -> MutexGuard<'_, HashMap<String, mpsc::Sender<M>>> {
+    // The map only holds channel handles, so a panic while it was held leaves nothing to repair.
+    queues
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Handles messages for the same key one at a time, in the order they were sent, and
+/// messages for different keys concurrently. Every key gets a worker task reading a
+/// bounded queue, started on demand and stopped once idle. When every clone is dropped
+/// the queues close, and the workers finish whatever is still queued before stopping.
+struct Executor<M> {
+    queues: Arc<Queues<M>>,
+    handler: Handler<M>,
+    capacity: usize,
+    idle_timeout: Duration,
+}
+
+impl<M> Clone for Executor<M> {
+    fn clone(&self) -> Self {
+        Self {
+            queues: self.queues.clone(),
+            handler: self.handler.clone(),
+            capacity: self.capacity,
+            idle_timeout: self.idle_timeout,
+        }
+    }
+}
+
+impl<M: Send + 'static> Executor<M> {
+    fn new(
+        capacity: usize,
+        idle_timeout: Duration,
+        handler: impl Fn(M) -> BoxFuture<'static, ()> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            queues: Arc::new(Mutex::new(HashMap::new())),
+            handler: Arc::new(handler),
+            capacity,
+            idle_timeout,
+        }
+    }
+
+    fn queue(&self, queues: &mut HashMap<String, mpsc::Sender<M>>, key: &str) -> mpsc::Sender<M> {
+        if let Some(queue) = queues.get(key).filter(|queue| !queue.is_closed()) {
+            return queue.clone();
+        }
+        let (tx, rx) = mpsc::channel(self.capacity);
+        tokio::spawn(run_worker(
+            key.to_string(),
+            rx,
+            Arc::downgrade(&self.queues),
+            self.handler.clone(),
+            self.idle_timeout,
+        ));
+        queues.insert(key.to_string(), tx.clone());
+        tx
+    }
+
+    fn try_send(&self, key: &str, message: M) -> Result<(), TrySendError<M>> {
+        let mut queues = lock(&self.queues);
+        self.queue(&mut queues, key).try_send(message)
+    }
+
+    async fn send(&self, key: &str, message: M) {
         loop {
-            let rx = rx.clone();
-            match tokio::task::spawn_blocking(move || -> Result<UseCaseMessage, Error> {
-                rx.lock()
-                    .map_err(as_basic_error)?
-                    .recv()
-                    .map_err(as_basic_error)
-            })
-            .await
-            {
-                Ok(Ok(message)) => {
-                    let UseCaseMessage {
-                        board_id,
-                        command,
-                        receiver,
-                    } = message;
-                    use_case
-                        .execute(&board_id, &command)
-                        .await
-                        .map(ServerMessage::CommandResult)
-                        .unwrap_or_else(|err| {
-                            log::error!("Error: {:?}", err);
-                            ServerMessage::Error(
-                                "There was an error processing your command.".to_string(),
-                            )
-                        })
-                        .send_to(receiver);
-                    log::info!("Command executed: {:?}", command)
-                }
-                Ok(_) => {
-                    break;
-                }
-                Err(err) => {
-                    log::error!("Error: {:?}", err);
-                    break;
+            let queue = {
+                let mut queues = lock(&self.queues);
+                self.queue(&mut queues, key)
+            };
+            // A worker only stops while idle, so a closed queue just needs a new worker.
+            if let Ok(permit) = queue.reserve_owned().await {
+                permit.send(message);
+                return;
+            }
+        }
+    }
+}
+
+/// A worker only stops for being idle while it holds the lock and nothing is queued or
+/// reserved on its channel, so a message can never be accepted by a stopped worker.
+async fn run_worker<M>(
+    key: String,
+    mut rx: mpsc::Receiver<M>,
+    queues: Weak<Queues<M>>,
+    handler: Handler<M>,
+    idle_timeout: Duration,
+) {
+    loop {
+        match tokio::time::timeout(idle_timeout, rx.recv()).await {
+            Ok(Some(message)) => handler(message).await,
+            Ok(None) => return,
+            Err(_) => {
+                let Some(queues) = queues.upgrade() else {
+                    continue;
+                };
+                let mut queues = lock(&queues);
+                let busy = queues
+                    .get(&key)
+                    .is_some_and(|queue| queue.capacity() < queue.max_capacity());
+                if !busy {
+                    queues.remove(&key);
+                    return;
                 }
             }
         }
-    });
+    }
+}
+
+/// Queues commands for [`start_usecase_sidecar`]'s executor. Commands for one board run
+/// in the order they were queued, while different boards run concurrently.
+#[derive(Clone)]
+pub struct UseCaseSender {
+    executor: Executor<UseCaseMessage>,
+}
+
+impl UseCaseSender {
+    /// Queues a command without waiting, failing if its board already has a full queue.
+    pub fn try_send(
+        &self,
+        message: UseCaseMessage,
+    ) -> Result<(), Box<TrySendError<UseCaseMessage>>> {
+        let board_id = message.board_id.clone();
+        self.executor.try_send(&board_id, message).map_err(Box::new)
+    }
+
+    /// Queues a command, waiting for room in its board's queue.
+    pub async fn send(&self, message: UseCaseMessage) {
+        let board_id = message.board_id.clone();
+        self.executor.send(&board_id, message).await
+    }
+}
+
+pub fn start_usecase_sidecar(use_case: Arc<UseCase<CombinedEvent>>) -> UseCaseSender {
+    UseCaseSender {
+        executor: Executor::new(BOARD_QUEUE_CAPACITY, WORKER_IDLE_TIMEOUT, move |message| {
+            execute(use_case.clone(), message).boxed()
+        }),
+    }
+}
+
+async fn execute(use_case: Arc<UseCase<CombinedEvent>>, message: UseCaseMessage) {
+    let UseCaseMessage {
+        board_id,
+        command,
+        receiver,
+        metadata,
+    } = message;
+    use_case
+        .execute(&board_id, &command, &metadata)
+        .await
+        .map(ServerMessage::CommandResult)
+        .unwrap_or_else(|err| {
+            log::error!("Error: {:?}", err);
+            ServerMessage::Error("There was an error processing your command.".to_string())
+        })
+        .send_to(receiver);
+    log::info!("Command executed: {:?}", command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::Notify;
+
+    type Log = Arc<Mutex<Vec<(&'static str, u32)>>>;
+
+    async fn eventually(condition: impl Fn() -> bool) {
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while !condition() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("condition was never met");
+    }
+
+    /// Records every message, holding messages with value 0 for board "a" until released.
+    fn gated_executor(
+        capacity: usize,
+        idle_timeout: Duration,
+    ) -> (Executor<(&'static str, u32)>, Log, Arc<Notify>) {
+        let log = Log::default();
+        let gate = Arc::new(Notify::new());
+        let executor = {
+            let log = log.clone();
+            let gate = gate.clone();
+            Executor::new(
+                capacity,
+                idle_timeout,
+                move |message: (&'static str, u32)| {
+                    let log = log.clone();
+                    let gate = gate.clone();
+                    async move {
+                        if message == ("a", 0) {
+                            gate.notified().await;
+                        }
+                        log.lock().unwrap().push(message);
+                    }
+                    .boxed()
+                },
+            )
+        };
+        (executor, log, gate)
+    }
+
+    #[tokio::test]
+    async fn it_should_keep_each_board_in_order_without_holding_up_the_others() {
+        let (executor, log, gate) = gated_executor(8, Duration::from_secs(60));
+        executor.try_send("a", ("a", 0)).unwrap();
+        executor.try_send("a", ("a", 1)).unwrap();
+        executor.try_send("b", ("b", 0)).unwrap();
+
+        eventually(|| log.lock().unwrap().contains(&("b", 0))).await;
+        assert_eq!(*log.lock().unwrap(), vec![("b", 0)]);
+
+        gate.notify_one();
+        eventually(|| log.lock().unwrap().len() == 3).await;
+        assert_eq!(*log.lock().unwrap(), vec![("b", 0), ("a", 0), ("a", 1)]);
+    }
+
+    #[tokio::test]
+    async fn it_should_push_back_once_a_board_queue_is_full() {
+        let (executor, log, gate) = gated_executor(1, Duration::from_secs(60));
+        executor.try_send("a", ("a", 0)).unwrap();
+        eventually(|| {
+            let queues = lock(&executor.queues);
+            queues["a"].capacity() == 1
+        })
+        .await;
+
+        executor.try_send("a", ("a", 1)).unwrap();
+        assert!(matches!(
+            executor.try_send("a", ("a", 2)),
+            Err(TrySendError::Full(("a", 2)))
+        ));
+        executor.try_send("b", ("b", 0)).unwrap();
+
+        let waiting = tokio::spawn({
+            let executor = executor.clone();
+            async move { executor.send("a", ("a", 2)).await }
+        });
+        gate.notify_one();
+        waiting.await.unwrap();
+        eventually(|| log.lock().unwrap().len() == 4).await;
+    }
+
+    #[tokio::test]
+    async fn it_should_stop_idle_workers_and_restart_them_on_demand() {
+        let (executor, log, _gate) = gated_executor(8, Duration::from_millis(20));
+        executor.try_send("b", ("b", 0)).unwrap();
+        eventually(|| lock(&executor.queues).is_empty()).await;
+
+        executor.send("b", ("b", 1)).await;
+        eventually(|| log.lock().unwrap().len() == 2).await;
+    }
+
+    #[tokio::test]
+    async fn it_should_finish_queued_commands_when_every_sender_is_dropped() {
+        let (executor, log, gate) = gated_executor(8, Duration::from_secs(60));
+        let handler = Arc::downgrade(&executor.handler);
+        executor.try_send("a", ("a", 0)).unwrap();
+        executor.try_send("a", ("a", 1)).unwrap();
+        drop(executor);
 
-    tx
+        gate.notify_one();
+        eventually(|| handler.strong_count() == 0).await;
+        assert_eq!(*log.lock().unwrap(), vec![("a", 0), ("a", 1)]);
+    }
 }
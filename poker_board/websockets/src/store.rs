@@ -1,77 +1,242 @@
-use crate::message::{LoadEvents, SaveEvents};
-use actix::{Actor, Addr, AsyncContext, Handler, Message, MessageResponse};
+use crate::message::{LoadVersion, SaveEvents};
+use crate::patch::{self, PatchOperation};
+use actix::dev::ToEnvelope;
+use actix::{Actor, ActorContext, Addr, AsyncContext, Handler, MailboxError, Message};
 
+use poker_board::command::adapter::ArcMutexStore;
 use poker_board::command::event::BoardModifiedEvent;
+use poker_board::query;
+use poker_board::query::presentation::BoardPresentation;
+use serde_json::Value;
 use std::collections::HashMap;
+use std::fmt::Display;
 
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
 
-use util::store::{LoadEntity, SaveEntity};
+use util::entity::HandleEvent;
+use util::envelope::{Envelope, Metadata};
+use util::query::PresentationOf;
+use util::store::{Conflict, LoadEntity, SaveEntity, Tail};
 
-struct EventUpdates {
-    store: HashMap<String, Board>,
-    self_address: Option<Addr<Self>>,
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// How long a board's actor stays up without messages or waiting sessions before it is
+/// shut down. Its events stay in the backing store and are loaded again on next use.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+type Registry = Arc<Mutex<HashMap<String, Addr<BoardUpdates>>>>;
+
+/// Projects the events of one board and broadcasts its presentation to subscribed sessions.
+/// The events themselves stay in the persistent store, which the projection is a window on.
+struct BoardUpdates {
+    key: String,
+    board: Option<Board>,
+    registry: Registry,
+    idle_timeout: Option<Duration>,
+    last_active: Instant,
 }
 
-impl EventUpdates {
-    fn new() -> Self {
+impl BoardUpdates {
+    fn new(key: &str, registry: Registry, idle_timeout: Option<Duration>) -> Self {
         Self {
-            store: HashMap::new(),
-            self_address: None,
+            key: key.to_string(),
+            board: None,
+            registry,
+            idle_timeout,
+            last_active: Instant::now(),
         }
     }
-}
 
-type Error = Box<dyn std::error::Error + Send + Sync>;
+    fn board(&mut self) -> &mut Board {
+        self.last_active = Instant::now();
+        self.board.get_or_insert_with(Board::new)
+    }
+
+    fn is_idle(&self, idle_timeout: Duration) -> bool {
+        self.last_active.elapsed() >= idle_timeout
+            && !self.board.as_ref().is_some_and(Board::has_subscribers)
+    }
 
-impl Actor for EventUpdates {
+    /// Removes this actor from the registry unless a newer one has replaced it.
+    fn deregister(&self, ctx: &mut actix::Context<Self>) {
+        let mut registry = self.registry.lock().unwrap_or_else(PoisonError::into_inner);
+        if registry.get(&self.key) == Some(&ctx.address()) {
+            registry.remove(&self.key);
+        }
+    }
+}
+
+impl Actor for BoardUpdates {
     type Context = actix::Context<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
-        self.self_address = Some(ctx.address());
+        if let Some(idle_timeout) = self.idle_timeout {
+            ctx.run_interval(idle_timeout, move |act, ctx| {
+                if act.is_idle(idle_timeout) {
+                    act.deregister(ctx);
+                    ctx.stop();
+                }
+            });
+        }
     }
 }
 
-impl Handler<LoadEvents> for EventUpdates {
-    type Result = Result<Option<Vec<BoardModifiedEvent>>, Error>;
+impl Handler<LoadVersion> for BoardUpdates {
+    type Result = Result<usize, Error>;
 
-    fn handle(&mut self, msg: LoadEvents, _ctx: &mut Self::Context) -> Self::Result {
-        Ok(self.store.get(&msg.key).map(|b| b.events.clone()))
+    fn handle(&mut self, _msg: LoadVersion, _ctx: &mut Self::Context) -> Self::Result {
+        self.last_active = Instant::now();
+        Ok(self.board.as_ref().map_or(0, |board| board.version))
     }
 }
 
-impl Handler<SaveEvents> for EventUpdates {
-    type Result = Result<Vec<BoardModifiedEvent>, Error>;
+impl Handler<SaveEvents> for BoardUpdates {
+    type Result = Result<(), Error>;
 
     fn handle(&mut self, msg: SaveEvents, _ctx: &mut Self::Context) -> Self::Result {
-        self.store
-            .entry(msg.key.clone())
-            .or_insert(Board::new())
-            .update_events(msg.event.clone());
+        Ok(self.board().update_events(msg.events)?)
+    }
+}
 
-        Ok(msg.event)
+impl Handler<Subscribe> for BoardUpdates {
+    type Result = Result<Subscription, Error>;
+
+    fn handle(&mut self, msg: Subscribe, _ctx: &mut Self::Context) -> Self::Result {
+        let board = self.board();
+        board.update_events(msg.events)?;
+        board.subscribe(msg.last_version)
     }
 }
 
-impl Handler<WaitForEvents> for EventUpdates {
-    type Result = Result<UpdateRequest, Error>;
+/// A message for the actor of the board at `key`.
+trait ForBoard: Message {
+    fn key(&self) -> &str;
+}
+
+impl ForBoard for LoadVersion {
+    fn key(&self) -> &str {
+        &self.key
+    }
+}
 
-    fn handle(&mut self, msg: WaitForEvents, _ctx: &mut Self::Context) -> Self::Result {
-        self.store
-            .entry(msg.key)
-            .or_insert(Board::new())
-            .get_update(msg.last_event)
+impl ForBoard for SaveEvents {
+    fn key(&self) -> &str {
+        &self.key
     }
 }
 
+impl ForBoard for Subscribe {
+    fn key(&self) -> &str {
+        &self.key
+    }
+}
+
+/// Starts a [`BoardUpdates`] actor per board on first use and routes messages to it by
+/// key, so that sessions on different boards never share a mailbox.
+#[derive(Clone)]
+struct Boards {
+    registry: Registry,
+    idle_timeout: Option<Duration>,
+}
+
+impl Boards {
+    fn new(idle_timeout: Option<Duration>) -> Self {
+        Self {
+            registry: Arc::new(Mutex::new(HashMap::new())),
+            idle_timeout,
+        }
+    }
+
+    fn get(&self, key: &str) -> Addr<BoardUpdates> {
+        let mut registry = self.registry.lock().unwrap_or_else(PoisonError::into_inner);
+        match registry.get(key) {
+            Some(addr) if addr.connected() => addr.clone(),
+            _ => {
+                let addr = BoardUpdates::new(key, self.registry.clone(), self.idle_timeout).start();
+                registry.insert(key.to_string(), addr.clone());
+                addr
+            }
+        }
+    }
+
+    async fn send<M>(&self, message: M) -> Result<M::Result, Error>
+    where
+        M: ForBoard + Clone + Send + 'static,
+        M::Result: Send,
+        BoardUpdates: Handler<M>,
+        actix::Context<BoardUpdates>: ToEnvelope<BoardUpdates, M>,
+    {
+        loop {
+            match self.get(message.key()).send(message.clone()).await {
+                // The board was shut down for being idle after we looked it up.
+                Err(MailboxError::Closed) => continue,
+                result => return result.map_err(|e| Box::new(e) as Error),
+            }
+        }
+    }
+}
+
+pub trait PersistentStore:
+    LoadEntity<Vec<Envelope<BoardModifiedEvent>>, Key = String, Error = Error>
+    + SaveEntity<Vec<BoardModifiedEvent>, Key = String, Error = Error>
+{
+}
+
+impl<S> PersistentStore for S where
+    S: LoadEntity<Vec<Envelope<BoardModifiedEvent>>, Key = String, Error = Error>
+        + SaveEntity<Vec<BoardModifiedEvent>, Key = String, Error = Error>
+{
+}
+
 #[derive(Clone)]
 pub struct StoreInterface {
-    store_addr: Addr<EventUpdates>,
+    boards: Boards,
+    persistent: Arc<dyn PersistentStore>,
 }
 
 impl StoreInterface {
-    fn new(store_addr: Addr<EventUpdates>) -> Self {
-        Self { store_addr }
+    fn new(boards: Boards, persistent: Arc<dyn PersistentStore>) -> Self {
+        Self { boards, persistent }
+    }
+
+    /// The persisted events of the board at `key` from position `from` on.
+    async fn persisted(
+        &self,
+        key: &str,
+        from: usize,
+    ) -> Result<Tail<Vec<Envelope<BoardModifiedEvent>>>, Error> {
+        Ok(self
+            .persistent
+            .load_from(&key.to_string(), &[from])
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// Projects `events` on the board's actor. An actor that has not projected the events
+    /// before them, because it was just started or saves reached it out of order, catches
+    /// up on everything persisted past its version instead.
+    async fn project(
+        &self,
+        key: &str,
+        events: Tail<Vec<Envelope<BoardModifiedEvent>>>,
+    ) -> Result<(), Error> {
+        let save = |events| SaveEvents {
+            key: key.to_string(),
+            events,
+        };
+        match self.boards.send(save(events)).await? {
+            Err(error) => match error.downcast_ref::<Conflict>() {
+                Some(conflict) => {
+                    let persisted = self.persisted(key, conflict.actual_version).await?;
+                    self.boards.send(save(persisted)).await?
+                }
+                None => Err(error),
+            },
+            saved => saved,
+        }
     }
 }
 
@@ -85,112 +250,232 @@ impl SaveEntity<Vec<BoardModifiedEvent>> for StoreInterface {
         key: &Self::Key,
         entity: Vec<BoardModifiedEvent>,
     ) -> Result<Vec<BoardModifiedEvent>, Self::Error> {
-        self.store_addr
-            .send(SaveEvents {
-                key: key.clone(),
-                event: entity.clone(),
-            })
+        self.persistent.save(key, entity.clone()).await?;
+        let projected = self.boards.send(LoadVersion { key: key.clone() }).await??;
+        self.project(key, self.persisted(key, projected).await?)
+            .await?;
+        Ok(entity)
+    }
+
+    async fn save_versioned(
+        &self,
+        key: &Self::Key,
+        entity: Vec<BoardModifiedEvent>,
+        expected_version: usize,
+        metadata: &Metadata,
+    ) -> Result<Vec<BoardModifiedEvent>, Self::Error> {
+        self.save_tail(key, Tail::whole(entity), expected_version, metadata)
             .await
-            .unwrap_or_else(|e| Err(Box::new(e)))
+            .map(|tail| tail.entity)
     }
-}
 
-struct Board {
-    events: Vec<BoardModifiedEvent>,
-    update_senders: Vec<UpdateChannel>,
+    /// Persists first and projects second, stamping both with the same `metadata` so the
+    /// projected envelopes match the persisted ones.
+    async fn save_tail(
+        &self,
+        key: &Self::Key,
+        tail: Tail<Vec<BoardModifiedEvent>>,
+        expected_version: usize,
+        metadata: &Metadata,
+    ) -> Result<Tail<Vec<BoardModifiedEvent>>, Self::Error> {
+        self.persistent
+            .save_tail(key, tail.clone(), expected_version, metadata)
+            .await?;
+        let appended = Envelope::wrap(
+            expected_version,
+            tail.after(expected_version).to_vec(),
+            metadata,
+        );
+        self.project(key, Tail::of_stream(expected_version, appended))
+            .await?;
+        Ok(tail)
+    }
 }
 
-struct UpdateChannel {
-    update_sender: tokio::sync::oneshot::Sender<Vec<BoardModifiedEvent>>,
-    position: usize,
+/// A subscriber asked for updates after a version the board has not reached.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AheadOfStream {
+    pub requested_version: usize,
+    pub actual_version: usize,
 }
 
-impl UpdateChannel {
-    fn new(
-        update_sender: tokio::sync::oneshot::Sender<Vec<BoardModifiedEvent>>,
-        position: usize,
-    ) -> Self {
-        Self {
-            update_sender,
-            position,
-        }
-    }
+impl std::error::Error for AheadOfStream {}
 
-    fn send(self, events: &[BoardModifiedEvent]) -> Result<(), Vec<BoardModifiedEvent>> {
-        self.update_sender
-            .send(events.iter().skip(self.position).cloned().collect())
+impl Display for AheadOfStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Version {} is ahead of the board, which is at version {}",
+            self.requested_version, self.actual_version
+        )
     }
 }
 
+/// How many presentations a subscriber can fall behind before it skips to the newest.
+const UPDATE_BUFFER: usize = 16;
+
+/// The presentation of a board as of its first `version` events, along with the patch
+/// from the presentation broadcast before it, as of `previous` events.
+#[derive(Debug, PartialEq)]
+pub struct PresentationUpdate {
+    pub version: usize,
+    pub presentation: BoardPresentation,
+    pub previous: usize,
+    pub patch: Vec<PatchOperation>,
+}
+
+fn as_json(presentation: &BoardPresentation) -> Value {
+    serde_json::to_value(presentation).unwrap_or_else(|e| {
+        log::error!("Error serializing presentation: {}", e);
+        Value::Null
+    })
+}
+
+struct Board {
+    version: usize,
+    state: query::Board,
+    presented: Value,
+    latest: Arc<PresentationUpdate>,
+    updates: broadcast::Sender<Arc<PresentationUpdate>>,
+}
+
 impl Board {
     fn new() -> Self {
+        let state = query::Board::new();
+        let presentation = BoardPresentation::from_model(&state);
         Self {
-            events: Vec::new(),
-            update_senders: Vec::new(),
+            version: 0,
+            presented: as_json(&presentation),
+            state,
+            latest: Arc::new(PresentationUpdate {
+                version: 0,
+                presentation,
+                previous: 0,
+                patch: Vec::new(),
+            }),
+            updates: broadcast::channel(UPDATE_BUFFER).0,
         }
     }
 
-    fn update_events(&mut self, events: Vec<BoardModifiedEvent>) {
-        self.events
-            .extend(events.into_iter().skip(self.events.len()));
-        self.update_senders.drain(..).for_each(|sender| {
-            sender.send(&self.events).unwrap_or_else(|e| {
-                for event in e {
-                    log::info!("Event {} could not be sent, channel closed", event);
-                }
-            })
+    /// Projects the events of `events` past the ones already projected, then diffs them once
+    /// and broadcasts the result to every subscriber. Fails if `events` starts past them.
+    fn update_events(
+        &mut self,
+        events: Tail<Vec<Envelope<BoardModifiedEvent>>>,
+    ) -> Result<(), Conflict> {
+        let projected = self.version;
+        if events.skipped() > projected {
+            return Err(Conflict {
+                expected_version: events.skipped(),
+                actual_version: projected,
+            });
+        }
+        let appended = events.after(projected);
+        if appended.is_empty() {
+            return Ok(());
+        }
+        appended
+            .iter()
+            .for_each(|envelope| self.state.apply(&envelope.event));
+        self.version += appended.len();
+
+        let presentation = BoardPresentation::from_model(&self.state);
+        let presented = as_json(&presentation);
+        let update = Arc::new(PresentationUpdate {
+            version: self.version,
+            presentation,
+            previous: projected,
+            patch: patch::diff(&self.presented, &presented),
         });
+        self.presented = presented;
+        self.latest = update.clone();
+        if self.updates.send(update).is_err() {
+            log::debug!("No subscribers for board update {}", self.version);
+        }
+        Ok(())
     }
 
-    fn get_update(&mut self, last_event: usize) -> Result<UpdateRequest, Error> {
-        match self.events.len() {
-            len if len > last_event => {
-                let events = self.events.iter().skip(last_event).cloned().collect();
-                Ok(UpdateRequest::Fulfilled(events))
-            }
-            len if len == last_event => {
-                let (sender, receiver) = tokio::sync::oneshot::channel();
-                self.update_senders
-                    .push(UpdateChannel::new(sender, last_event));
-                Ok(UpdateRequest::Pending(receiver))
-            }
-            _ => {
-                let err_msg = format!(
-                    "Invalid event index {} events len {}",
-                    last_event,
-                    self.events.len()
-                );
-                Err(Box::new(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    err_msg,
-                )))
+    fn has_subscribers(&self) -> bool {
+        self.updates.receiver_count() > 0
+    }
+
+    fn subscribe(&self, last_version: usize) -> Result<Subscription, Error> {
+        if last_version > self.version {
+            return Err(AheadOfStream {
+                requested_version: last_version,
+                actual_version: self.version,
             }
+            .into());
         }
+        Ok(Subscription {
+            current: Some(self.latest.clone()).filter(|update| update.version > last_version),
+            latest: self.latest.clone(),
+            receiver: self.updates.subscribe(),
+            last_version,
+        })
     }
 }
 
+/// Subscribes to the board at `key` after projecting the persisted `events`, in one message
+/// so that the board cannot be shut down in between.
 #[derive(Message)]
-#[rtype(result = "Result<UpdateRequest, Error>")]
+#[rtype(result = "Result<Subscription, Error>")]
 #[derive(Debug, Clone)]
-pub struct WaitForEvents {
+pub struct Subscribe {
     pub key: String,
-    pub last_event: usize,
+    pub last_version: usize,
+    pub events: Tail<Vec<Envelope<BoardModifiedEvent>>>,
 }
 
-#[derive(MessageResponse)]
-pub enum UpdateRequest {
-    Pending(tokio::sync::oneshot::Receiver<Vec<BoardModifiedEvent>>),
-    Fulfilled(Vec<BoardModifiedEvent>),
+/// The presentations of a board after `last_version`: the current one if it is newer,
+/// followed by every one broadcast since subscribing.
+pub struct Subscription {
+    current: Option<Arc<PresentationUpdate>>,
+    latest: Arc<PresentationUpdate>,
+    receiver: broadcast::Receiver<Arc<PresentationUpdate>>,
+    last_version: usize,
 }
 
-impl UpdateRequest {
-    async fn get(self) -> Vec<BoardModifiedEvent> {
-        match self {
-            Self::Pending(receiver) => receiver.await.unwrap_or_else(|e| {
-                log::error!("Error getting request {}", e);
-                Vec::new()
-            }),
-            Self::Fulfilled(events) => events,
+impl Subscription {
+    /// The presentation of the board as of subscribing, which [`Self::next`] only returns
+    /// if it is newer than `last_version`. A board without events is presented at version 0.
+    pub fn latest(&self) -> Arc<PresentationUpdate> {
+        self.latest.clone()
+    }
+
+    /// Waits for the next newer presentation. Every update carries the whole presentation,
+    /// so a subscriber that has fallen behind skips straight to the newest one queued.
+    /// Returns `None` once the board has stopped broadcasting.
+    pub async fn next(&mut self) -> Option<Arc<PresentationUpdate>> {
+        loop {
+            let update = match self.current.take() {
+                Some(update) => update,
+                None => match self.receiver.recv().await {
+                    Ok(update) => update,
+                    Err(RecvError::Lagged(skipped)) => {
+                        log::info!("Subscriber lagged behind by {} board updates", skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return None,
+                },
+            };
+            let update = self.skip_to_newest(update);
+            if update.version > self.last_version {
+                self.last_version = update.version;
+                return Some(update);
+            }
+        }
+    }
+
+    fn skip_to_newest(&mut self, mut update: Arc<PresentationUpdate>) -> Arc<PresentationUpdate> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(newer) => update = newer,
+                Err(TryRecvError::Lagged(skipped)) => {
+                    log::info!("Subscriber lagged behind by {} board updates", skipped);
+                }
+                Err(_) => return update,
+            }
         }
     }
 }
@@ -234,7 +519,7 @@ where
 }
 
 #[async_trait::async_trait]
-impl LoadUpdate<Vec<BoardModifiedEvent>> for StoreInterface {
+impl LoadUpdate<Subscription> for StoreInterface {
     type Key = String;
     type Error = Error;
 
@@ -242,17 +527,22 @@ impl LoadUpdate<Vec<BoardModifiedEvent>> for StoreInterface {
         &self,
         key: &Self::Key,
         last_version: usize,
-    ) -> Result<Vec<BoardModifiedEvent>, Self::Error> {
-        Ok(self
-            .store_addr
-            .send(WaitForEvents {
-                key: key.clone(),
-                last_event: last_version,
-            })
-            .await
-            .unwrap_or_else(|e| Err(Box::new(e)))?
-            .get()
-            .await)
+    ) -> Result<Subscription, Self::Error> {
+        let subscribe = |events| Subscribe {
+            key: key.clone(),
+            last_version,
+            events,
+        };
+        let projected = self.boards.send(LoadVersion { key: key.clone() }).await??;
+        let events = self.persisted(key, projected).await?;
+        match self.boards.send(subscribe(events)).await? {
+            // The board was shut down after telling its version, so project it from scratch.
+            Err(error) if error.downcast_ref::<Conflict>().is_some() => {
+                let events = self.persisted(key, 0).await?;
+                self.boards.send(subscribe(events)).await?
+            }
+            subscription => subscription,
+        }
     }
 }
 
@@ -262,14 +552,247 @@ impl LoadEntity<Vec<BoardModifiedEvent>> for StoreInterface {
     type Error = Error;
 
     async fn load(&self, key: &Self::Key) -> Result<Option<Vec<BoardModifiedEvent>>, Self::Error> {
-        self.store_addr
-            .send(LoadEvents { key: key.clone() })
-            .await
-            .unwrap_or_else(|e| Err(Box::new(e)))
+        let envelopes: Option<Vec<Envelope<BoardModifiedEvent>>> = self.load(key).await?;
+        Ok(envelopes.map(|envelopes| Envelope::events(&envelopes)))
+    }
+
+    async fn load_from(
+        &self,
+        key: &Self::Key,
+        from: &[usize],
+    ) -> Result<Option<Tail<Vec<BoardModifiedEvent>>>, Self::Error> {
+        let tail: Option<Tail<Vec<Envelope<BoardModifiedEvent>>>> =
+            self.load_from(key, from).await?;
+        Ok(tail.map(|Tail { from, entity }| Tail {
+            from,
+            entity: Envelope::events(&entity),
+        }))
     }
 }
 
+#[async_trait::async_trait]
+impl LoadEntity<Vec<Envelope<BoardModifiedEvent>>> for StoreInterface {
+    type Key = String;
+    type Error = Error;
+
+    async fn load(
+        &self,
+        key: &Self::Key,
+    ) -> Result<Option<Vec<Envelope<BoardModifiedEvent>>>, Self::Error> {
+        self.persistent.load(key).await
+    }
+
+    async fn load_from(
+        &self,
+        key: &Self::Key,
+        from: &[usize],
+    ) -> Result<Option<Tail<Vec<Envelope<BoardModifiedEvent>>>>, Self::Error> {
+        self.persistent.load_from(key, from).await
+    }
+}
+
+/// Keeps every board's events in memory, projected for subscribers by the same per-board
+/// actors as a persistent store so that idle boards are shut down alike.
 pub fn create_store() -> StoreInterface {
-    let store_addr = EventUpdates::new().start();
-    StoreInterface::new(store_addr)
+    in_memory_store(IDLE_TIMEOUT)
+}
+
+fn in_memory_store(idle_timeout: Duration) -> StoreInterface {
+    StoreInterface::new(
+        Boards::new(Some(idle_timeout)),
+        Arc::new(ArcMutexStore::<BoardModifiedEvent>::new()),
+    )
+}
+
+pub fn create_persistent_store(persistent: impl PersistentStore + 'static) -> StoreInterface {
+    StoreInterface::new(Boards::new(Some(IDLE_TIMEOUT)), Arc::new(persistent))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enveloped(events: Vec<BoardModifiedEvent>) -> Tail<Vec<Envelope<BoardModifiedEvent>>> {
+        Tail::whole(Envelope::wrap_from(0, events, &Metadata::default()))
+    }
+
+    fn enveloped_from(
+        from: usize,
+        events: Vec<BoardModifiedEvent>,
+    ) -> Tail<Vec<Envelope<BoardModifiedEvent>>> {
+        Tail::of_stream(from, Envelope::wrap(from, events, &Metadata::default()))
+    }
+
+    fn added(id: &str) -> BoardModifiedEvent {
+        BoardModifiedEvent::ParticipantAdded {
+            participant_id: id.to_string(),
+            participant_name: id.to_string(),
+        }
+    }
+
+    #[test]
+    fn it_should_reject_events_starting_past_the_projected_ones() {
+        let mut board = Board::new();
+        board.update_events(enveloped(vec![added("a")])).unwrap();
+
+        assert_eq!(
+            board.update_events(enveloped_from(2, vec![added("c")])),
+            Err(Conflict {
+                expected_version: 2,
+                actual_version: 1
+            })
+        );
+        assert_eq!(board.version, 1);
+    }
+
+    #[test]
+    fn it_should_project_only_the_events_past_the_projected_ones() {
+        let mut board = Board::new();
+        board.update_events(enveloped(vec![added("a")])).unwrap();
+        board
+            .update_events(enveloped(vec![added("a"), added("b")]))
+            .unwrap();
+        board
+            .update_events(enveloped_from(1, vec![added("b")]))
+            .unwrap();
+
+        assert_eq!(board.version, 2);
+        assert_eq!(board.presented["participants"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn it_should_broadcast_one_presentation_to_every_subscriber() {
+        let mut board = Board::new();
+        board.update_events(enveloped(vec![added("a")])).unwrap();
+        let mut first = board.subscribe(1).unwrap();
+        let mut second = board.subscribe(1).unwrap();
+
+        board
+            .update_events(enveloped(vec![added("a"), added("b")]))
+            .unwrap();
+        let update = first.next().await.unwrap();
+        assert_eq!(update.version, 2);
+        assert_eq!(update.previous, 1);
+        assert!(!update.patch.is_empty());
+        assert!(Arc::ptr_eq(&update, &board.latest));
+        assert!(Arc::ptr_eq(&update, &second.next().await.unwrap()));
+    }
+
+    #[tokio::test]
+    async fn it_should_start_a_subscriber_behind_the_board_from_its_current_presentation() {
+        let mut board = Board::new();
+        let mut subscription = board.subscribe(0).unwrap();
+        board
+            .update_events(enveloped(vec![added("a"), added("b")]))
+            .unwrap();
+
+        assert_eq!(board.subscribe(0).unwrap().next().await.unwrap().version, 2);
+        assert_eq!(subscription.next().await.unwrap().version, 2);
+        let error = board.subscribe(3).err().unwrap();
+        assert_eq!(
+            error.downcast_ref::<AheadOfStream>(),
+            Some(&AheadOfStream {
+                requested_version: 3,
+                actual_version: 2
+            })
+        );
+    }
+
+    #[test]
+    fn it_should_present_a_board_without_events_at_version_zero() {
+        let subscription = Board::new().subscribe(0).unwrap();
+
+        assert_eq!(subscription.latest().version, 0);
+        assert!(subscription.current.is_none());
+    }
+
+    #[tokio::test]
+    async fn it_should_skip_a_lagging_subscriber_to_the_newest_presentation() {
+        let mut board = Board::new();
+        let mut subscription = board.subscribe(0).unwrap();
+        let mut events = Vec::new();
+        for id in 0..UPDATE_BUFFER * 2 {
+            events.push(added(&id.to_string()));
+            board.update_events(enveloped(events.clone())).unwrap();
+        }
+
+        let update = subscription.next().await.unwrap();
+        assert_eq!(update.version, UPDATE_BUFFER * 2);
+        assert!(Arc::ptr_eq(&update, &board.latest));
+    }
+
+    fn is_registered(boards: &Boards, key: &str) -> bool {
+        boards.registry.lock().unwrap().contains_key(key)
+    }
+
+    #[actix::test]
+    async fn it_should_start_one_actor_per_board() {
+        let boards = Boards::new(None);
+        assert!(boards.get("a") == boards.get("a"));
+        assert!(boards.get("a") != boards.get("b"));
+    }
+
+    #[actix::test]
+    async fn it_should_shut_down_idle_boards_unless_a_session_is_waiting() {
+        let boards = Boards::new(Some(Duration::from_millis(10)));
+        boards
+            .send(LoadVersion {
+                key: "idle".to_string(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        let waiting = boards
+            .send(Subscribe {
+                key: "waiting".to_string(),
+                last_version: 0,
+                events: Tail::default(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!is_registered(&boards, "idle"));
+        assert!(is_registered(&boards, "waiting"));
+
+        drop(waiting);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!is_registered(&boards, "waiting"));
+    }
+
+    #[actix::test]
+    async fn it_should_reload_a_shut_down_board_from_the_backing_store() {
+        let store = in_memory_store(Duration::from_millis(10));
+        let key = "board".to_string();
+        store
+            .save_versioned(&key, vec![added("a")], 0, &Metadata::default())
+            .await
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!is_registered(&store.boards, &key));
+        store
+            .save_versioned(&key, vec![added("a"), added("b")], 1, &Metadata::default())
+            .await
+            .unwrap();
+
+        let events: Option<Vec<BoardModifiedEvent>> = store.load(&key).await.unwrap();
+        assert_eq!(events, Some(vec![added("a"), added("b")]));
+    }
+
+    #[actix::test]
+    async fn it_should_subscribe_to_a_board_shut_down_since_it_was_saved() {
+        let store = in_memory_store(Duration::from_millis(10));
+        let key = "board".to_string();
+        store
+            .save_versioned(&key, vec![added("a"), added("b")], 0, &Metadata::default())
+            .await
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!is_registered(&store.boards, &key));
+        let mut subscription = store.load_update(&key, 1).await.unwrap();
+        assert_eq!(subscription.next().await.unwrap().version, 2);
+    }
 }
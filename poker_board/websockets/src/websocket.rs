@@ -1,46 +1,121 @@
-use crate::store::LoadUpdate;
+use crate::patch::PatchOperation;
+use crate::presence::{Presence, GRACE_PERIOD};
+use crate::protocol::{Command, SyncMessage, UpdateMode, WsCommand, PROTOCOL_VERSION};
+use crate::sidecar::UseCaseSender;
+use crate::store::{AheadOfStream, LoadUpdate, PresentationUpdate, Subscription};
 use crate::Error;
 use actix::{Actor, ActorContext, Addr, AsyncContext, Handler, Message, Recipient, StreamHandler};
 use actix_web_actors::ws;
 use actix_web_actors::ws::{ProtocolError, WebsocketContext};
 use poker_board::command;
-use poker_board::command::event::BoardModifiedEvent;
+use poker_board::command::event::{CombinedEvent, ParticipantKind};
 use poker_board::command::{remove_participant, BoardCommand};
 
 use actix_web::{web, HttpResponse};
 use poker_board::query::presentation::BoardPresentation;
-use poker_board::query::Board;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::task::JoinHandle;
-use util::entity::HandleEvent;
-use util::query::PresentationOf;
-
-use crate::websocket::WsCommand::ParticipantVoted;
+use util::envelope::Metadata;
 
 #[derive(Clone, Deserialize, Debug)]
-struct Command {
-    #[serde(flatten)]
-    command: WsCommand,
+pub struct JoinRequest {
+    name: String,
+    #[serde(default)]
+    kind: ParticipantKind,
+    token: Option<String>,
+    #[serde(default)]
+    updates: UpdateMode,
+    #[serde(default)]
+    since: usize,
 }
 
-#[derive(Clone, Deserialize, Debug)]
-enum WsCommand {
-    ParticipantVoted { vote: u8 },
-}
+pub type Updates = dyn LoadUpdate<Subscription, Key = String, Error = Error>;
 
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How many versions a delta client that acknowledges can fall behind before it is sent
+/// snapshots instead of patches.
+const MAX_UNACKNOWLEDGED: usize = 16;
+
+/// What a connection in [`UpdateMode::Delta`] has been sent and has acknowledged.
+#[derive(Default)]
+struct DeltaSync {
+    latest: Option<Arc<PresentationUpdate>>,
+    sent: Option<usize>,
+    acknowledged: Option<usize>,
+}
+
+impl DeltaSync {
+    /// A client resuming from `since` already holds that version, so it can be sent a
+    /// patch from it straight away.
+    fn resuming_from(since: usize) -> Self {
+        Self {
+            sent: (since > 0).then_some(since),
+            ..Self::default()
+        }
+    }
+
+    /// A patch when the client holds the version `update` was diffed from, a snapshot
+    /// when it does not or has stopped acknowledging.
+    fn message_for(&mut self, update: Arc<PresentationUpdate>) -> ServerMessage {
+        let behind = self.acknowledged.is_some_and(|acknowledged| {
+            update.version.saturating_sub(acknowledged) > MAX_UNACKNOWLEDGED
+        });
+        self.latest = Some(update.clone());
+        if self.sent == Some(update.previous) && !behind {
+            self.sent = Some(update.version);
+            ServerMessage::Patch {
+                from: update.previous,
+                version: update.version,
+                operations: update.patch.clone(),
+            }
+        } else {
+            self.snapshot(&update)
+        }
+    }
+
+    fn snapshot(&mut self, update: &PresentationUpdate) -> ServerMessage {
+        self.sent = Some(update.version);
+        ServerMessage::Snapshot {
+            version: update.version,
+            board: update.presentation.clone(),
+        }
+    }
+
+    fn resync(&mut self) -> Option<ServerMessage> {
+        let latest = self.latest.clone()?;
+        Some(self.snapshot(&latest))
+    }
+
+    /// Resyncs a client that claims a version it was never sent.
+    fn acknowledge(&mut self, version: usize) -> Option<ServerMessage> {
+        if self.sent.is_none_or(|sent| version > sent) {
+            return self.resync();
+        }
+        self.acknowledged = Some(version);
+        None
+    }
+}
 
 pub struct WebSocket {
     board_id: String,
-    updates: Arc<dyn LoadUpdate<Vec<BoardModifiedEvent>, Key = String, Error = Error>>,
-    use_case: Arc<std::sync::mpsc::Sender<UseCaseMessage>>,
+    updates: Arc<Updates>,
+    use_case: Arc<UseCaseSender>,
     task_handle: Option<JoinHandle<()>>,
+    connection_id: String,
     id: String,
+    token: String,
     name: String,
+    kind: ParticipantKind,
+    presence: Presence,
+    left: bool,
+    on_board: bool,
     hb: Instant,
+    since: usize,
+    mode: UpdateMode,
+    sync: DeltaSync,
 }
 
 #[derive(Debug)]
@@ -48,38 +123,85 @@ pub struct UseCaseMessage {
     pub board_id: String,
     pub command: BoardCommand,
     pub receiver: Recipient<ServerMessage>,
+    pub metadata: Metadata,
+}
+
+impl UseCaseMessage {
+    /// Correlates the command with the connection that sent it, and attributes it to the
+    /// participant the connection belongs to. Commands without a client `command_id` get
+    /// a fresh one, so only client ids are ever deduplicated.
+    pub fn new(
+        board_id: String,
+        command: BoardCommand,
+        receiver: Recipient<ServerMessage>,
+        connection_id: &str,
+        participant_id: &str,
+        command_id: Option<String>,
+    ) -> Self {
+        let command_id = command_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let metadata = Metadata::new()
+            .with_command_id(command_id)
+            .with_correlation_id(connection_id)
+            .with_actor_id(participant_id);
+        Self {
+            board_id,
+            command,
+            receiver,
+            metadata,
+        }
+    }
 }
 
 pub fn start(
     r: actix_web::HttpRequest,
     stream: web::Payload,
     board_id: String,
-    updates: Arc<dyn LoadUpdate<Vec<BoardModifiedEvent>, Key = String, Error = Error>>,
-    use_case_tx: Arc<std::sync::mpsc::Sender<UseCaseMessage>>,
-    name: String,
+    updates: Arc<Updates>,
+    use_case_tx: Arc<UseCaseSender>,
+    presence: Presence,
+    join: JoinRequest,
 ) -> Result<HttpResponse, actix_web::error::Error> {
     ws::start(
-        WebSocket::new(board_id, updates, use_case_tx, name),
+        WebSocket::new(board_id, updates, use_case_tx, presence, join),
         &r,
         stream,
     )
 }
 
+/// The participant a token stands for. Ids are derived from tokens one way, so the ids
+/// shown on the board do not let anyone act as the participants they belong to.
+pub fn participant_id(token: &str) -> String {
+    uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, token.as_bytes()).to_string()
+}
+
 impl WebSocket {
     pub fn new(
         board_id: String,
-        udpdates: Arc<dyn LoadUpdate<Vec<BoardModifiedEvent>, Key = String, Error = Error>>,
-        use_case: Arc<std::sync::mpsc::Sender<UseCaseMessage>>,
-        name: String,
+        udpdates: Arc<Updates>,
+        use_case: Arc<UseCaseSender>,
+        presence: Presence,
+        join: JoinRequest,
     ) -> Self {
+        let token = join
+            .token
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
         Self {
-            id: uuid::Uuid::new_v4().to_string(),
+            connection_id: uuid::Uuid::new_v4().to_string(),
+            id: participant_id(&token),
+            token,
             board_id,
             updates: udpdates,
             use_case,
             task_handle: None,
-            name,
+            name: join.name,
+            kind: join.kind,
+            presence,
+            left: false,
+            on_board: false,
             hb: Instant::now(),
+            since: join.since,
+            mode: join.updates,
+            sync: DeltaSync::resuming_from(join.since),
         }
     }
 
@@ -98,32 +220,37 @@ impl WebSocket {
         });
     }
 
+    /// Forwards the board's broadcast presentations newer than `since` to the socket,
+    /// subscribing again from the last one sent if the board stops broadcasting. A client
+    /// resuming from a version the board has not reached is told so and sent the board
+    /// from scratch.
     async fn update_loop(
         addr: Addr<WebSocket>,
-        updates: Arc<dyn LoadUpdate<Vec<BoardModifiedEvent>, Key = String, Error = Error>>,
+        updates: Arc<Updates>,
         board_id: &String,
+        since: usize,
     ) {
-        let mut last_event: usize = 0;
-        let mut state = Board::new();
+        let mut last_version = since;
 
         loop {
-            let updates = updates.load_update(&board_id, last_event).await;
-
-            match updates {
-                Ok(updates) => {
-                    updates.iter().for_each(|event| state.apply(event));
-                    let presentation = BoardPresentation::from_model(&state);
-                    match addr.send(ServerMessage::QueryUpdated(presentation)).await {
-                        Ok(_) => {
-                            last_event += updates.len();
-                        }
-                        Err(e) => {
-                            log::error!("Error sending message: {}", e);
-                        }
-                    };
+            let mut subscription = match updates.load_update(board_id, last_version).await {
+                Ok(subscription) => subscription,
+                Err(e) if e.downcast_ref::<AheadOfStream>().is_some() && last_version > 0 => {
+                    addr.do_send(ServerMessage::Error(e.to_string()));
+                    addr.do_send(ResumeFailed);
+                    last_version = 0;
+                    continue;
                 }
                 Err(e) => {
                     log::error!("Error loading updates: {}", e);
+                    return;
+                }
+            };
+            while let Some(update) = subscription.next().await {
+                last_version = update.version;
+                if let Err(e) = addr.send(BoardUpdated(update)).await {
+                    log::error!("Error sending message: {}", e);
+                    return;
                 }
             }
         }
@@ -133,8 +260,18 @@ impl WebSocket {
 #[derive(Message, Serialize)]
 #[rtype(result = "()")]
 pub enum ServerMessage {
+    ParticipantToken(String),
     QueryUpdated(BoardPresentation),
-    CommandResult(Vec<BoardModifiedEvent>),
+    Snapshot {
+        version: usize,
+        board: BoardPresentation,
+    },
+    Patch {
+        from: usize,
+        version: usize,
+        operations: Vec<PatchOperation>,
+    },
+    CommandResult(Vec<CombinedEvent>),
     Error(String),
 }
 
@@ -144,14 +281,6 @@ impl ServerMessage {
     }
 }
 
-impl WebSocket {
-    fn convert_command(&self, command: WsCommand) -> BoardCommand {
-        match command {
-            ParticipantVoted { vote } => command::vote(vote, "1".to_string(), self.id.clone()),
-        }
-    }
-}
-
 impl Handler<ServerMessage> for WebSocket {
     type Result = ();
 
@@ -160,6 +289,66 @@ impl Handler<ServerMessage> for WebSocket {
     }
 }
 
+#[derive(Message)]
+#[rtype(result = "()")]
+struct BoardUpdated(Arc<PresentationUpdate>);
+
+/// The version the client resumed from was ahead of the board, so it holds nothing usable.
+#[derive(Message)]
+#[rtype(result = "()")]
+struct ResumeFailed;
+
+impl Handler<ResumeFailed> for WebSocket {
+    type Result = ();
+
+    fn handle(&mut self, _msg: ResumeFailed, _ctx: &mut Self::Context) -> Self::Result {
+        self.sync = DeltaSync::default();
+    }
+}
+
+impl Handler<BoardUpdated> for WebSocket {
+    type Result = ();
+
+    fn handle(&mut self, msg: BoardUpdated, ctx: &mut Self::Context) -> Self::Result {
+        // Once on the board, a participant only drops off it by leaving or being kicked,
+        // possibly from another connection, and this one has nothing left to do.
+        let on_board = msg.0.presentation.includes(&self.id);
+        if self.on_board && !on_board {
+            self.left = true;
+            ctx.text(
+                serde_json::to_string(&ServerMessage::Error(
+                    "You are no longer on the board".to_string(),
+                ))
+                .unwrap(),
+            );
+            ctx.stop();
+            return;
+        }
+        self.on_board = on_board;
+
+        let message = match self.mode {
+            UpdateMode::Full => ServerMessage::QueryUpdated(msg.0.presentation.clone()),
+            UpdateMode::Delta => self.sync.message_for(msg.0),
+        };
+        ctx.text(serde_json::to_string(&message).unwrap());
+    }
+}
+
+impl WebSocket {
+    fn handle_sync(&mut self, message: SyncMessage, ctx: &mut WebsocketContext<Self>) {
+        if self.mode != UpdateMode::Delta {
+            return;
+        }
+        let reply = match message {
+            SyncMessage::Acknowledged { version } => self.sync.acknowledge(version),
+            SyncMessage::ResyncRequested => self.sync.resync(),
+        };
+        if let Some(reply) = reply {
+            ctx.text(serde_json::to_string(&reply).unwrap());
+        }
+    }
+}
+
 impl StreamHandler<Result<ws::Message, ProtocolError>> for WebSocket {
     fn handle(&mut self, message: Result<ws::Message, ProtocolError>, ctx: &mut Self::Context) {
         match message {
@@ -175,26 +364,45 @@ impl StreamHandler<Result<ws::Message, ProtocolError>> for WebSocket {
                 ctx.stop();
             }
             Ok(ws::Message::Text(text)) => {
+                if let Ok(sync) = serde_json::from_str::<SyncMessage>(&text) {
+                    return self.handle_sync(sync, ctx);
+                }
                 let msg = serde_json::from_str::<Command>(&text);
                 match msg {
+                    Ok(command) if !command.is_supported() => {
+                        ctx.address().do_send(ServerMessage::Error(format!(
+                            "Unsupported protocol version {}, expected {}",
+                            command.version, PROTOCOL_VERSION
+                        )));
+                    }
                     Ok(command) => {
                         let addr = ctx.address().recipient();
                         let key = self.board_id.clone();
-                        let command = self.convert_command(command.command);
+                        let leaving = command.command == WsCommand::ParticipantLeft;
+                        let command_id = command.command_id;
+                        let command = command.command.into_board_command(&self.id);
                         let use_case = self.use_case.clone();
-                        use_case
-                            .send(UseCaseMessage {
-                                board_id: key,
-                                command,
-                                receiver: addr,
-                            })
-                            .unwrap_or_else(|err| {
+                        match use_case.try_send(UseCaseMessage::new(
+                            key,
+                            command,
+                            addr,
+                            &self.connection_id,
+                            &self.id,
+                            command_id,
+                        )) {
+                            // Only a leave that reached the board skips the grace-period removal.
+                            Ok(()) => self.left = leaving,
+                            Err(err) => {
                                 log::error!("Error sending command: {:?}", err);
                                 ctx.address().do_send(ServerMessage::Error(format!(
                                     "There was an error processing your command {}",
                                     err
                                 )));
-                            });
+                            }
+                        }
+                        if leaving {
+                            ctx.stop();
+                        }
                     }
                     Err(err) => {
                         log::error!("Error deserializing command: {:?} {:?}", text, err);
@@ -221,24 +429,34 @@ impl Actor for WebSocket {
         let updates = self.updates.clone();
         let board_id = self.board_id.clone();
 
+        self.presence.connect(&board_id, &self.id);
+        addr.do_send(ServerMessage::ParticipantToken(self.token.clone()));
+
         let id = self.id.clone();
         let name = self.name.clone();
+        let kind = self.kind;
+        let since = self.since;
         let use_case = self.use_case.clone();
 
-        if let Ok(_) = use_case
-            .send(UseCaseMessage {
-                board_id: board_id.clone(),
-                command: command::add_participant(name.clone(), id.clone()),
-                receiver: addr.clone().recipient(),
-            })
-            .or_else(|err| {
-                log::error!("Error Adding Participant: {:?}", err.0);
-                Err(err)
+        // Whether a reconnecting participant is still on the board is up to the board, so
+        // they only get added again if they are not.
+        if use_case
+            .try_send(UseCaseMessage::new(
+                board_id.clone(),
+                command::rejoin_participant(name.clone(), id.clone(), kind),
+                addr.clone().recipient(),
+                &self.connection_id,
+                &id,
+                None,
+            ))
+            .inspect_err(|err| {
+                log::error!("Error Adding Participant: {:?}", err);
             })
+            .is_ok()
         {
             let handle = tokio::spawn(async move {
                 {
-                    Self::update_loop(addr, updates, &board_id).await;
+                    Self::update_loop(addr, updates, &board_id, since).await;
                 }
             });
             self.task_handle = Some(handle);
@@ -251,18 +469,95 @@ impl Actor for WebSocket {
         }
 
         let id = self.id.clone();
-        let use_case = self.use_case.clone();
         let board_id = self.board_id.clone();
+        let presence = self.presence.clone();
+        let use_case = self.use_case.clone();
+        let receiver = ctx.address().recipient();
+        let connection_id = self.connection_id.clone();
 
-        let remove_participant = remove_participant(id.clone());
-        self.use_case
-            .send(UseCaseMessage {
-                board_id,
-                command: remove_participant,
-                receiver: ctx.address().recipient(),
-            })
-            .unwrap_or_else(|err| {
-                log::error!("Error Removing Participant: {:?}", err.0);
-            });
+        if self.left {
+            presence.leave(&board_id, &id);
+            return;
+        }
+        let disconnected = presence.disconnect(&board_id, &id);
+        tokio::spawn(async move {
+            tokio::time::sleep(GRACE_PERIOD).await;
+            if !presence.has_left(&board_id, &id, disconnected) {
+                return;
+            }
+
+            use_case
+                .send(UseCaseMessage::new(
+                    board_id,
+                    remove_participant(id.clone()),
+                    receiver,
+                    &connection_id,
+                    &id,
+                    None,
+                ))
+                .await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn update(previous: usize, version: usize) -> Arc<PresentationUpdate> {
+        Arc::new(PresentationUpdate {
+            version,
+            presentation: BoardPresentation::default(),
+            previous,
+            patch: vec![PatchOperation::Replace {
+                path: "/votes_revealed".to_string(),
+                value: json!(true),
+            }],
+        })
+    }
+
+    fn is_snapshot(message: &ServerMessage, at: usize) -> bool {
+        matches!(message, ServerMessage::Snapshot { version, .. } if *version == at)
+    }
+
+    #[test]
+    fn it_should_send_a_snapshot_then_patches_while_the_client_is_in_step() {
+        let mut sync = DeltaSync::default();
+        assert!(is_snapshot(&sync.message_for(update(1, 2)), 2));
+        assert!(matches!(
+            sync.message_for(update(2, 3)),
+            ServerMessage::Patch {
+                from: 2,
+                version: 3,
+                ..
+            }
+        ));
+        assert!(is_snapshot(&sync.message_for(update(4, 5)), 5));
+    }
+
+    #[test]
+    fn it_should_patch_a_resuming_client_from_the_version_it_holds() {
+        let mut sync = DeltaSync::resuming_from(2);
+        assert!(matches!(
+            sync.message_for(update(2, 3)),
+            ServerMessage::Patch { from: 2, .. }
+        ));
+        assert!(is_snapshot(
+            &DeltaSync::resuming_from(0).message_for(update(0, 1)),
+            1
+        ));
+    }
+
+    #[test]
+    fn it_should_resync_on_request_or_when_acknowledgements_fall_behind() {
+        let mut sync = DeltaSync::default();
+        sync.message_for(update(0, 1));
+        assert!(sync.acknowledge(1).is_none());
+        assert!(is_snapshot(&sync.resync().unwrap(), 1));
+        assert!(is_snapshot(&sync.acknowledge(2).unwrap(), 1));
+
+        let behind = 2 + MAX_UNACKNOWLEDGED;
+        assert!(is_snapshot(&sync.message_for(update(1, behind)), behind));
     }
 }
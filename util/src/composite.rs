@@ -0,0 +1,656 @@
+use crate::entity::HandleEvent;
+use crate::envelope::Metadata;
+use crate::store::{LoadEntity, SaveEntity, Tail};
+use async_trait::async_trait;
+use std::error::Error;
+use std::fmt::{Debug, Display};
+use std::marker::PhantomData;
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// An event of one sub-stream, embedded in the composite event `E`.
+pub trait SubEvent<E>: Sized {
+    fn from_composite(event: &E) -> Option<&Self>;
+}
+
+pub trait Route<E> {
+    fn route(&mut self, event: &E);
+}
+
+macro_rules! route_tuple {
+    ($($part:ident: $index:tt),+) => {
+        impl<E, $($part),+> Route<E> for ($($part,)+)
+        where
+            $($part: HandleEvent, $part::Event: SubEvent<E>,)+
+        {
+            fn route(&mut self, event: &E) {
+                $(
+                    if let Some(event) = <$part::Event as SubEvent<E>>::from_composite(event) {
+                        self.$index.apply(event);
+                    }
+                )+
+            }
+        }
+    };
+}
+
+route_tuple!(A: 0);
+route_tuple!(A: 0, B: 1);
+route_tuple!(A: 0, B: 1, C: 2);
+route_tuple!(A: 0, B: 1, C: 2, D: 3);
+route_tuple!(A: 0, B: 1, C: 2, D: 3, F: 4);
+route_tuple!(A: 0, B: 1, C: 2, D: 3, F: 4, G: 5);
+
+/// An aggregate made of a tuple of sub-aggregates. Every composite event is handed to each
+/// part whose event type it embeds, so several parts can follow the same sub-stream.
+pub struct Composite<E, T> {
+    parts: T,
+    event: PhantomData<fn(&E)>,
+}
+
+impl<E, T> Composite<E, T> {
+    pub fn new(parts: T) -> Self {
+        Self {
+            parts,
+            event: PhantomData,
+        }
+    }
+
+    pub fn parts(&self) -> &T {
+        &self.parts
+    }
+}
+
+impl<E, T> HandleEvent for Composite<E, T>
+where
+    T: Route<E>,
+{
+    type Event = E;
+
+    fn apply(&mut self, event: &E) {
+        self.parts.route(event);
+    }
+}
+
+impl<E, T: Default> Default for Composite<E, T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<E, T: Clone> Clone for Composite<E, T> {
+    fn clone(&self) -> Self {
+        Self::new(self.parts.clone())
+    }
+}
+
+impl<E, T: PartialEq> PartialEq for Composite<E, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.parts == other.parts
+    }
+}
+
+impl<E, T: Debug> Debug for Composite<E, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Composite").field(&self.parts).finish()
+    }
+}
+
+#[async_trait]
+trait Stream<E>: Send + Sync {
+    fn routes(&self, event: &E) -> bool;
+
+    async fn load(&self, key: &str) -> Result<Vec<E>, BoxError>;
+
+    async fn load_from(&self, key: &str, from: usize) -> Result<Tail<Vec<E>>, BoxError>;
+
+    async fn save(
+        &self,
+        key: &str,
+        from: usize,
+        events: &[E],
+        loaded: Option<usize>,
+        metadata: &Metadata,
+    ) -> Result<(), BoxError>;
+}
+
+struct RoutedStream<S> {
+    write_store: Box<dyn SaveEntity<Vec<S>, Key = String, Error = BoxError>>,
+    read_store: Box<dyn LoadEntity<Vec<S>, Key = String, Error = BoxError>>,
+}
+
+#[async_trait]
+impl<E, S> Stream<E> for RoutedStream<S>
+where
+    E: From<S> + Sync,
+    S: SubEvent<E> + Clone + Send + Sync,
+{
+    fn routes(&self, event: &E) -> bool {
+        S::from_composite(event).is_some()
+    }
+
+    async fn load(&self, key: &str) -> Result<Vec<E>, BoxError> {
+        let events = self
+            .read_store
+            .load(&key.to_string())
+            .await?
+            .unwrap_or_default();
+        Ok(events.into_iter().map(E::from).collect())
+    }
+
+    async fn load_from(&self, key: &str, from: usize) -> Result<Tail<Vec<E>>, BoxError> {
+        let tail = self
+            .read_store
+            .load_from(&key.to_string(), &[from])
+            .await?
+            .unwrap_or_default();
+        Ok(Tail::of_stream(
+            tail.skipped(),
+            tail.entity.into_iter().map(E::from).collect(),
+        ))
+    }
+
+    /// `events` hold the composite stream past the first `from` events of this stream,
+    /// and the first `loaded` of them are the ones that were loaded, so counting this
+    /// stream's events among them gives its own expected version. A stream without new
+    /// events is left alone, which keeps a command touching one stream a single checked
+    /// write.
+    async fn save(
+        &self,
+        key: &str,
+        from: usize,
+        events: &[E],
+        loaded: Option<usize>,
+        metadata: &Metadata,
+    ) -> Result<(), BoxError> {
+        let key = key.to_string();
+        let stream_events: Vec<S> = events
+            .iter()
+            .filter_map(S::from_composite)
+            .cloned()
+            .collect();
+        match loaded {
+            None => {
+                self.write_store.save(&key, stream_events).await?;
+            }
+            Some(loaded) => {
+                let expected_version = from
+                    + events[..loaded.min(events.len())]
+                        .iter()
+                        .filter_map(S::from_composite)
+                        .count();
+                if from + stream_events.len() > expected_version {
+                    self.write_store
+                        .save_tail(
+                            &key,
+                            Tail::of_stream(from, stream_events),
+                            expected_version,
+                            metadata,
+                        )
+                        .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A versioned save would have appended to more than one sub-stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpansStreams {
+    pub streams: usize,
+}
+
+impl Error for SpansStreams {}
+
+impl Display for SpansStreams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Cannot append to {} streams in one versioned save",
+            self.streams
+        )
+    }
+}
+
+/// A store for a composite event stream made of any number of typed sub-streams, each
+/// loaded from and saved to its own stores. Loading concatenates the sub-streams in the
+/// order they were added.
+///
+/// The sub-streams' stores cannot be written atomically together, so a versioned save may
+/// only append to one of them and fails with [`SpansStreams`] before writing anything
+/// otherwise. Its write is then a single checked append, which either lands whole or fails
+/// with a [`Conflict`](crate::store::Conflict) that is safe to retry.
+pub struct CompositeStore<E> {
+    streams: Vec<Box<dyn Stream<E>>>,
+}
+
+impl<E> CompositeStore<E>
+where
+    E: Send + Sync + 'static,
+{
+    pub fn new() -> Self {
+        Self {
+            streams: Vec::new(),
+        }
+    }
+
+    pub fn with_stream<S>(
+        mut self,
+        write_store: impl SaveEntity<Vec<S>, Key = String, Error = BoxError> + 'static,
+        read_store: impl LoadEntity<Vec<S>, Key = String, Error = BoxError> + 'static,
+    ) -> Self
+    where
+        E: From<S>,
+        S: SubEvent<E> + Clone + Send + Sync + 'static,
+    {
+        self.streams.push(Box::new(RoutedStream {
+            write_store: Box::new(write_store),
+            read_store: Box::new(read_store),
+        }));
+        self
+    }
+}
+
+impl<E> Default for CompositeStore<E>
+where
+    E: Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl<E> LoadEntity<Vec<E>> for CompositeStore<E>
+where
+    E: Send + Sync + 'static,
+{
+    type Key = String;
+    type Error = BoxError;
+
+    async fn load(&self, key: &Self::Key) -> Result<Option<Vec<E>>, Self::Error> {
+        let mut events = Vec::new();
+        for stream in &self.streams {
+            events.extend(stream.load(key).await?);
+        }
+        Ok(Some(events))
+    }
+
+    /// Skips the first `from[n]` events of the `n`th stream added.
+    async fn load_from(
+        &self,
+        key: &Self::Key,
+        from: &[usize],
+    ) -> Result<Option<Tail<Vec<E>>>, Self::Error> {
+        let mut tail = Tail::<Vec<E>>::default();
+        for (index, stream) in self.streams.iter().enumerate() {
+            let stream_tail = stream
+                .load_from(key, from.get(index).copied().unwrap_or(0))
+                .await?;
+            tail.from.push(stream_tail.skipped());
+            tail.entity.extend(stream_tail.entity);
+        }
+        Ok(Some(tail))
+    }
+}
+
+#[async_trait]
+impl<E> SaveEntity<Vec<E>> for CompositeStore<E>
+where
+    E: Send + Sync + 'static,
+{
+    type Key = String;
+    type Error = BoxError;
+
+    async fn save(&self, key: &Self::Key, entity: Vec<E>) -> Result<Vec<E>, Self::Error> {
+        for stream in &self.streams {
+            stream
+                .save(key, 0, &entity, None, &Metadata::default())
+                .await?;
+        }
+        Ok(entity)
+    }
+
+    async fn save_versioned(
+        &self,
+        key: &Self::Key,
+        entity: Vec<E>,
+        expected_version: usize,
+        metadata: &Metadata,
+    ) -> Result<Vec<E>, Self::Error> {
+        self.save_tail(key, Tail::whole(entity), expected_version, metadata)
+            .await
+            .map(|tail| tail.entity)
+    }
+
+    async fn save_tail(
+        &self,
+        key: &Self::Key,
+        tail: Tail<Vec<E>>,
+        expected_version: usize,
+        metadata: &Metadata,
+    ) -> Result<Tail<Vec<E>>, Self::Error> {
+        let loaded = expected_version.saturating_sub(tail.skipped());
+        let appended = &tail.entity[loaded.min(tail.entity.len())..];
+        let streams = self
+            .streams
+            .iter()
+            .filter(|stream| appended.iter().any(|event| stream.routes(event)))
+            .count();
+        if streams > 1 {
+            return Err(SpansStreams { streams }.into());
+        }
+
+        for (index, stream) in self.streams.iter().enumerate() {
+            let from = tail.from.get(index).copied().unwrap_or(0);
+            stream
+                .save(key, from, &tail.entity, Some(loaded), metadata)
+                .await?;
+        }
+        Ok(tail)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::Conflict;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Added(u32);
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Renamed(&'static str);
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Event {
+        Added(Added),
+        Renamed(Renamed),
+    }
+
+    impl From<Added> for Event {
+        fn from(event: Added) -> Self {
+            Self::Added(event)
+        }
+    }
+
+    impl From<Renamed> for Event {
+        fn from(event: Renamed) -> Self {
+            Self::Renamed(event)
+        }
+    }
+
+    impl SubEvent<Event> for Added {
+        fn from_composite(event: &Event) -> Option<&Self> {
+            match event {
+                Event::Added(event) => Some(event),
+                _ => None,
+            }
+        }
+    }
+
+    impl SubEvent<Event> for Renamed {
+        fn from_composite(event: &Event) -> Option<&Self> {
+            match event {
+                Event::Renamed(event) => Some(event),
+                _ => None,
+            }
+        }
+    }
+
+    #[derive(Default, Debug, Clone, PartialEq)]
+    struct Total(u32);
+
+    impl HandleEvent for Total {
+        type Event = Added;
+
+        fn apply(&mut self, event: &Added) {
+            self.0 += event.0;
+        }
+    }
+
+    #[derive(Default, Debug, Clone, PartialEq)]
+    struct Count(usize);
+
+    impl HandleEvent for Count {
+        type Event = Added;
+
+        fn apply(&mut self, _event: &Added) {
+            self.0 += 1;
+        }
+    }
+
+    #[derive(Default, Debug, Clone, PartialEq)]
+    struct Name(&'static str);
+
+    impl HandleEvent for Name {
+        type Event = Renamed;
+
+        fn apply(&mut self, event: &Renamed) {
+            self.0 = event.0;
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestStore<T>(Arc<Mutex<HashMap<String, Vec<T>>>>);
+
+    impl<T> Default for TestStore<T> {
+        fn default() -> Self {
+            Self(Arc::new(Mutex::new(HashMap::new())))
+        }
+    }
+
+    #[async_trait]
+    impl<T: Clone + Send + Sync + 'static> LoadEntity<Vec<T>> for TestStore<T> {
+        type Key = String;
+        type Error = BoxError;
+
+        async fn load(&self, key: &String) -> Result<Option<Vec<T>>, BoxError> {
+            Ok(self.0.lock().unwrap().get(key).cloned())
+        }
+
+        async fn load_from(
+            &self,
+            key: &String,
+            from: &[usize],
+        ) -> Result<Option<Tail<Vec<T>>>, BoxError> {
+            Ok(self.0.lock().unwrap().get(key).map(|events| {
+                let from = from.first().copied().unwrap_or(0).min(events.len());
+                Tail::of_stream(from, events[from..].to_vec())
+            }))
+        }
+    }
+
+    #[async_trait]
+    impl<T: Clone + Send + Sync + 'static> SaveEntity<Vec<T>> for TestStore<T> {
+        type Key = String;
+        type Error = BoxError;
+
+        async fn save(&self, key: &String, events: Vec<T>) -> Result<Vec<T>, BoxError> {
+            self.0.lock().unwrap().insert(key.clone(), events.clone());
+            Ok(events)
+        }
+
+        async fn save_versioned(
+            &self,
+            key: &String,
+            events: Vec<T>,
+            expected_version: usize,
+            _metadata: &Metadata,
+        ) -> Result<Vec<T>, BoxError> {
+            let mut streams = self.0.lock().unwrap();
+            let stored = streams.get(key).map(Vec::len).unwrap_or(0);
+            Conflict::check(expected_version, stored)?;
+            streams.insert(key.clone(), events.clone());
+            Ok(events)
+        }
+
+        async fn save_tail(
+            &self,
+            key: &String,
+            tail: Tail<Vec<T>>,
+            expected_version: usize,
+            _metadata: &Metadata,
+        ) -> Result<Tail<Vec<T>>, BoxError> {
+            let mut streams = self.0.lock().unwrap();
+            let stream = streams.entry(key.clone()).or_default();
+            Conflict::check(expected_version, stream.len())?;
+            stream.extend(tail.after(expected_version).iter().cloned());
+            Ok(tail)
+        }
+    }
+
+    fn composite_store() -> (CompositeStore<Event>, TestStore<Added>, TestStore<Renamed>) {
+        let added = TestStore::default();
+        let renamed = TestStore::default();
+        let store = CompositeStore::new()
+            .with_stream(renamed.clone(), renamed.clone())
+            .with_stream(added.clone(), added.clone());
+        (store, added, renamed)
+    }
+
+    #[test]
+    fn it_should_route_each_event_to_every_part_that_follows_its_stream() {
+        let mut aggregate = Composite::<Event, (Total, Count, Name)>::default();
+        for event in [
+            Event::Added(Added(2)),
+            Event::Renamed(Renamed("board")),
+            Event::Added(Added(3)),
+        ] {
+            aggregate.apply(&event);
+        }
+        assert_eq!(aggregate.parts(), &(Total(5), Count(2), Name("board")));
+    }
+
+    #[tokio::test]
+    async fn it_should_save_each_sub_stream_to_its_own_store() {
+        let (store, added, renamed) = composite_store();
+        let key = "key".to_string();
+
+        store
+            .save_versioned(&key, vec![Event::Added(Added(1))], 0, &Metadata::default())
+            .await
+            .unwrap();
+        store
+            .save_versioned(
+                &key,
+                vec![Event::Added(Added(1)), Event::Renamed(Renamed("board"))],
+                1,
+                &Metadata::default(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(added.load(&key).await.unwrap(), Some(vec![Added(1)]));
+        assert_eq!(
+            renamed.load(&key).await.unwrap(),
+            Some(vec![Renamed("board")])
+        );
+        assert_eq!(
+            store.load(&key).await.unwrap(),
+            Some(vec![
+                Event::Renamed(Renamed("board")),
+                Event::Added(Added(1))
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_refuse_a_versioned_save_appending_to_several_streams() {
+        let (store, added, renamed) = composite_store();
+        let key = "key".to_string();
+
+        let error = store
+            .save_versioned(
+                &key,
+                vec![Event::Added(Added(1)), Event::Renamed(Renamed("board"))],
+                0,
+                &Metadata::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<SpansStreams>(),
+            Some(&SpansStreams { streams: 2 })
+        );
+        assert_eq!(added.load(&key).await.unwrap(), None);
+        assert_eq!(renamed.load(&key).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn it_should_check_each_sub_stream_against_what_was_loaded_from_it() {
+        let (store, _added, renamed) = composite_store();
+        let key = "key".to_string();
+        store
+            .save_versioned(&key, vec![Event::Added(Added(1))], 0, &Metadata::default())
+            .await
+            .unwrap();
+        renamed
+            .save(&key, vec![Renamed("concurrent")])
+            .await
+            .unwrap();
+
+        let error = store
+            .save_versioned(
+                &key,
+                vec![Event::Added(Added(1)), Event::Renamed(Renamed("board"))],
+                1,
+                &Metadata::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<Conflict>(),
+            Some(&Conflict {
+                expected_version: 0,
+                actual_version: 1
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_load_and_save_each_sub_stream_past_its_own_position() {
+        let (store, added, renamed) = composite_store();
+        let key = "key".to_string();
+        renamed
+            .save(&key, vec![Renamed("a"), Renamed("b")])
+            .await
+            .unwrap();
+        added.save(&key, vec![Added(1), Added(2)]).await.unwrap();
+
+        let tail = store.load_from(&key, &[1, 2]).await.unwrap().unwrap();
+        assert_eq!(
+            tail,
+            Tail {
+                from: vec![1, 2],
+                entity: vec![Event::Renamed(Renamed("b"))]
+            }
+        );
+
+        let mut entity = tail.entity;
+        entity.push(Event::Added(Added(3)));
+        store
+            .save_tail(
+                &key,
+                Tail {
+                    from: vec![1, 2],
+                    entity,
+                },
+                4,
+                &Metadata::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            added.load(&key).await.unwrap(),
+            Some(vec![Added(1), Added(2), Added(3)])
+        );
+        assert_eq!(
+            renamed.load(&key).await.unwrap(),
+            Some(vec![Renamed("a"), Renamed("b")])
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use crate::schema::{upcast, Schema, INITIAL_VERSION};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// Who and what caused a save, stamped onto every event it appends.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    pub actor_id: Option<String>,
+    pub command_id: Option<String>,
+    pub correlation_id: Option<String>,
+    pub recorded_at: u64,
+}
+
+impl Metadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_actor_id(mut self, actor_id: impl Into<String>) -> Self {
+        self.actor_id = Some(actor_id.into());
+        self
+    }
+
+    pub fn with_command_id(mut self, command_id: impl Into<String>) -> Self {
+        self.command_id = Some(command_id.into());
+        self
+    }
+
+    pub fn with_correlation_id(mut self, correlation_id: impl Into<String>) -> Self {
+        self.correlation_id = Some(correlation_id.into());
+        self
+    }
+
+    /// Sets `recorded_at` to the current time in milliseconds since the unix epoch.
+    pub fn recorded_now(mut self) -> Self {
+        self.recorded_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+        self
+    }
+}
+
+/// A persisted event with its position in the stream and the [`Metadata`] of the save
+/// that appended it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub sequence: usize,
+    pub recorded_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    pub event: T,
+}
+
+impl<T> Envelope<T> {
+    pub fn new(sequence: usize, metadata: &Metadata, event: T) -> Self {
+        Self {
+            sequence,
+            recorded_at: metadata.recorded_at,
+            actor_id: metadata.actor_id.clone(),
+            command_id: metadata.command_id.clone(),
+            correlation_id: metadata.correlation_id.clone(),
+            event,
+        }
+    }
+
+    /// Wraps the events of `stream` from position `from` onwards.
+    pub fn wrap_from(from: usize, stream: Vec<T>, metadata: &Metadata) -> Vec<Self> {
+        Self::wrap(from, stream.into_iter().skip(from), metadata)
+    }
+
+    /// Wraps `events` as appended to a stream from position `sequence` onwards.
+    pub fn wrap(
+        sequence: usize,
+        events: impl IntoIterator<Item = T>,
+        metadata: &Metadata,
+    ) -> Vec<Self> {
+        events
+            .into_iter()
+            .enumerate()
+            .map(|(offset, event)| Self::new(sequence + offset, metadata, event))
+            .collect()
+    }
+}
+
+impl<T: Clone> Envelope<T> {
+    pub fn events(envelopes: &[Self]) -> Vec<T> {
+        envelopes
+            .iter()
+            .map(|envelope| envelope.event.clone())
+            .collect()
+    }
+}
+
+#[derive(Serialize)]
+struct Stored<'a, T> {
+    schema_version: u32,
+    #[serde(flatten)]
+    envelope: &'a Envelope<T>,
+}
+
+/// Serializes an envelope for storage, tagged with the schema version of its event.
+pub fn encode<T>(envelope: &Envelope<T>) -> Result<String, BoxError>
+where
+    T: Schema + Serialize,
+{
+    Ok(serde_json::to_string(&Stored {
+        schema_version: T::VERSION,
+        envelope,
+    })?)
+}
+
+/// Reads back a stored envelope, upcasting its event to the current schema. Events stored
+/// bare, before envelopes were introduced, get `sequence` as their position and no metadata.
+/// Payloads without a schema version were written with [`INITIAL_VERSION`].
+pub fn decode<T: Schema>(payload: &str, sequence: usize) -> Result<Envelope<T>, BoxError> {
+    let payload: Value = serde_json::from_str(payload)?;
+    if payload.get("sequence").is_none() || payload.get("event").is_none() {
+        let event = upcast(payload, INITIAL_VERSION)?;
+        return Ok(Envelope::new(sequence, &Metadata::default(), event));
+    }
+
+    let schema_version = payload
+        .get("schema_version")
+        .and_then(Value::as_u64)
+        .map_or(INITIAL_VERSION, |version| version as u32);
+    let envelope: Envelope<Value> = serde_json::from_value(payload)?;
+    Ok(Envelope {
+        sequence: envelope.sequence,
+        recorded_at: envelope.recorded_at,
+        actor_id: envelope.actor_id,
+        command_id: envelope.command_id,
+        correlation_id: envelope.correlation_id,
+        event: upcast(envelope.event, schema_version)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::Upcasters;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(tag = "type")]
+    enum TestEvent {
+        Happened { value: u8 },
+    }
+
+    fn rename_amount(mut payload: Value) -> Result<Value, BoxError> {
+        let amount = payload["amount"].take();
+        payload["value"] = amount;
+        Ok(payload)
+    }
+
+    impl Schema for TestEvent {
+        const VERSION: u32 = 2;
+
+        fn upcasters() -> Upcasters {
+            Upcasters::new().with(1, rename_amount)
+        }
+    }
+
+    #[test]
+    fn it_should_stamp_the_metadata_on_events_past_the_offset() {
+        let metadata = Metadata::new()
+            .with_actor_id("alice")
+            .with_command_id("command")
+            .recorded_now();
+        let envelopes = Envelope::wrap_from(1, vec![1, 2, 3], &metadata);
+
+        assert_eq!(
+            envelopes
+                .iter()
+                .map(|envelope| envelope.sequence)
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(Envelope::events(&envelopes), vec![2, 3]);
+        assert!(envelopes
+            .iter()
+            .all(|envelope| envelope.actor_id.as_deref() == Some("alice")
+                && envelope.recorded_at == metadata.recorded_at));
+    }
+
+    #[test]
+    fn it_should_read_both_enveloped_and_bare_events() {
+        let envelope = Envelope::new(
+            3,
+            &Metadata::new().with_correlation_id("request"),
+            TestEvent::Happened { value: 1 },
+        );
+        let stored = encode(&envelope).unwrap();
+        assert_eq!(decode::<TestEvent>(&stored, 0).unwrap(), envelope);
+
+        let bare = decode::<TestEvent>(r#"{"type":"Happened","amount":2}"#, 4).unwrap();
+        assert_eq!(
+            bare,
+            Envelope::new(4, &Metadata::default(), TestEvent::Happened { value: 2 })
+        );
+    }
+
+    #[test]
+    fn it_should_upcast_events_stored_with_an_older_schema() {
+        let stored = r#"{"schema_version":1,"sequence":0,"recorded_at":7,"event":{"type":"Happened","amount":2}}"#;
+        let envelope = decode::<TestEvent>(stored, 0).unwrap();
+        assert_eq!(envelope.event, TestEvent::Happened { value: 2 });
+        assert_eq!(envelope.recorded_at, 7);
+    }
+}
@@ -1,6 +1,10 @@
 pub mod command;
+pub mod composite;
 pub mod entity;
+pub mod envelope;
 pub mod query;
+pub mod schema;
+pub mod snapshot;
 pub mod store;
 pub mod transaction;
 pub mod use_case;
@@ -1,3 +1,4 @@
+use crate::snapshot::Snapshots;
 use crate::store::LoadEntity;
 use crate::transaction::NormaliseTo;
 use std::error::Error;
@@ -22,6 +23,7 @@ where
 
 pub struct Query<T> {
     loader: Box<dyn LoadEntity<Vec<T>, Key = String, Error = Box<dyn Error + Send + Sync>>>,
+    snapshots: Snapshots,
 }
 
 impl<T> Query<T>
@@ -34,17 +36,23 @@ where
     {
         Self {
             loader: Box::new(loader),
+            snapshots: Snapshots::new(),
         }
     }
 
+    pub fn with_snapshots(mut self, snapshots: Snapshots) -> Self {
+        self.snapshots = snapshots;
+        self
+    }
+
     pub async fn query<Entity>(&self, key: &str) -> Result<Entity, Box<dyn Error + Send + Sync>>
     where
         Entity: PresentationOf,
+        Entity::Model: 'static,
+        T: 'static,
         Vec<T>: NormaliseTo<Entity::Model> + Default,
     {
-        match self.loader.load(&key.into()).await {
-            Ok(entity) => Ok(entity.unwrap_or_default().render_normalised().present_as()),
-            Err(e) => Err(e),
-        }
+        let (_, model): (_, Entity::Model) = self.snapshots.load(key, &*self.loader).await?;
+        Ok(model.present_as())
     }
 }
@@ -0,0 +1,152 @@
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Display;
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// The version every stored event without one was written with.
+pub const INITIAL_VERSION: u32 = 1;
+
+/// Migrates a payload written with one schema version to the next.
+pub type Upcast = fn(Value) -> Result<Value, BoxError>;
+
+/// An event type whose stored payloads carry a schema version. Bump `VERSION` whenever
+/// the serialized shape changes, and add an upcaster from the previous version.
+pub trait Schema: DeserializeOwned {
+    const VERSION: u32;
+
+    fn upcasters() -> Upcasters {
+        Upcasters::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaError {
+    MissingUpcaster { version: u32 },
+    NewerVersion { version: u32, current: u32 },
+}
+
+impl Error for SchemaError {}
+
+impl Display for SchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaError::MissingUpcaster { version } => {
+                write!(f, "No upcaster from schema version {}", version)
+            }
+            SchemaError::NewerVersion { version, current } => write!(
+                f,
+                "Schema version {} is newer than the current version {}",
+                version, current
+            ),
+        }
+    }
+}
+
+/// A chain of upcasters, each keyed by the version it migrates from.
+#[derive(Default)]
+pub struct Upcasters {
+    steps: BTreeMap<u32, Upcast>,
+}
+
+impl Upcasters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, from_version: u32, upcast: Upcast) -> Self {
+        self.steps.insert(from_version, upcast);
+        self
+    }
+
+    /// Runs every upcaster from `version` up to `current` in order.
+    pub fn upcast(
+        &self,
+        mut payload: Value,
+        version: u32,
+        current: u32,
+    ) -> Result<Value, BoxError> {
+        if version > current {
+            return Err(SchemaError::NewerVersion { version, current }.into());
+        }
+        for from_version in version..current {
+            let upcast = self
+                .steps
+                .get(&from_version)
+                .ok_or(SchemaError::MissingUpcaster {
+                    version: from_version,
+                })?;
+            payload = upcast(payload)?;
+        }
+        Ok(payload)
+    }
+}
+
+/// Deserializes a payload stored with schema `version` as the current shape of `T`.
+pub fn upcast<T: Schema>(payload: Value, version: u32) -> Result<T, BoxError> {
+    let payload = T::upcasters().upcast(payload, version, T::VERSION)?;
+    Ok(serde_json::from_value(payload)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(tag = "type")]
+    enum TestEvent {
+        Voted { vote: u8, at: u64 },
+    }
+
+    fn rename_value(mut payload: Value) -> Result<Value, BoxError> {
+        let value = payload["value"].take();
+        payload["vote"] = value;
+        Ok(payload)
+    }
+
+    fn add_timestamp(mut payload: Value) -> Result<Value, BoxError> {
+        payload["at"] = json!(0);
+        Ok(payload)
+    }
+
+    impl Schema for TestEvent {
+        const VERSION: u32 = 3;
+
+        fn upcasters() -> Upcasters {
+            Upcasters::new()
+                .with(1, rename_value)
+                .with(2, add_timestamp)
+        }
+    }
+
+    #[test]
+    fn it_should_migrate_old_payloads_through_every_upcaster() {
+        let event: TestEvent = upcast(json!({"type": "Voted", "value": 5}), 1).unwrap();
+        assert_eq!(event, TestEvent::Voted { vote: 5, at: 0 });
+
+        let event: TestEvent = upcast(json!({"type": "Voted", "vote": 5}), 2).unwrap();
+        assert_eq!(event, TestEvent::Voted { vote: 5, at: 0 });
+    }
+
+    #[test]
+    fn it_should_leave_current_payloads_alone() {
+        let event: TestEvent = upcast(json!({"type": "Voted", "vote": 5, "at": 9}), 3).unwrap();
+        assert_eq!(event, TestEvent::Voted { vote: 5, at: 9 });
+    }
+
+    #[test]
+    fn it_should_refuse_payloads_from_a_newer_schema() {
+        let error = upcast::<TestEvent>(json!({"type": "Voted"}), 4).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<SchemaError>(),
+            Some(&SchemaError::NewerVersion {
+                version: 4,
+                current: 3
+            })
+        );
+    }
+}
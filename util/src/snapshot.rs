@@ -0,0 +1,551 @@
+use crate::entity::{EventSourced, HandleEvent};
+use crate::store::{LoadEntity, SaveEntity, Tail};
+use crate::transaction::NormaliseTo;
+use async_trait::async_trait;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+pub trait Snapshot: EventSourced + Clone {
+    fn resume<'a>(self, events: impl IntoIterator<Item = &'a Self::Event>) -> Self
+    where
+        Self::Event: 'a;
+}
+
+impl<T, E> Snapshot for T
+where
+    T: HandleEvent<Event = E> + Default + Clone,
+{
+    fn resume<'a>(mut self, events: impl IntoIterator<Item = &'a E>) -> Self
+    where
+        E: 'a,
+    {
+        for event in events {
+            self.apply(event);
+        }
+        self
+    }
+}
+
+/// Numbers the stream an event was appended to, for aggregates sourced from several
+/// streams loaded one after the other.
+pub type StreamOf<E> = fn(&E) -> usize;
+
+/// An aggregate's state after the first `versions[n]` events of each stream `n`. Counting
+/// per stream keeps a snapshot valid when events are appended to a stream loaded before
+/// another, which moves the later stream's events along.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshotted<S> {
+    pub versions: Vec<usize>,
+    pub state: S,
+}
+
+impl<S> Snapshotted<S> {
+    /// How many events the snapshot covers across all its streams.
+    pub fn version(&self) -> usize {
+        self.versions.iter().sum()
+    }
+}
+
+pub trait SnapshotPolicy {
+    fn should_snapshot(&self, snapshot_version: usize, version: usize) -> bool;
+}
+
+impl<T> SnapshotPolicy for T
+where
+    T: Fn(usize, usize) -> bool,
+{
+    fn should_snapshot(&self, snapshot_version: usize, version: usize) -> bool {
+        self(snapshot_version, version)
+    }
+}
+
+pub struct EveryNEvents {
+    interval: usize,
+}
+
+impl EveryNEvents {
+    pub fn new(interval: usize) -> Self {
+        Self { interval }
+    }
+}
+
+impl SnapshotPolicy for EveryNEvents {
+    fn should_snapshot(&self, snapshot_version: usize, version: usize) -> bool {
+        version >= snapshot_version + self.interval
+    }
+}
+
+pub struct Snapshotter<S: EventSourced> {
+    policy: Box<dyn SnapshotPolicy + Send + Sync>,
+    write_store: Box<dyn SaveEntity<Snapshotted<S>, Key = String, Error = BoxError>>,
+    read_store: Box<dyn LoadEntity<Snapshotted<S>, Key = String, Error = BoxError>>,
+    stream_of: StreamOf<S::Event>,
+}
+
+impl<S> Snapshotter<S>
+where
+    S: Snapshot + Send + Sync,
+    S::Event: Send + Sync,
+{
+    pub fn new(
+        policy: impl SnapshotPolicy + Send + Sync + 'static,
+        write_store: impl SaveEntity<Snapshotted<S>, Key = String, Error = BoxError> + 'static,
+        read_store: impl LoadEntity<Snapshotted<S>, Key = String, Error = BoxError> + 'static,
+    ) -> Self {
+        Self {
+            policy: Box::new(policy),
+            write_store: Box::new(write_store),
+            read_store: Box::new(read_store),
+            stream_of: |_| 0,
+        }
+    }
+
+    /// Counts the events of every stream `stream_of` tells apart on their own, for
+    /// aggregates whose events are loaded from several streams.
+    pub fn with_streams(mut self, stream_of: StreamOf<S::Event>) -> Self {
+        self.stream_of = stream_of;
+        self
+    }
+
+    /// Resumes `snapshot` with the events of a stream past the first `from[n]` of each
+    /// stream `n` that it does not cover, counting every stream's events as it goes. A
+    /// snapshot ahead of the events in any stream belongs to a stream that no longer
+    /// exists, and gives `None`.
+    fn resume(
+        &self,
+        snapshot: Snapshotted<S>,
+        from: &[usize],
+        events: &[S::Event],
+    ) -> Option<Snapshotted<S>> {
+        let Snapshotted {
+            versions: covered,
+            state,
+        } = snapshot;
+        let mut versions = from.to_vec();
+        let after = events.iter().filter(|event| {
+            let stream = (self.stream_of)(event);
+            next_position(&mut versions, stream) >= covered.get(stream).copied().unwrap_or(0)
+        });
+        let state = state.resume(after);
+        let ahead = covered
+            .iter()
+            .enumerate()
+            .any(|(stream, version)| *version > versions.get(stream).copied().unwrap_or(0));
+        (!ahead).then_some(Snapshotted { versions, state })
+    }
+
+    fn replay(&self, events: &[S::Event]) -> Snapshotted<S> {
+        let mut versions = Vec::new();
+        let counted = events.iter().inspect(|event| {
+            next_position(&mut versions, (self.stream_of)(event));
+        });
+        let state = S::source(&[]).resume(counted);
+        Snapshotted { versions, state }
+    }
+
+    /// Takes a new snapshot of `resumed` when the policy asks for one.
+    async fn snapshot(
+        &self,
+        key: &String,
+        snapshot_version: usize,
+        resumed: &Snapshotted<S>,
+    ) -> Result<(), BoxError> {
+        if self
+            .policy
+            .should_snapshot(snapshot_version, resumed.version())
+        {
+            self.write_store.save(key, resumed.clone()).await?;
+        }
+        Ok(())
+    }
+
+    /// Resumes the latest snapshot with the events after it, taking a new snapshot when
+    /// the policy asks for one. A snapshot ahead of `events` in any stream belongs to a
+    /// stream that no longer exists and is ignored.
+    pub async fn source(&self, key: &str, events: &[S::Event]) -> Result<S, BoxError> {
+        let key = key.to_string();
+        let snapshot = self.read_store.load(&key).await?;
+        let resumed = snapshot.and_then(|snapshot| {
+            let snapshot_version = snapshot.version();
+            self.resume(snapshot, &[], events)
+                .map(|resumed| (snapshot_version, resumed))
+        });
+        let (snapshot_version, resumed) = resumed.unwrap_or_else(|| (0, self.replay(events)));
+        self.snapshot(&key, snapshot_version, &resumed).await?;
+        Ok(resumed.state)
+    }
+
+    /// Like [`source`](Self::source), but loads only the events after the latest snapshot
+    /// from `events`, along with where they start. The whole stream is only loaded when
+    /// there is no snapshot yet or it is ahead of the stream.
+    pub async fn load(
+        &self,
+        key: &str,
+        events: &dyn LoadEntity<Vec<S::Event>, Key = String, Error = BoxError>,
+    ) -> Result<(Tail<Vec<S::Event>>, S), BoxError> {
+        let key = key.to_string();
+        if let Some(snapshot) = self.read_store.load(&key).await? {
+            let snapshot_version = snapshot.version();
+            let tail = events
+                .load_from(&key, &snapshot.versions)
+                .await?
+                .unwrap_or_default();
+            if let Some(resumed) = self.resume(snapshot, &tail.from, &tail.entity) {
+                self.snapshot(&key, snapshot_version, &resumed).await?;
+                return Ok((tail, resumed.state));
+            }
+        }
+
+        let tail = Tail::whole(events.load(&key).await?.unwrap_or_default());
+        let resumed = self.replay(&tail.entity);
+        self.snapshot(&key, 0, &resumed).await?;
+        Ok((tail, resumed.state))
+    }
+}
+
+/// Counts one more event in `stream`, returning its position there.
+fn next_position(versions: &mut Vec<usize>, stream: usize) -> usize {
+    if versions.len() <= stream {
+        versions.resize(stream + 1, 0);
+    }
+    versions[stream] += 1;
+    versions[stream] - 1
+}
+
+#[async_trait]
+trait SourceFrom<V, T>: Send + Sync {
+    async fn source_from(&self, key: &str, value: &V) -> Result<T, BoxError>;
+
+    async fn load_from(
+        &self,
+        key: &str,
+        store: &dyn LoadEntity<V, Key = String, Error = BoxError>,
+    ) -> Result<(Tail<V>, T), BoxError>;
+}
+
+#[async_trait]
+impl<S> SourceFrom<Vec<S::Event>, S> for Snapshotter<S>
+where
+    S: Snapshot + Send + Sync,
+    S::Event: Send + Sync,
+{
+    async fn source_from(&self, key: &str, value: &Vec<S::Event>) -> Result<S, BoxError> {
+        self.source(key, value).await
+    }
+
+    async fn load_from(
+        &self,
+        key: &str,
+        store: &dyn LoadEntity<Vec<S::Event>, Key = String, Error = BoxError>,
+    ) -> Result<(Tail<Vec<S::Event>>, S), BoxError> {
+        self.load(key, store).await
+    }
+}
+
+/// The snapshotters a [`Transaction`](crate::transaction::Transaction) or
+/// [`Query`](crate::query::Query) sources its aggregates with, one per aggregate type.
+/// Aggregates without a snapshotter are replayed from the first event.
+#[derive(Clone, Default)]
+pub struct Snapshots {
+    snapshotters: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl Snapshots {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with<S>(mut self, snapshotter: Snapshotter<S>) -> Self
+    where
+        S: Snapshot + Send + Sync + 'static,
+        S::Event: Send + Sync + 'static,
+    {
+        let snapshotter: Arc<dyn SourceFrom<Vec<S::Event>, S>> = Arc::new(snapshotter);
+        self.snapshotters
+            .insert(TypeId::of::<S>(), Arc::new(snapshotter));
+        self
+    }
+
+    fn snapshotter<V, T>(&self) -> Option<&Arc<dyn SourceFrom<V, T>>>
+    where
+        V: 'static,
+        T: 'static,
+    {
+        self.snapshotters
+            .get(&TypeId::of::<T>())
+            .and_then(|snapshotter| snapshotter.downcast_ref::<Arc<dyn SourceFrom<V, T>>>())
+    }
+
+    pub async fn source<V, T>(&self, key: &str, value: &V) -> Result<T, BoxError>
+    where
+        V: NormaliseTo<T> + Sync + 'static,
+        T: 'static,
+    {
+        match self.snapshotter::<V, T>() {
+            Some(snapshotter) => snapshotter.source_from(key, value).await,
+            None => Ok(value.render_normalised()),
+        }
+    }
+
+    /// Loads the stream at `key` from `store` and sources `T` from it, along with what was
+    /// loaded. With a snapshotter for `T` only the events after its latest snapshot are
+    /// loaded; without one the whole stream is.
+    pub async fn load<V, T>(
+        &self,
+        key: &str,
+        store: &dyn LoadEntity<V, Key = String, Error = BoxError>,
+    ) -> Result<(Tail<V>, T), BoxError>
+    where
+        V: NormaliseTo<T> + Default + 'static,
+        T: 'static,
+    {
+        match self.snapshotter::<V, T>() {
+            Some(snapshotter) => snapshotter.load_from(key, store).await,
+            None => {
+                let tail = Tail::whole(store.load(&key.to_string()).await?.unwrap_or_default());
+                let normalised = tail.entity.render_normalised();
+                Ok((tail, normalised))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default, Debug, Clone, PartialEq)]
+    struct Sum {
+        total: u32,
+        applied: usize,
+    }
+
+    impl HandleEvent for Sum {
+        type Event = u32;
+
+        fn apply(&mut self, event: &u32) {
+            self.total += event;
+            self.applied += 1;
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct TestSnapshotStore(Arc<Mutex<Option<Snapshotted<Sum>>>>);
+
+    #[async_trait]
+    impl LoadEntity<Snapshotted<Sum>> for TestSnapshotStore {
+        type Key = String;
+        type Error = BoxError;
+
+        async fn load(&self, _key: &String) -> Result<Option<Snapshotted<Sum>>, BoxError> {
+            Ok(self.0.lock().unwrap().clone())
+        }
+    }
+
+    #[async_trait]
+    impl SaveEntity<Snapshotted<Sum>> for TestSnapshotStore {
+        type Key = String;
+        type Error = BoxError;
+
+        async fn save(
+            &self,
+            _key: &String,
+            snapshot: Snapshotted<Sum>,
+        ) -> Result<Snapshotted<Sum>, BoxError> {
+            *self.0.lock().unwrap() = Some(snapshot.clone());
+            Ok(snapshot)
+        }
+    }
+
+    /// Hands out its events, counting every event it is asked to load.
+    #[derive(Default)]
+    struct CountingEventStore {
+        events: Vec<u32>,
+        loaded: Mutex<usize>,
+    }
+
+    #[async_trait]
+    impl LoadEntity<Vec<u32>> for CountingEventStore {
+        type Key = String;
+        type Error = BoxError;
+
+        async fn load(&self, _key: &String) -> Result<Option<Vec<u32>>, BoxError> {
+            *self.loaded.lock().unwrap() += self.events.len();
+            Ok(Some(self.events.clone()))
+        }
+
+        async fn load_from(
+            &self,
+            _key: &String,
+            from: &[usize],
+        ) -> Result<Option<Tail<Vec<u32>>>, BoxError> {
+            let from = from.first().copied().unwrap_or(0).min(self.events.len());
+            *self.loaded.lock().unwrap() += self.events.len() - from;
+            Ok(Some(Tail::of_stream(from, self.events[from..].to_vec())))
+        }
+    }
+
+    fn snapshotter(store: &TestSnapshotStore) -> Snapshotter<Sum> {
+        Snapshotter::new(EveryNEvents::new(3), store.clone(), store.clone())
+    }
+
+    #[tokio::test]
+    async fn it_should_only_apply_the_events_after_the_snapshot() {
+        let store = TestSnapshotStore::default();
+        *store.0.lock().unwrap() = Some(Snapshotted {
+            versions: vec![3],
+            state: Sum {
+                total: 100,
+                applied: 3,
+            },
+        });
+
+        let state = snapshotter(&store)
+            .source("key", &[1, 2, 3, 4])
+            .await
+            .unwrap();
+        assert_eq!(
+            state,
+            Sum {
+                total: 104,
+                applied: 4
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_never_load_the_events_the_snapshot_covers() {
+        let store = TestSnapshotStore::default();
+        *store.0.lock().unwrap() = Some(Snapshotted {
+            versions: vec![3],
+            state: Sum {
+                total: 6,
+                applied: 3,
+            },
+        });
+        let events = CountingEventStore {
+            events: vec![1, 2, 3, 4],
+            ..Default::default()
+        };
+
+        let (tail, state) = snapshotter(&store).load("key", &events).await.unwrap();
+        assert_eq!(tail, Tail::of_stream(3, vec![4]));
+        assert_eq!(
+            state,
+            Sum {
+                total: 10,
+                applied: 4
+            }
+        );
+        assert_eq!(*events.loaded.lock().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn it_should_load_the_whole_stream_when_the_snapshot_is_ahead_of_it() {
+        let store = TestSnapshotStore::default();
+        *store.0.lock().unwrap() = Some(Snapshotted {
+            versions: vec![10],
+            state: Sum {
+                total: 100,
+                applied: 10,
+            },
+        });
+        let events = CountingEventStore {
+            events: vec![1, 2],
+            ..Default::default()
+        };
+
+        let (tail, state) = snapshotter(&store).load("key", &events).await.unwrap();
+        assert_eq!(tail, Tail::whole(vec![1, 2]));
+        assert_eq!(
+            state,
+            Sum {
+                total: 3,
+                applied: 2
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_take_a_snapshot_every_n_events() {
+        let store = TestSnapshotStore::default();
+        let snapshotter = snapshotter(&store);
+
+        snapshotter.source("key", &[1, 2]).await.unwrap();
+        assert_eq!(*store.0.lock().unwrap(), None);
+
+        snapshotter.source("key", &[1, 2, 3]).await.unwrap();
+        snapshotter.source("key", &[1, 2, 3, 4]).await.unwrap();
+        assert_eq!(
+            *store.0.lock().unwrap(),
+            Some(Snapshotted {
+                versions: vec![3],
+                state: Sum {
+                    total: 6,
+                    applied: 3
+                }
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_ignore_a_snapshot_ahead_of_the_events() {
+        let store = TestSnapshotStore::default();
+        *store.0.lock().unwrap() = Some(Snapshotted {
+            versions: vec![10],
+            state: Sum {
+                total: 100,
+                applied: 10,
+            },
+        });
+
+        let state = snapshotter(&store).source("key", &[1, 2]).await.unwrap();
+        assert_eq!(
+            state,
+            Sum {
+                total: 3,
+                applied: 2
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_resume_each_stream_from_its_own_version() {
+        let store = TestSnapshotStore::default();
+        let snapshotter = snapshotter(&store).with_streams(|event| (*event >= 100) as usize);
+
+        // Events of stream 0 are loaded before those of stream 1.
+        snapshotter.source("key", &[1, 100, 200]).await.unwrap();
+        assert_eq!(
+            store
+                .0
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|snapshot| &snapshot.versions),
+            Some(&vec![1, 2])
+        );
+
+        let events = [1, 2, 100, 200, 300];
+        let state = snapshotter.source("key", &events).await.unwrap();
+        assert_eq!(state, Sum::source(&events));
+    }
+
+    #[tokio::test]
+    async fn it_should_replay_aggregates_without_a_snapshotter() {
+        let store = TestSnapshotStore::default();
+        let snapshots = Snapshots::new().with(snapshotter(&store));
+        let empty = Snapshots::new();
+        let events = vec![1, 2, 3];
+
+        let sourced: Sum = snapshots.source("key", &events).await.unwrap();
+        let replayed: Sum = empty.source("key", &events).await.unwrap();
+        assert_eq!(sourced, replayed);
+        assert!(store.0.lock().unwrap().is_some());
+    }
+}
@@ -1,10 +1,25 @@
+use crate::envelope::Metadata;
 use async_trait::async_trait;
+use std::fmt::Display;
 
 #[async_trait]
 pub trait LoadEntity<Entity>: Send + Sync {
     type Key: Send + Sync + 'static;
     type Error: Send + Sync + 'static;
     async fn load(&self, key: &Self::Key) -> Result<Option<Entity>, Self::Error>;
+
+    /// Loads what is stored at `key` past the first `from[n]` events of each sub-stream
+    /// `n`. Stores that cannot skip events load everything, skipping none.
+    async fn load_from(
+        &self,
+        key: &Self::Key,
+        _from: &[usize],
+    ) -> Result<Option<Tail<Entity>>, Self::Error>
+    where
+        Entity: Send + 'async_trait,
+    {
+        Ok(self.load(key).await?.map(Tail::whole))
+    }
 }
 
 #[async_trait]
@@ -12,4 +27,120 @@ pub trait SaveEntity<Entity>: Send + Sync {
     type Key: Send + Sync + 'static;
     type Error: Send + Sync + 'static;
     async fn save(&self, key: &Self::Key, entity: Entity) -> Result<Entity, Self::Error>;
+
+    /// Saves `entity` only if the stored version is still `expected_version`, failing
+    /// with [`Conflict`] otherwise, and stamps `metadata` on what it appends. Stores that
+    /// cannot check the version or keep metadata just save.
+    async fn save_versioned(
+        &self,
+        key: &Self::Key,
+        entity: Entity,
+        _expected_version: usize,
+        _metadata: &Metadata,
+    ) -> Result<Entity, Self::Error>
+    where
+        Entity: Send + 'async_trait,
+    {
+        self.save(key, entity).await
+    }
+
+    /// Saves the events past `expected_version` of a `tail` loaded with
+    /// [`LoadEntity::load_from`], checking the version as `save_versioned` does.
+    /// `expected_version` counts every event of the stream, skipped or not. Stores that
+    /// load whole streams save the entity whole.
+    async fn save_tail(
+        &self,
+        key: &Self::Key,
+        tail: Tail<Entity>,
+        expected_version: usize,
+        metadata: &Metadata,
+    ) -> Result<Tail<Entity>, Self::Error>
+    where
+        Entity: Send + 'async_trait,
+    {
+        let Tail { from, entity } = tail;
+        let entity = self
+            .save_versioned(key, entity, expected_version, metadata)
+            .await?;
+        Ok(Tail { from, entity })
+    }
+}
+
+/// What a stream holds past the first `from[n]` events of each of its sub-streams `n`.
+/// A stream loaded whole skips nothing and has an empty `from`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Tail<Entity> {
+    pub from: Vec<usize>,
+    pub entity: Entity,
+}
+
+impl<Entity> Tail<Entity> {
+    pub fn whole(entity: Entity) -> Self {
+        Self {
+            from: Vec::new(),
+            entity,
+        }
+    }
+
+    /// How many events were skipped across all sub-streams.
+    pub fn skipped(&self) -> usize {
+        self.from.iter().sum()
+    }
+}
+
+impl<T> Tail<Vec<T>> {
+    /// The events of a single stream tail from position `from` on, where the stream holds
+    /// at least `from` events.
+    pub fn of_stream(from: usize, events: Vec<T>) -> Self {
+        Self {
+            from: vec![from],
+            entity: events,
+        }
+    }
+
+    /// The events past the first `version` of the stream.
+    pub fn after(&self, version: usize) -> &[T] {
+        let skip = version.saturating_sub(self.skipped());
+        &self.entity[skip.min(self.entity.len())..]
+    }
+}
+
+pub trait Versioned {
+    fn version(&self) -> usize;
+}
+
+impl<T> Versioned for Vec<T> {
+    fn version(&self) -> usize {
+        self.len()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub expected_version: usize,
+    pub actual_version: usize,
+}
+
+impl Conflict {
+    pub fn check(expected_version: usize, actual_version: usize) -> Result<(), Conflict> {
+        match expected_version == actual_version {
+            true => Ok(()),
+            false => Err(Conflict {
+                expected_version,
+                actual_version,
+            }),
+        }
+    }
+}
+
+impl std::error::Error for Conflict {}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Expected stream version {} but found {}",
+            self.expected_version, self.actual_version
+        )
+    }
 }
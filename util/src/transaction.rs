@@ -4,7 +4,9 @@ mod process;
 pub mod retry;
 mod update_with;
 
-use crate::store::{LoadEntity, SaveEntity};
+use crate::envelope::Metadata;
+use crate::snapshot::Snapshots;
+use crate::store::{LoadEntity, SaveEntity, Tail, Versioned};
 use crate::transaction::process::process;
 use crate::transaction::retry::{Instruction, RetryPolicyService, RetryStrategy};
 pub use normalise_to::NormaliseTo;
@@ -18,6 +20,7 @@ pub struct Transaction<V> {
         Box<dyn SaveEntity<V, Key = String, Error = Box<dyn Error + Send + Sync + 'static>>>,
     read_store:
         Box<dyn LoadEntity<V, Key = String, Error = Box<dyn Error + Send + Sync + 'static>>>,
+    snapshots: Snapshots,
 }
 
 impl<V> Transaction<V> {
@@ -32,25 +35,42 @@ impl<V> Transaction<V> {
             retry_policy_service: RetryPolicyService::new(retry_statergy),
             write_store: Box::new(write_store),
             read_store: Box::new(read_store),
+            snapshots: Snapshots::new(),
         }
     }
 
+    pub fn with_snapshots(mut self, snapshots: Snapshots) -> Self {
+        self.snapshots = snapshots;
+        self
+    }
+
+    /// Runs `operation` against the aggregate at `key` and saves its result, retrying as
+    /// the strategy instructs. Every attempt stamps `metadata` with its own time.
     pub async fn execute<T, U>(
         &self,
         key: &str,
         operation: &impl Operation<T, U>,
+        metadata: &Metadata,
     ) -> Result<V::UpdateResponse, Box<dyn Error + Send + Sync>>
     where
-        V: NormaliseTo<T> + UpdateWith<U> + Default,
+        V: NormaliseTo<T> + UpdateWith<U> + Versioned + Default + Send + Sync + 'static,
+        T: Send + 'static,
     {
         let mut retry_policy = self.retry_policy_service.generate_policy();
         loop {
-            let result: Result<V::UpdateResponse, Box<dyn Error + Send + Sync>> =
-                try_operation(&*self.read_store, &*self.write_store, key, operation).await;
+            let result = try_operation(
+                &*self.read_store,
+                &*self.write_store,
+                &self.snapshots,
+                key,
+                operation,
+                &metadata.clone().recorded_now(),
+            )
+            .await;
             match result {
                 Ok(result) => break Ok(result),
                 Err(error) => {
-                    let instruction = retry_policy.retry();
+                    let instruction = retry_policy.retry(&*error);
                     match instruction {
                         Instruction::Retry(delay) => {
                             tokio::time::sleep(delay).await;
@@ -64,35 +84,43 @@ impl<V> Transaction<V> {
     }
 }
 
-async fn try_operation<V, T, U, E>(
-    load_entity: &(impl LoadEntity<V, Key = String, Error = E> + ?Sized),
-    save_entity: &(impl SaveEntity<V, Key = String, Error = E> + ?Sized),
+async fn try_operation<V, T, U>(
+    load_entity: &dyn LoadEntity<V, Key = String, Error = Box<dyn Error + Send + Sync>>,
+    save_entity: &(impl SaveEntity<V, Key = String, Error = Box<dyn Error + Send + Sync>> + ?Sized),
+    snapshots: &Snapshots,
     key: &str,
     operation: &impl Operation<T, U>,
-) -> Result<V::UpdateResponse, E>
+    metadata: &Metadata,
+) -> Result<V::UpdateResponse, Box<dyn Error + Send + Sync>>
 where
-    V: NormaliseTo<T> + UpdateWith<U> + Default,
+    V: NormaliseTo<T> + UpdateWith<U> + Versioned + Default + Send + Sync + 'static,
+    T: Send + 'static,
 {
-    match load_entity
-        .load(&key.into())
+    let (Tail { from, entity }, normalised) = snapshots.load(key, load_entity).await?;
+    let expected_version = from.iter().sum::<usize>() + entity.version();
+    let process_result = process(entity, &normalised, operation);
+    save_entity
+        .save_tail(
+            &key.into(),
+            Tail {
+                from,
+                entity: process_result.value,
+            },
+            expected_version,
+            metadata,
+        )
         .await
-        .map(|value| value.unwrap_or_default())
-        .map(|value| process(value, operation))
-        .map(|process_result| async {
-            save_entity
-                .save(&key.into(), process_result.value)
-                .await
-                .map(|_| process_result.update_response)
-        }) {
-        Ok(result) => result.await,
-        Err(error) => Err(error),
-    }
+        .map(|_| process_result.update_response)
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison, clippy::unnecessary_to_owned)]
 mod test_try_operation {
+    use crate::envelope::Metadata;
+    use crate::snapshot::Snapshots;
     use crate::store::LoadEntity;
     use crate::store::SaveEntity;
+    use crate::store::Versioned;
     use crate::transaction::normalise_to::NormaliseTo;
     use crate::transaction::operation::Operation;
     use crate::transaction::try_operation;
@@ -125,6 +153,12 @@ mod test_try_operation {
         }
     }
 
+    impl Versioned for TestEntity {
+        fn version(&self) -> usize {
+            0
+        }
+    }
+
     struct TestOperation;
 
     impl Operation<String, String> for TestOperation {
@@ -171,8 +205,10 @@ mod test_try_operation {
         let result = try_operation(
             &load_entity,
             &save_entity,
+            &Snapshots::new(),
             &"key".to_string(),
             &TestOperation,
+            &Metadata::default(),
         )
         .await;
         assert_eq!(result.is_ok(), true);
@@ -202,8 +238,10 @@ mod test_try_operation {
         let result = try_operation(
             &load_entity,
             &save_entity,
+            &Snapshots::new(),
             &"key".to_string(),
             &TestOperation,
+            &Metadata::default(),
         )
         .await;
         assert_eq!(result.is_err(), true);
@@ -231,8 +269,10 @@ mod test_try_operation {
         let result = try_operation(
             &load_entity,
             &save_entity,
+            &Snapshots::new(),
             &"key".to_string(),
             &TestOperation,
+            &Metadata::default(),
         )
         .await;
         assert_eq!(result.is_err(), true);
@@ -240,16 +280,19 @@ mod test_try_operation {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison, clippy::unnecessary_to_owned)]
 mod test_transaction {
+    use crate::envelope::Metadata;
     use crate::store::LoadEntity;
     use crate::store::SaveEntity;
+    use crate::store::{Conflict, Versioned};
     use crate::transaction::normalise_to::NormaliseTo;
     use crate::transaction::operation::Operation;
-    use crate::transaction::retry::Instruction;
+    use crate::transaction::retry::{Instruction, RetryContext};
     use crate::transaction::update_with::UpdateWith;
     use crate::transaction::Transaction;
     use std::error::Error;
-    use std::sync::Mutex;
+    use std::sync::{Arc, Mutex};
     use std::time::Duration;
 
     struct TestEntity {
@@ -278,6 +321,12 @@ mod test_transaction {
         }
     }
 
+    impl Versioned for TestEntity {
+        fn version(&self) -> usize {
+            0
+        }
+    }
+
     struct TestOperation;
 
     impl Operation<String, String> for TestOperation {
@@ -321,13 +370,13 @@ mod test_transaction {
     async fn it_should_load_perform_operation_and_save() {
         let load_entity = TestLoadEntity {};
         let save_entity = TestSaveEntity {};
-        let retry_strategy = |_previous_instruction: &Option<Instruction>, _attempt: &u8| {
-            Instruction::Retry(Duration::from_millis(0))
-        };
+        let retry_strategy = |_context: &RetryContext| Instruction::Retry(Duration::from_millis(0));
         let transaction = Transaction::<TestEntity>::new(retry_strategy, save_entity, load_entity);
 
         let operation = TestOperation {};
-        let result = transaction.execute(&"key".to_string(), &operation).await;
+        let result = transaction
+            .execute(&"key".to_string(), &operation, &Metadata::default())
+            .await;
         assert_eq!(result.is_ok(), true);
         assert_eq!(result.unwrap(), "update-response".to_string());
     }
@@ -352,12 +401,13 @@ mod test_transaction {
 
         let load_entity = TestLoadEntity {};
         let save_entity = TestSaveEntityWithError {};
-        let retry_strategy =
-            |_previous_instruction: &Option<Instruction>, _attempt: &u8| Instruction::Abort;
+        let retry_strategy = |_context: &RetryContext| Instruction::Abort;
         let transaction = Transaction::<TestEntity>::new(retry_strategy, save_entity, load_entity);
 
         let operation = TestOperation {};
-        let result = transaction.execute(&"key".to_string(), &operation).await;
+        let result = transaction
+            .execute(&"key".to_string(), &operation, &Metadata::default())
+            .await;
         assert_eq!(result.is_err(), true);
         assert_eq!(result.unwrap_err().to_string(), "error".to_string());
     }
@@ -405,13 +455,154 @@ mod test_transaction {
 
         let load_entity = TestLoadEntity {};
         let save_entity = TestSaveEntityWithError(Mutex::new(Counter::new()));
-        let retry_strategy = |_previous_instruction: &Option<Instruction>, _attempt: &u8| {
-            Instruction::Retry(Duration::from_millis(0))
-        };
+        let retry_strategy = |_context: &RetryContext| Instruction::Retry(Duration::from_millis(0));
         let transaction = Transaction::<TestEntity>::new(retry_strategy, save_entity, load_entity);
 
         let operation = TestOperation {};
-        let result = transaction.execute(&"key".to_string(), &operation).await;
+        let result = transaction
+            .execute(&"key".to_string(), &operation, &Metadata::default())
+            .await;
         assert_eq!(result.is_ok(), true);
     }
+
+    #[tokio::test]
+    async fn it_should_retry_when_another_writer_saved_first() {
+        #[derive(Default)]
+        struct Stream(Vec<String>);
+
+        impl NormaliseTo<usize> for Stream {
+            fn render_normalised(&self) -> usize {
+                self.0.len()
+            }
+        }
+
+        impl UpdateWith<String> for Stream {
+            type UpdateResponse = usize;
+            fn update_with(&mut self, update: String) -> Self::UpdateResponse {
+                self.0.push(update);
+                self.0.len()
+            }
+        }
+
+        impl Versioned for Stream {
+            fn version(&self) -> usize {
+                self.0.len()
+            }
+        }
+
+        #[derive(Clone, Default)]
+        struct StreamStore {
+            events: Arc<Mutex<Vec<String>>>,
+            interleaved: Arc<Mutex<bool>>,
+        }
+
+        #[async_trait::async_trait]
+        impl LoadEntity<Stream> for StreamStore {
+            type Key = String;
+            type Error = Box<dyn Error + Send + Sync + 'static>;
+
+            async fn load(
+                &self,
+                _key: &String,
+            ) -> Result<Option<Stream>, Box<dyn Error + Send + Sync + 'static>> {
+                Ok(Some(Stream(self.events.lock().unwrap().clone())))
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl SaveEntity<Stream> for StreamStore {
+            type Key = String;
+            type Error = Box<dyn Error + Send + Sync + 'static>;
+
+            async fn save(
+                &self,
+                _key: &String,
+                value: Stream,
+            ) -> Result<Stream, Box<dyn Error + Send + Sync + 'static>> {
+                *self.events.lock().unwrap() = value.0.clone();
+                Ok(value)
+            }
+
+            async fn save_versioned(
+                &self,
+                _key: &String,
+                value: Stream,
+                expected_version: usize,
+                _metadata: &Metadata,
+            ) -> Result<Stream, Box<dyn Error + Send + Sync + 'static>> {
+                let mut events = self.events.lock().unwrap();
+                let mut interleaved = self.interleaved.lock().unwrap();
+                if !*interleaved {
+                    *interleaved = true;
+                    events.push("concurrent".to_string());
+                }
+                Conflict::check(expected_version, events.len())?;
+                *events = value.0.clone();
+                Ok(value)
+            }
+        }
+
+        let store = StreamStore::default();
+        let retry_strategy = |context: &RetryContext| match context.retry_count {
+            0 => Instruction::Retry(Duration::from_millis(0)),
+            _ => Instruction::Abort,
+        };
+        let transaction = Transaction::<Stream>::new(retry_strategy, store.clone(), store.clone());
+        let operation = |len: &usize| format!("event-{}", len);
+
+        let result = transaction
+            .execute("key", &operation, &Metadata::default())
+            .await;
+        assert_eq!(result.unwrap(), 2);
+        assert_eq!(
+            *store.events.lock().unwrap(),
+            vec!["concurrent".to_string(), "event-1".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_return_the_conflict_when_the_retry_strategy_says_stop() {
+        struct ConflictingSaveEntity;
+
+        #[async_trait::async_trait]
+        impl SaveEntity<TestEntity> for ConflictingSaveEntity {
+            type Key = String;
+            type Error = Box<dyn Error + Send + Sync + 'static>;
+
+            async fn save(
+                &self,
+                _key: &String,
+                value: TestEntity,
+            ) -> Result<TestEntity, Box<dyn Error + Send + Sync + 'static>> {
+                Ok(value)
+            }
+
+            async fn save_versioned(
+                &self,
+                _key: &String,
+                _value: TestEntity,
+                expected_version: usize,
+                _metadata: &Metadata,
+            ) -> Result<TestEntity, Box<dyn Error + Send + Sync + 'static>> {
+                Err(Conflict::check(expected_version, expected_version + 1)
+                    .unwrap_err()
+                    .into())
+            }
+        }
+
+        let retry_strategy = |_context: &RetryContext| Instruction::Abort;
+        let transaction =
+            Transaction::<TestEntity>::new(retry_strategy, ConflictingSaveEntity, TestLoadEntity);
+
+        let result = transaction
+            .execute("key", &TestOperation, &Metadata::default())
+            .await;
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<Conflict>(),
+            Some(&Conflict {
+                expected_version: 0,
+                actual_version: 1
+            })
+        );
+    }
 }
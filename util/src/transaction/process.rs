@@ -1,16 +1,15 @@
-use crate::transaction::normalise_to::NormaliseTo;
 use crate::transaction::operation::Operation;
 use crate::transaction::update_with::UpdateWith;
 
 pub fn process<V, R, U>(
     mut input: V,
+    normalised: &R,
     operation: &impl Operation<R, U>,
 ) -> ProcessResult<V, V::UpdateResponse>
 where
-    V: NormaliseTo<R> + UpdateWith<U>,
+    V: UpdateWith<U>,
 {
-    let normalised = input.render_normalised();
-    let update_response = operation.operate_on(&normalised);
+    let update_response = operation.operate_on(normalised);
     let update_result = input.update_with(update_response);
     ProcessResult {
         value: input,
@@ -27,12 +26,6 @@ pub struct ProcessResult<V, U> {
 mod tests {
     use super::*;
 
-    impl NormaliseTo<i32> for i32 {
-        fn render_normalised(&self) -> i32 {
-            self.clone()
-        }
-    }
-
     impl UpdateWith<i32> for i32 {
         type UpdateResponse = i32;
         fn update_with(&mut self, update_value: i32) -> Self::UpdateResponse {
@@ -45,7 +38,7 @@ mod tests {
     fn test_process() {
         let input = 0;
         let operation = |input: &i32| input + 1;
-        let result = process(input, &operation);
+        let result = process(input, &input, &operation);
         assert_eq!(result.value, 1);
         assert_eq!(result.update_response, 1);
     }
@@ -1,24 +1,32 @@
+use crate::store::Conflict;
+use rand::Rng;
+use std::error::Error;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+pub struct RetryContext<'a> {
+    pub error: &'a (dyn Error + Send + Sync + 'static),
+    pub previous_instruction: &'a Option<Instruction>,
+    pub retry_count: u32,
+    pub elapsed: Duration,
+}
+
+impl RetryContext<'_> {
+    pub fn is_conflict(&self) -> bool {
+        self.error.downcast_ref::<Conflict>().is_some()
+    }
+}
 
 pub trait RetryStrategy {
-    fn should_retry(
-        &self,
-        previous_instruction: &Option<Instruction>,
-        retry_count: &u8,
-    ) -> Instruction;
+    fn should_retry(&self, context: &RetryContext) -> Instruction;
 }
 
 impl<T> RetryStrategy for T
 where
-    T: Fn(&Option<Instruction>, &u8) -> Instruction,
+    T: Fn(&RetryContext) -> Instruction,
 {
-    fn should_retry(
-        &self,
-        previous_instruction: &Option<Instruction>,
-        retry_count: &u8,
-    ) -> Instruction {
-        self(previous_instruction, retry_count)
+    fn should_retry(&self, context: &RetryContext) -> Instruction {
+        self(context)
     }
 }
 
@@ -26,13 +34,8 @@ impl<T> RetryStrategy for Arc<T>
 where
     T: RetryStrategy + ?Sized,
 {
-    fn should_retry(
-        &self,
-        previous_instruction: &Option<Instruction>,
-        retry_count: &u8,
-    ) -> Instruction {
-        self.as_ref()
-            .should_retry(previous_instruction, retry_count)
+    fn should_retry(&self, context: &RetryContext) -> Instruction {
+        self.as_ref().should_retry(context)
     }
 }
 
@@ -54,8 +57,9 @@ impl RetryPolicyService {
 
 pub struct RetryPolicy {
     strategy: Box<dyn RetryStrategy + Send>,
-    retry_count: u8,
+    retry_count: u32,
     instruction: Option<Instruction>,
+    started: Instant,
 }
 
 impl RetryPolicy {
@@ -64,16 +68,20 @@ impl RetryPolicy {
             strategy: Box::new(strategy),
             retry_count: 0,
             instruction: None,
+            started: Instant::now(),
         }
     }
 }
 
 impl RetryPolicy {
-    pub fn retry(&mut self) -> Instruction {
-        let instruction = self
-            .strategy
-            .should_retry(&self.instruction, &self.retry_count);
-        self.retry_count += 1;
+    pub fn retry(&mut self, error: &(dyn Error + Send + Sync + 'static)) -> Instruction {
+        let instruction = self.strategy.should_retry(&RetryContext {
+            error,
+            previous_instruction: &self.instruction,
+            retry_count: self.retry_count,
+            elapsed: self.started.elapsed(),
+        });
+        self.retry_count = self.retry_count.saturating_add(1);
         self.instruction = Some(instruction);
         self.instruction.clone().unwrap()
     }
@@ -85,33 +93,128 @@ pub enum Instruction {
     Abort,
 }
 
+/// Retries up to `max_retries` times, waiting the same delay each time.
+pub struct FixedDelay {
+    delay: Duration,
+    max_retries: u32,
+}
+
+impl FixedDelay {
+    pub fn new(delay: Duration, max_retries: u32) -> Self {
+        Self { delay, max_retries }
+    }
+}
+
+impl RetryStrategy for FixedDelay {
+    fn should_retry(&self, context: &RetryContext) -> Instruction {
+        match context.retry_count < self.max_retries {
+            true => Instruction::Retry(self.delay),
+            false => Instruction::Abort,
+        }
+    }
+}
+
+/// Doubles the delay on every retry up to `max_delay`, picking a random delay between
+/// zero and that bound ("full jitter") so that writers that collided once do not collide
+/// again. Gives up once `max_elapsed` has passed since the first attempt.
+pub struct ExponentialBackoff {
+    initial_delay: Duration,
+    max_delay: Duration,
+    max_elapsed: Duration,
+}
+
+impl ExponentialBackoff {
+    pub fn new(initial_delay: Duration, max_delay: Duration, max_elapsed: Duration) -> Self {
+        Self {
+            initial_delay,
+            max_delay,
+            max_elapsed,
+        }
+    }
+
+    fn ceiling(&self, retry_count: u32) -> Duration {
+        self.initial_delay
+            .checked_mul(2u32.saturating_pow(retry_count))
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+}
+
+impl RetryStrategy for ExponentialBackoff {
+    fn should_retry(&self, context: &RetryContext) -> Instruction {
+        if context.elapsed >= self.max_elapsed {
+            return Instruction::Abort;
+        }
+        let ceiling = self.ceiling(context.retry_count);
+        let delay = rand::thread_rng().gen_range(Duration::ZERO..=ceiling);
+        Instruction::Retry(delay.min(self.max_elapsed - context.elapsed))
+    }
+}
+
+/// The most retries [`RetryOnConflict`] allows, whatever the wrapped strategy says.
+pub const MAX_CONFLICT_RETRIES: u32 = 64;
+
+/// Defers to the wrapped strategy for [`Conflict`]s and aborts on any other error,
+/// since retrying a failing store or a bad command only repeats the failure. Gives up
+/// after [`MAX_CONFLICT_RETRIES`] even if the wrapped strategy never would.
+pub struct RetryOnConflict<S> {
+    strategy: S,
+}
+
+impl<S> RetryOnConflict<S> {
+    pub fn new(strategy: S) -> Self {
+        Self { strategy }
+    }
+}
+
+impl<S> RetryStrategy for RetryOnConflict<S>
+where
+    S: RetryStrategy,
+{
+    fn should_retry(&self, context: &RetryContext) -> Instruction {
+        match context.is_conflict() && context.retry_count < MAX_CONFLICT_RETRIES {
+            true => self.strategy.should_retry(context),
+            false => Instruction::Abort,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test_retry_policy {
     use super::*;
 
     #[test]
     pub fn it_should_carry_retry_state_into_next_retry_call() {
-        let mut policy =
-            RetryPolicy::new(|previous_instruction: &Option<Instruction>, count: &u8| {
-                if *count == 0 {
-                    Instruction::Retry(Duration::from_secs(1))
-                } else {
-                    assert_eq!(
-                        previous_instruction,
-                        &Some(Instruction::Retry(Duration::from_secs(1)))
-                    );
-                    Instruction::Abort
-                }
-            });
+        let mut policy = RetryPolicy::new(|context: &RetryContext| {
+            if context.retry_count == 0 {
+                Instruction::Retry(Duration::from_secs(1))
+            } else {
+                assert_eq!(
+                    context.previous_instruction,
+                    &Some(Instruction::Retry(Duration::from_secs(1)))
+                );
+                Instruction::Abort
+            }
+        });
+        let error = std::io::Error::other("error");
 
         {
-            let instruction = policy.retry();
+            let instruction = policy.retry(&error);
             assert_eq!(instruction, Instruction::Retry(Duration::from_secs(1)));
         }
 
-        let instruction_2 = policy.retry();
+        let instruction_2 = policy.retry(&error);
         assert_eq!(instruction_2, Instruction::Abort);
     }
+
+    #[test]
+    pub fn it_should_not_overflow_when_the_strategy_never_gives_up() {
+        let mut policy =
+            RetryPolicy::new(|_context: &RetryContext| Instruction::Retry(Duration::ZERO));
+        policy.retry_count = u32::MAX;
+
+        policy.retry(&std::io::Error::other("error"));
+        assert_eq!(policy.retry_count, u32::MAX);
+    }
 }
 
 #[cfg(test)]
@@ -120,14 +223,113 @@ mod test_retry_service {
 
     #[test]
     pub fn it_should_generate_retry_policy() {
-        let service =
-            RetryPolicyService::new(|_previous_instruction: &Option<Instruction>, _count: &u8| {
-                Instruction::Abort
-            });
+        let service = RetryPolicyService::new(|_context: &RetryContext| Instruction::Abort);
 
         let mut policy = service.generate_policy();
 
-        let instruction = policy.retry();
+        let instruction = policy.retry(&std::io::Error::other("error"));
         assert_eq!(instruction, Instruction::Abort);
     }
 }
+
+#[cfg(test)]
+mod test_strategies {
+    use super::*;
+
+    fn context<'a>(
+        error: &'a (dyn Error + Send + Sync + 'static),
+        retry_count: u32,
+    ) -> RetryContext<'a> {
+        RetryContext {
+            error,
+            previous_instruction: &None,
+            retry_count,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    fn conflict() -> Conflict {
+        Conflict {
+            expected_version: 0,
+            actual_version: 1,
+        }
+    }
+
+    #[test]
+    fn fixed_delay_should_stop_after_max_retries() {
+        let strategy = FixedDelay::new(Duration::from_millis(5), 2);
+        let error = conflict();
+
+        assert_eq!(
+            strategy.should_retry(&context(&error, 1)),
+            Instruction::Retry(Duration::from_millis(5))
+        );
+        assert_eq!(
+            strategy.should_retry(&context(&error, 2)),
+            Instruction::Abort
+        );
+    }
+
+    #[test]
+    fn exponential_backoff_should_stay_within_the_doubled_delay() {
+        let strategy = ExponentialBackoff::new(
+            Duration::from_millis(10),
+            Duration::from_millis(50),
+            Duration::from_secs(1),
+        );
+        let error = conflict();
+
+        for (retry_count, ceiling) in [(0, 10), (1, 20), (2, 40), (3, 50), (200, 50)] {
+            match strategy.should_retry(&context(&error, retry_count)) {
+                Instruction::Retry(delay) => assert!(delay <= Duration::from_millis(ceiling)),
+                Instruction::Abort => panic!("expected a retry"),
+            }
+        }
+    }
+
+    #[test]
+    fn exponential_backoff_should_give_up_after_max_elapsed() {
+        let strategy = ExponentialBackoff::new(
+            Duration::from_millis(10),
+            Duration::from_millis(50),
+            Duration::from_millis(100),
+        );
+        let error = conflict();
+        let context = RetryContext {
+            elapsed: Duration::from_millis(100),
+            ..context(&error, 1)
+        };
+
+        assert_eq!(strategy.should_retry(&context), Instruction::Abort);
+    }
+
+    #[test]
+    fn retry_on_conflict_should_give_up_after_max_conflict_retries() {
+        let strategy =
+            RetryOnConflict::new(|_context: &RetryContext| Instruction::Retry(Duration::ZERO));
+        let error = conflict();
+
+        assert_eq!(
+            strategy.should_retry(&context(&error, MAX_CONFLICT_RETRIES - 1)),
+            Instruction::Retry(Duration::ZERO)
+        );
+        assert_eq!(
+            strategy.should_retry(&context(&error, MAX_CONFLICT_RETRIES)),
+            Instruction::Abort
+        );
+    }
+
+    #[test]
+    fn retry_on_conflict_should_abort_on_other_errors() {
+        let strategy = RetryOnConflict::new(FixedDelay::new(Duration::ZERO, 3));
+
+        assert_eq!(
+            strategy.should_retry(&context(&conflict(), 0)),
+            Instruction::Retry(Duration::ZERO)
+        );
+        assert_eq!(
+            strategy.should_retry(&context(&std::io::Error::other("error"), 0)),
+            Instruction::Abort
+        );
+    }
+}
@@ -1,7 +1,15 @@
+mod dedupe;
+
 use crate::command::Command;
 use crate::entity::EventSourced;
+use crate::envelope::Metadata;
 use crate::transaction::{NormaliseTo, Transaction, UpdateWith};
+use dedupe::{DedupeWindow, Outcome};
+use std::collections::hash_map::DefaultHasher;
 use std::error::Error;
+use std::fmt::Display;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 
 impl<T, U> NormaliseTo<U> for Vec<T>
 where
@@ -26,31 +34,201 @@ where
     }
 }
 
+/// A command id was sent again with a different command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandIdReused {
+    pub command_id: String,
+}
+
+impl Error for CommandIdReused {}
+
+impl Display for CommandIdReused {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Command id {} was used for another command",
+            self.command_id
+        )
+    }
+}
+
 pub struct UseCase<T> {
     transaction: Transaction<Vec<T>>,
+    dedupe: Option<DedupeWindow>,
 }
 
 impl<T> UseCase<T>
 where
-    T: Clone,
+    T: Clone + Send + Sync + 'static,
 {
     pub fn new(transaction: Transaction<Vec<T>>) -> Self {
-        Self { transaction }
+        Self {
+            transaction,
+            dedupe: None,
+        }
+    }
+
+    /// Remembers the last `capacity` command ids of each of the `max_keys` most recently
+    /// used keys, so that a command repeated with the same `command_id` and `actor_id` in
+    /// its metadata gets the original response instead of being applied again. Callers
+    /// set `actor_id` to the identity they verified, so that nobody can replay another
+    /// actor's ids. Reusing an id for a different command fails with [`CommandIdReused`].
+    /// Commands that failed are not remembered.
+    pub fn with_dedupe_window(mut self, capacity: usize, max_keys: usize) -> Self {
+        self.dedupe = Some(DedupeWindow::new(capacity, max_keys));
+        self
     }
 
     pub async fn execute<Cmd>(
         &self,
         key: &str,
         command: &Cmd,
+        metadata: &Metadata,
     ) -> Result<<Vec<T> as UpdateWith<Vec<Cmd::Event>>>::UpdateResponse, Box<dyn Error + Send + Sync>>
     where
-        Cmd: Command,
+        Cmd: Command + Hash,
         Cmd::Event: Into<T>,
-        Cmd::Entity: EventSourced<Event = T>,
+        Cmd::Entity: EventSourced<Event = T> + Send + 'static,
         Vec<T>: NormaliseTo<Cmd::Entity> + UpdateWith<Vec<Cmd::Event>>,
+        <Vec<T> as UpdateWith<Vec<Cmd::Event>>>::UpdateResponse: Clone + Send + Sync + 'static,
     {
         let operation = |input: &Cmd::Entity| command.apply(input);
-        let result = self.transaction.execute(key, &operation).await;
-        result
+        let (Some(dedupe), Some(command_id)) = (&self.dedupe, &metadata.command_id) else {
+            return self.transaction.execute(key, &operation, metadata).await;
+        };
+
+        let reused = || CommandIdReused {
+            command_id: command_id.clone(),
+        };
+        let mut hasher = DefaultHasher::new();
+        command.hash(&mut hasher);
+        let outcome = dedupe
+            .entry(
+                key,
+                metadata.actor_id.as_deref(),
+                command_id,
+                hasher.finish(),
+            )
+            .ok_or_else(reused)?
+            .get_or_try_init(|| async {
+                let response = self.transaction.execute(key, &operation, metadata).await?;
+                Ok::<Outcome, Box<dyn Error + Send + Sync>>(Arc::new(response))
+            })
+            .await?
+            .clone();
+        outcome
+            .downcast_ref()
+            .cloned()
+            .ok_or_else(|| reused().into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::HandleEvent;
+    use crate::store::{LoadEntity, SaveEntity};
+    use crate::transaction::retry::{Instruction, RetryContext};
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    type BoxError = Box<dyn Error + Send + Sync>;
+
+    #[derive(Default)]
+    struct Total(u32);
+
+    impl HandleEvent for Total {
+        type Event = u32;
+
+        fn apply(&mut self, event: &u32) {
+            self.0 += event;
+        }
+    }
+
+    #[derive(Hash)]
+    struct Add(u32);
+
+    impl Command for Add {
+        type Entity = Total;
+        type Event = u32;
+
+        fn apply(&self, _entity: &Total) -> Vec<u32> {
+            vec![self.0]
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct TestStore(Arc<Mutex<Vec<u32>>>);
+
+    #[async_trait]
+    impl LoadEntity<Vec<u32>> for TestStore {
+        type Key = String;
+        type Error = BoxError;
+
+        async fn load(&self, _key: &String) -> Result<Option<Vec<u32>>, BoxError> {
+            Ok(Some(self.0.lock().unwrap().clone()))
+        }
+    }
+
+    #[async_trait]
+    impl SaveEntity<Vec<u32>> for TestStore {
+        type Key = String;
+        type Error = BoxError;
+
+        async fn save(&self, _key: &String, events: Vec<u32>) -> Result<Vec<u32>, BoxError> {
+            *self.0.lock().unwrap() = events.clone();
+            Ok(events)
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_apply_a_repeated_command_once() {
+        let store = TestStore::default();
+        let transaction = Transaction::new(
+            |_context: &RetryContext| Instruction::Abort,
+            store.clone(),
+            store.clone(),
+        );
+        let use_case = UseCase::new(transaction).with_dedupe_window(10, 10);
+        let metadata = Metadata::new().with_command_id("command");
+
+        assert_eq!(
+            use_case.execute("key", &Add(1), &metadata).await.unwrap(),
+            vec![1]
+        );
+        assert_eq!(
+            use_case.execute("key", &Add(1), &metadata).await.unwrap(),
+            vec![1]
+        );
+        assert_eq!(*store.0.lock().unwrap(), vec![1]);
+
+        let other = Metadata::new().with_command_id("other");
+        use_case.execute("key", &Add(2), &other).await.unwrap();
+        assert_eq!(*store.0.lock().unwrap(), vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn it_should_refuse_a_command_id_reused_for_another_command() {
+        let store = TestStore::default();
+        let transaction = Transaction::new(
+            |_context: &RetryContext| Instruction::Abort,
+            store.clone(),
+            store.clone(),
+        );
+        let use_case = UseCase::new(transaction).with_dedupe_window(10, 10);
+        let metadata = Metadata::new().with_command_id("command");
+        use_case.execute("key", &Add(1), &metadata).await.unwrap();
+
+        let error = use_case
+            .execute("key", &Add(2), &metadata)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<CommandIdReused>(),
+            Some(&CommandIdReused {
+                command_id: "command".to_string()
+            })
+        );
+        assert_eq!(*store.0.lock().unwrap(), vec![1]);
     }
 }
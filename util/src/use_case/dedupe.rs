@@ -0,0 +1,168 @@
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+
+pub type Outcome = Arc<dyn Any + Send + Sync>;
+
+/// A command id as issued by one actor, so that clients picking the same id do not
+/// collide.
+type CommandKey = (Option<String>, String);
+
+#[derive(Default)]
+struct Recent {
+    order: VecDeque<CommandKey>,
+    outcomes: HashMap<CommandKey, (u64, Arc<OnceCell<Outcome>>)>,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Keys {
+    recent: HashMap<String, Recent>,
+    uses: u64,
+}
+
+/// The last `capacity` command ids seen for each of the `max_keys` most recently used keys,
+/// each with the fingerprint of its command and a cell holding the response of the first
+/// successful execution.
+pub struct DedupeWindow {
+    capacity: usize,
+    max_keys: usize,
+    keys: Mutex<Keys>,
+}
+
+impl DedupeWindow {
+    pub fn new(capacity: usize, max_keys: usize) -> Self {
+        Self {
+            capacity,
+            max_keys,
+            keys: Mutex::new(Keys::default()),
+        }
+    }
+
+    /// Returns the cell for `command_id` issued by `actor_id`, remembering it along with the
+    /// command's `fingerprint` if it is new, and forgetting the oldest ids of `key` beyond
+    /// the capacity, and the least recently used keys beyond `max_keys`. Returns `None` when
+    /// the id is remembered for a command with another fingerprint.
+    pub fn entry(
+        &self,
+        key: &str,
+        actor_id: Option<&str>,
+        command_id: &str,
+        fingerprint: u64,
+    ) -> Option<Arc<OnceCell<Outcome>>> {
+        // The window is only a cache, so a panic while it was held leaves nothing to repair.
+        let mut keys = self
+            .keys
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        keys.uses += 1;
+        let uses = keys.uses;
+        if !keys.recent.contains_key(key) {
+            Self::evict(&mut keys.recent, self.max_keys.saturating_sub(1));
+        }
+
+        let recent = keys.recent.entry(key.to_string()).or_default();
+        recent.last_used = uses;
+        let command_key = (actor_id.map(str::to_string), command_id.to_string());
+        if let Some((remembered, outcome)) = recent.outcomes.get(&command_key) {
+            return (*remembered == fingerprint).then(|| outcome.clone());
+        }
+
+        let outcome = Arc::new(OnceCell::new());
+        recent.order.push_back(command_key.clone());
+        recent
+            .outcomes
+            .insert(command_key, (fingerprint, outcome.clone()));
+        while recent.order.len() > self.capacity {
+            if let Some(oldest) = recent.order.pop_front() {
+                recent.outcomes.remove(&oldest);
+            }
+        }
+        Some(outcome)
+    }
+
+    fn evict(recent: &mut HashMap<String, Recent>, keep: usize) {
+        while recent.len() > keep {
+            let least_recent = recent
+                .iter()
+                .min_by_key(|(_, recent)| recent.last_used)
+                .map(|(key, _)| key.clone());
+            match least_recent {
+                Some(key) => recent.remove(&key),
+                None => break,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_hand_out_the_same_cell_for_a_repeated_command() {
+        let window = DedupeWindow::new(2, 2);
+        let first = window.entry("board", None, "a", 0).unwrap();
+        assert!(Arc::ptr_eq(
+            &first,
+            &window.entry("board", None, "a", 0).unwrap()
+        ));
+        assert!(!Arc::ptr_eq(
+            &first,
+            &window.entry("other", None, "a", 0).unwrap()
+        ));
+    }
+
+    #[test]
+    fn it_should_keep_the_commands_of_different_actors_apart() {
+        let window = DedupeWindow::new(2, 2);
+        let alice = window.entry("board", Some("alice"), "a", 0).unwrap();
+        assert!(Arc::ptr_eq(
+            &alice,
+            &window.entry("board", Some("alice"), "a", 0).unwrap()
+        ));
+        assert!(!Arc::ptr_eq(
+            &alice,
+            &window.entry("board", Some("bob"), "a", 0).unwrap()
+        ));
+    }
+
+    #[test]
+    fn it_should_forget_the_oldest_commands_beyond_the_capacity() {
+        let window = DedupeWindow::new(2, 2);
+        let first = window.entry("board", None, "a", 0).unwrap();
+        window.entry("board", None, "b", 0).unwrap();
+        window.entry("board", None, "c", 0).unwrap();
+        assert!(!Arc::ptr_eq(
+            &first,
+            &window.entry("board", None, "a", 0).unwrap()
+        ));
+    }
+
+    #[test]
+    fn it_should_forget_the_least_recently_used_keys_beyond_max_keys() {
+        let window = DedupeWindow::new(2, 2);
+        let first = window.entry("first", None, "a", 0).unwrap();
+        let second = window.entry("second", None, "a", 0).unwrap();
+        window.entry("first", None, "b", 0).unwrap();
+        window.entry("third", None, "a", 0).unwrap();
+
+        assert!(Arc::ptr_eq(
+            &first,
+            &window.entry("first", None, "a", 0).unwrap()
+        ));
+        assert!(!Arc::ptr_eq(
+            &second,
+            &window.entry("second", None, "a", 0).unwrap()
+        ));
+    }
+
+    #[test]
+    fn it_should_refuse_an_id_repeated_with_another_command() {
+        let window = DedupeWindow::new(2, 2);
+        window.entry("board", None, "a", 1).unwrap();
+        assert!(window.entry("board", None, "a", 2).is_none());
+        assert!(window.entry("board", None, "a", 1).is_some());
+    }
+}